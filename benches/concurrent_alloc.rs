@@ -0,0 +1,50 @@
+//! Measures the throughput of allocating & protecting memory concurrently.
+//!
+//! On Haiku, run this with & without the default `registry` feature to
+//! contrast the cost of synchronizing on the global allocation registry:
+//!
+//! ```sh
+//! cargo bench --bench concurrent_alloc
+//! cargo bench --bench concurrent_alloc --no-default-features
+//! ```
+use region::Protection;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: usize = 10_000;
+
+fn run(threads: usize) -> Duration {
+  let start = Instant::now();
+  let handles = (0..threads)
+    .map(|_| {
+      std::thread::spawn(|| {
+        for _ in 0..ITERATIONS {
+          let memory = region::alloc(1, Protection::READ).expect("allocating memory");
+          unsafe {
+            region::protect(memory.as_ptr::<u8>(), memory.len(), Protection::READ_WRITE)
+              .expect("protecting memory")
+          };
+        }
+      })
+    })
+    .collect::<Vec<_>>();
+
+  for handle in handles {
+    handle.join().expect("joining thread");
+  }
+  start.elapsed()
+}
+
+fn main() {
+  // Warm up the allocator before taking any measurements
+  run(1);
+
+  for &threads in &[1, 2, 4, 8] {
+    let elapsed = run(threads);
+    let operations = (threads * ITERATIONS) as f64;
+    println!(
+      "{:>2} thread(s): {:>12.0} allocations/s",
+      threads,
+      operations / elapsed.as_secs_f64()
+    );
+  }
+}
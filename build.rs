@@ -0,0 +1,7 @@
+fn main() {
+  // The C interface test compiles against the header for the same target
+  if let Ok(target) = std::env::var("TARGET") {
+    println!("cargo:rustc-env=REGION_TARGET={}", target);
+  }
+  println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,165 @@
+/*
+ * C interface of the `region` crate, available with the `capi` feature.
+ *
+ * Generated from `src/capi.rs` by cbindgen; do not edit.
+ */
+
+#ifndef REGION_H
+#define REGION_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Protection flags, combinable using bitwise OR. */
+#define REGION_PROTECTION_NONE 0u
+#define REGION_PROTECTION_READ (1u << 1)
+#define REGION_PROTECTION_WRITE (1u << 2)
+#define REGION_PROTECTION_EXECUTE (1u << 3)
+
+/**
+ * The outcome of a C interface call.
+ */
+typedef enum RegionError {
+  /**
+   * The call succeeded.
+   */
+  REGION_ERROR_OK = 0,
+  /**
+   * See [`Error::UnmappedRegion`].
+   */
+  REGION_ERROR_UNMAPPED_REGION = 1,
+  /**
+   * See [`Error::InvalidParameter`].
+   */
+  REGION_ERROR_INVALID_PARAMETER = 2,
+  /**
+   * See [`Error::AlreadyMapped`].
+   */
+  REGION_ERROR_ALREADY_MAPPED = 3,
+  /**
+   * See [`Error::LockLimitExceeded`].
+   */
+  REGION_ERROR_LOCK_LIMIT_EXCEEDED = 4,
+  /**
+   * See [`Error::PartialFailure`].
+   */
+  REGION_ERROR_PARTIAL_FAILURE = 5,
+  /**
+   * An interaction with the underlying operating system failed.
+   */
+  REGION_ERROR_OS = 6,
+  /**
+   * See [`Error::UnsupportedOperation`].
+   */
+  REGION_ERROR_UNSUPPORTED_OPERATION = 7,
+} RegionError;
+
+/**
+ * An opaque handle to an owned region of memory.
+ *
+ * This is created by [`region_alloc`], and must be freed by [`region_free`].
+ */
+typedef struct RegionAllocation RegionAllocation;
+
+/**
+ * A description of a mapped region, filled in by [`region_query`].
+ */
+typedef struct RegionInfo {
+  /**
+   * The base address of the region.
+   */
+  const void *base;
+  /**
+   * The size of the region, in bytes.
+   */
+  size_t size;
+  /**
+   * The protection of the region.
+   */
+  uint32_t protection;
+  /**
+   * Whether the region is committed or not.
+   */
+  bool committed;
+  /**
+   * Whether the region is guarded or not.
+   */
+  bool guarded;
+  /**
+   * Whether the region is shared or not.
+   */
+  bool shared;
+} RegionInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Allocates one or more pages of memory, with a defined protection.
+ *
+ * Returns a handle to the allocation, or null on failure. If `error` is not
+ * null, the outcome is written to it.
+ *
+ * # Safety
+ *
+ * `error` must either be null, or be valid for writes.
+ */
+struct RegionAllocation *region_alloc(size_t size, uint32_t protection, enum RegionError *error);
+
+/**
+ * Returns the base address of an allocation.
+ *
+ * # Safety
+ *
+ * `allocation` must be a live handle returned by [`region_alloc`].
+ */
+void *region_allocation_base(const struct RegionAllocation *allocation);
+
+/**
+ * Returns the size, in bytes, of an allocation.
+ *
+ * # Safety
+ *
+ * `allocation` must be a live handle returned by [`region_alloc`].
+ */
+size_t region_allocation_size(const struct RegionAllocation *allocation);
+
+/**
+ * Frees an allocation. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * `allocation` must either be null, or a live handle returned by
+ * [`region_alloc`]. The handle must not be used thereafter.
+ */
+void region_free(struct RegionAllocation *allocation);
+
+/**
+ * Changes the memory protection of one or more pages.
+ *
+ * See [`protect`](crate::protect) for more information.
+ *
+ * # Safety
+ *
+ * See [`protect`](crate::protect).
+ */
+enum RegionError region_protect(const void *address, size_t size, uint32_t protection);
+
+/**
+ * Queries the region an address resides within.
+ *
+ * On success, the region is written to `info`.
+ *
+ * # Safety
+ *
+ * `info` must be valid for writes.
+ */
+enum RegionError region_query(const void *address, struct RegionInfo *info);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* REGION_H */
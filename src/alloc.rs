@@ -1,16 +1,39 @@
-use crate::{os, page, util, Error, Protection, Result};
+use crate::{oom, os, page, util, Error, Protection, Result};
+use std::pin::Pin;
+use std::ptr::NonNull;
 
 /// A handle to an owned region of memory.
 ///
 /// This handle does not dereference to a slice, since the underlying memory may
 /// have been created with [`Protection::NONE`].
+///
+/// The base address of an allocation is stable for its entire lifetime, even
+/// when the handle itself is moved, since the pages are owned by the operating
+/// system rather than the handle. Therefore, pointers into an allocation remain
+/// valid whilst it's alive, which makes it suitable for self-referential and
+/// intrusive data structures (see [`Allocation::as_pinned_mut_slice`]).
 #[allow(clippy::len_without_is_empty)]
 pub struct Allocation {
   base: *const (),
   size: usize,
+  // The size of the guard pages trailing the allocation (see `alloc_guarded`)
+  guard: usize,
 }
 
 impl Allocation {
+  /// Creates an allocation from an existing mapping, which is freed once the
+  /// allocation is dropped.
+  #[inline(always)]
+  pub(crate) unsafe fn from_raw_parts(base: *const (), size: usize) -> Self {
+    #[cfg(feature = "tracking")]
+    crate::owned::register(base, size);
+    Self {
+      base,
+      size,
+      guard: 0,
+    }
+  }
+
   /// Returns a pointer to the allocation's base address.
   ///
   /// The address is always aligned to the operating system's page size.
@@ -25,6 +48,15 @@ impl Allocation {
     self.base as *mut T
   }
 
+  /// Returns a non-null pointer to the allocation's base address.
+  ///
+  /// This is useful for FFI, since an allocation's base is never null.
+  #[inline(always)]
+  pub fn as_non_null<T>(&self) -> NonNull<T> {
+    // SAFETY: the OS never maps an allocation at the null address
+    unsafe { NonNull::new_unchecked(self.base as *mut T) }
+  }
+
   /// Returns two raw pointers spanning the allocation's address space.
   ///
   /// The returned range is half-open, which means that the end pointer points
@@ -44,6 +76,16 @@ impl Allocation {
     (range.start as *mut T)..(range.end as *mut T)
   }
 
+  /// Returns two non-null pointers spanning the allocation's address space.
+  #[inline(always)]
+  pub fn as_non_null_ptr_range<T>(&self) -> std::ops::Range<NonNull<T>> {
+    let range = self.as_range();
+    // SAFETY: neither the base nor the (saturated) end of an allocation is null
+    unsafe {
+      NonNull::new_unchecked(range.start as *mut T)..NonNull::new_unchecked(range.end as *mut T)
+    }
+  }
+
   /// Returns a range spanning the allocation's address space.
   #[inline(always)]
   pub fn as_range(&self) -> std::ops::Range<usize> {
@@ -58,12 +100,426 @@ impl Allocation {
   pub fn len(&self) -> usize {
     self.size
   }
+
+  /// Returns an iterator over the base address of each page in the
+  /// allocation.
+  ///
+  /// This is useful for per-page operations, such as selectively locking or
+  /// protecting individual pages of an allocation.
+  #[inline]
+  pub fn pages(&self) -> impl Iterator<Item = *const ()> {
+    self
+      .as_range()
+      .step_by(page::size())
+      .map(|page| page as *const ())
+  }
+
+  /// Returns a view of the allocation as chunks of `N` bytes, along with the
+  /// remainder that does not fill an entire chunk.
+  ///
+  /// The allocation is queried beforehand, to verify that it's readable.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If `N` is zero, or the allocation is not readable (e.g.
+  ///   [`Protection::NONE`]), [`Error::InvalidParameter`] will be returned.
+  #[inline]
+  pub fn as_chunks<const N: usize>(&self) -> Result<(&[[u8; N]], &[u8])> {
+    if N == 0 {
+      return Err(Error::InvalidParameter("N"));
+    }
+
+    let (data, size) = (self.as_ptr::<u8>(), self.len());
+    util::ensure_readable(data, size)?;
+
+    let chunks = size / N;
+    unsafe {
+      Ok((
+        std::slice::from_raw_parts(data.cast::<[u8; N]>(), chunks),
+        std::slice::from_raw_parts(data.add(chunks * N), size % N),
+      ))
+    }
+  }
+
+  /// Divides the allocation into two mutable slices of `T`, at an element
+  /// index.
+  ///
+  /// The first slice contains the elements `[0, mid)`, and the second one the
+  /// elements `[mid, len)`, where `len` is the number of whole `T` that fit
+  /// within the allocation. This mirrors [`slice::split_at_mut`], so that
+  /// disjoint parts of an allocation can be processed in parallel. The
+  /// allocation is queried beforehand, to verify that it's writable.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If `T` is zero-sized, `mid` exceeds the number of elements, or the
+  ///   allocation is not writable (e.g. [`Protection::READ`]),
+  ///   [`Error::InvalidParameter`] will be returned.
+  ///
+  /// # Safety
+  ///
+  /// The memory must contain valid values of `T`. Since anonymous memory is
+  /// zero-initialized, this holds for any type that is valid when zeroed (e.g.
+  /// integers).
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::Protection;
+  ///
+  /// let mut memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// let (left, right) = unsafe { memory.as_mut_slices::<u32>(1)? };
+  ///
+  /// left[0] = 1;
+  /// right[0] = 2;
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub unsafe fn as_mut_slices<T>(&mut self, mid: usize) -> Result<(&mut [T], &mut [T])> {
+    if std::mem::size_of::<T>() == 0 {
+      return Err(Error::InvalidParameter("T"));
+    }
+
+    let len = self.size / std::mem::size_of::<T>();
+    if mid > len {
+      return Err(Error::InvalidParameter("mid"));
+    }
+
+    util::ensure_writable(self.base, self.size)?;
+    let data = self.as_mut_ptr::<T>();
+    Ok((
+      std::slice::from_raw_parts_mut(data, mid),
+      std::slice::from_raw_parts_mut(data.add(mid), len - mid),
+    ))
+  }
+
+  /// Returns a pinned, mutable slice of `T`, spanning the entire allocation.
+  ///
+  /// The slice contains every whole `T` that fits within the allocation. Since
+  /// the pages of an allocation never move, pinning its contents does not
+  /// require the handle itself to stay in place, only to stay alive. The
+  /// allocation is queried beforehand, to verify that it's writable.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If `T` is zero-sized, or the allocation is not writable (e.g.
+  ///   [`Protection::READ`]), [`Error::InvalidParameter`] will be returned.
+  ///
+  /// # Safety
+  ///
+  /// The memory must contain valid values of `T` (see
+  /// [`Allocation::as_mut_slices`]). Furthermore, since pinned values must be
+  /// dropped before their memory is reused, any `T` that requires dropping must
+  /// be dropped in place before the allocation is freed.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::Protection;
+  /// use std::pin::Pin;
+  ///
+  /// let mut memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// let mut slice = unsafe { Pin::new(&mut memory).as_pinned_mut_slice::<u64>()? };
+  ///
+  /// slice[0] = 1;
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub unsafe fn as_pinned_mut_slice<T>(self: Pin<&mut Self>) -> Result<Pin<&mut [T]>> {
+    let (_, slice) = self.get_mut().as_mut_slices::<T>(0)?;
+    Ok(Pin::new_unchecked(slice))
+  }
+
+  /// Flushes any modifications of a shared, file-backed, allocation to its
+  /// underlying file, and waits for the write to complete.
+  ///
+  /// The range is relative to the allocation's base address, and defaults to
+  /// the entire allocation. Flushing an anonymous allocation has no effect.
+  ///
+  /// # Implementation
+  ///
+  /// This function is implemented using `msync(MS_SYNC)` on POSIX, and
+  /// `FlushViewOfFile` on Windows (which does not wait for the write to
+  /// complete).
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If the range exceeds the allocation, [`Error::InvalidParameter`] will be
+  ///   returned.
+  #[inline]
+  pub fn flush(&self, range: Option<std::ops::Range<usize>>) -> Result<()> {
+    self.flush_range(range, false)
+  }
+
+  /// Initiates flushing any modifications of a shared, file-backed,
+  /// allocation to its underlying file, without waiting for the write to
+  /// complete.
+  ///
+  /// See [`Allocation::flush`] for more information. On POSIX, this function
+  /// is implemented using `msync(MS_ASYNC)`.
+  #[inline]
+  pub fn flush_async(&self, range: Option<std::ops::Range<usize>>) -> Result<()> {
+    self.flush_range(range, true)
+  }
+
+  fn flush_range(&self, range: Option<std::ops::Range<usize>>, asynchronous: bool) -> Result<()> {
+    match self.page_range(range)? {
+      Some((address, size)) => unsafe { os::flush(address, size, asynchronous) },
+      None => Ok(()),
+    }
+  }
+
+  /// Resolves a range relative to the allocation's base address (defaulting to
+  /// the entire allocation) into its page boundaries, or `None` if it's empty.
+  fn page_range(
+    &self,
+    range: Option<std::ops::Range<usize>>,
+  ) -> Result<Option<(*const (), usize)>> {
+    let range = range.unwrap_or(0..self.size);
+    if range.start > range.end || range.end > self.size {
+      return Err(Error::InvalidParameter("range"));
+    }
+
+    if range.start == range.end {
+      return Ok(None);
+    }
+
+    let address = (self.base as usize + range.start) as *const ();
+    util::round_to_page_boundaries(address, range.end - range.start).map(Some)
+  }
+
+  /// Prefetches the pages of an allocation, resolving once they're resident.
+  ///
+  /// This is only available with the `async` feature. The range is relative
+  /// to the allocation's base address, and defaults to the entire allocation.
+  /// The OS is advised to read in the pages (e.g. from a mapped file) on the
+  /// calling thread, whereupon their residency is polled by a worker thread
+  /// shared by all prefetches, with an increasing interval. The future
+  /// resolves once every page is resident, and it does not depend on any
+  /// particular runtime. The allocation is borrowed until the future is
+  /// dropped, at which point the range is no longer polled.
+  ///
+  /// # Implementation
+  ///
+  /// The advice is issued using `madvise(MADV_WILLNEED)` on POSIX, and
+  /// `PrefetchVirtualMemory` on Windows. The residency is polled as by
+  /// [`is_resident`](crate::is_resident).
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If the range exceeds the allocation, [`Error::InvalidParameter`] will be
+  ///   returned.
+  /// - If no further pages are brought in before every page is resident (e.g.
+  ///   for anonymous memory that has never been touched),
+  ///   [`Error::PartialFailure`] will be returned, reporting the number of
+  ///   resident bytes.
+  #[cfg(feature = "async")]
+  #[inline]
+  pub fn prefetch_async(
+    &self,
+    range: Option<std::ops::Range<usize>>,
+  ) -> impl std::future::Future<Output = Result<()>> + '_ {
+    match self.page_range(range) {
+      Ok(Some((address, size))) => crate::prefetch::Prefetch::new(address, size),
+      Ok(None) => crate::prefetch::Prefetch::completed(Ok(())),
+      Err(error) => crate::prefetch::Prefetch::completed(Err(error)),
+    }
+  }
+
+  /// Changes the memory protection of the entire allocation.
+  ///
+  /// This is equivalent to calling [`protect`](crate::protect) with the
+  /// allocation's address range.
+  ///
+  /// # Safety
+  ///
+  /// See [`protect`](crate::protect).
+  #[inline]
+  pub unsafe fn protect(&self, protection: Protection) -> Result<()> {
+    crate::observe::protect(self.base, self.size, protection, || {
+      os::protect(self.base, self.size, protection)
+    })
+  }
+
+  /// Makes the guard page of the allocation inaccessible again, after it has
+  /// been disarmed using [`Allocation::disarm_guard`].
+  ///
+  /// # Errors
+  ///
+  /// - If the allocation was not created by [`alloc_guarded`],
+  ///   [`Error::UnsupportedOperation`] will be returned.
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  ///
+  /// # Safety
+  ///
+  /// See [`protect`](crate::protect). The guard page must no longer be
+  /// accessed once armed.
+  #[inline]
+  pub unsafe fn arm_guard(&self) -> Result<()> {
+    crate::protect(self.guard_ptr()?, self.guard, Protection::NONE)
+  }
+
+  /// Makes the guard page of the allocation accessible, with the protection
+  /// of the allocation's last page.
+  ///
+  /// This allows a buffer to temporarily grow into its guard page, until it's
+  /// re-armed using [`Allocation::arm_guard`]. The guard page remains outside
+  /// of [`Allocation::len`] either way.
+  ///
+  /// # Errors
+  ///
+  /// - If the allocation was not created by [`alloc_guarded`],
+  ///   [`Error::UnsupportedOperation`] will be returned.
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  ///
+  /// # Safety
+  ///
+  /// See [`protect`](crate::protect).
+  #[inline]
+  pub unsafe fn disarm_guard(&self) -> Result<()> {
+    let guard = self.guard_ptr()?;
+    let protection = crate::protection_at(guard.cast::<u8>().sub(page::size()))?;
+    crate::protect(guard, self.guard, protection)
+  }
+
+  fn guard_ptr(&self) -> Result<*const ()> {
+    match self.guard {
+      0 => Err(Error::UnsupportedOperation),
+      _ => Ok(self.as_ptr_range::<u8>().end.cast()),
+    }
+  }
+
+  /// Frees the allocation, reporting whether it succeeded.
+  ///
+  /// Dropping an allocation frees it as well, but any failure is silently
+  /// ignored (in release builds). The allocation is consumed either way, hence
+  /// it's never freed twice.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned. The memory is left in an unspecified state.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::Protection;
+  ///
+  /// let memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// memory.free()?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn free(self) -> Result<()> {
+    // The destructor is suppressed, since it would otherwise free it again
+    let allocation = std::mem::ManuallyDrop::new(self);
+
+    #[cfg(feature = "tracking")]
+    crate::owned::unregister(allocation.base);
+    unsafe { os::free(allocation.base, allocation.size + allocation.guard) }
+  }
+
+  /// Returns the physical address of the allocation's first page.
+  ///
+  /// This is only supported on Haiku, for physically contiguous allocations
+  /// (e.g. those created with `HaikuLock::Contiguous`).
+  ///
+  /// # Errors
+  ///
+  /// - On every other platform, [`Error::UnsupportedOperation`] will be
+  ///   returned.
+  #[inline]
+  pub fn physical_address(&self) -> Result<u64> {
+    Err(Error::UnsupportedOperation)
+  }
+
+  /// Converts the allocation into [`Bytes`](bytes::Bytes), without copying.
+  ///
+  /// This is only available with the `bytes` feature. Ownership of the
+  /// allocation is handed off to the `Bytes`, and its memory is freed once the
+  /// last clone of it is dropped. This enables mapped data to be passed to
+  /// code that operates on `Bytes` (e.g. network I/O) as-is.
+  ///
+  /// Since a `Bytes` is immutable and may be shared across threads, the
+  /// memory must not be written to, nor be made unreadable, through any other
+  /// means (e.g. a pointer retained from [`Allocation::as_mut_ptr`]) for as
+  /// long as the `Bytes` is alive.
+  ///
+  /// # Errors
+  ///
+  /// - If any part of the allocation is not readable,
+  ///   [`Error::InvalidParameter`] will be returned, and the allocation is
+  ///   freed.
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned, and the allocation is freed.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::Protection;
+  ///
+  /// let mut memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// unsafe { memory.as_mut_ptr::<u8>().write(0xFF) };
+  ///
+  /// let bytes = memory.into_bytes()?;
+  /// assert_eq!(bytes[0], 0xFF);
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[cfg(feature = "bytes")]
+  #[inline]
+  pub fn into_bytes(self) -> Result<bytes::Bytes> {
+    util::ensure_readable(self.base, self.size)?;
+    Ok(bytes::Bytes::from_owner(BytesOwner(self)))
+  }
+}
+
+/// The owner of an allocation that has been converted into `Bytes`.
+#[cfg(feature = "bytes")]
+struct BytesOwner(Allocation);
+
+#[cfg(feature = "bytes")]
+impl AsRef<[u8]> for BytesOwner {
+  #[inline]
+  fn as_ref(&self) -> &[u8] {
+    // The memory is guaranteed to be readable by the contract of `into_bytes`
+    unsafe { std::slice::from_raw_parts(self.0.as_ptr(), self.0.len()) }
+  }
 }
 
+// The memory is no longer mutated once it's owned by `Bytes`
+#[cfg(feature = "bytes")]
+unsafe impl Send for BytesOwner {}
+#[cfg(feature = "bytes")]
+unsafe impl Sync for BytesOwner {}
+
 impl Drop for Allocation {
   #[inline]
   fn drop(&mut self) {
-    let result = unsafe { os::free(self.base, self.size) };
+    // The allocation is unregistered first, since its address may be reused
+    // by another thread as soon as it's freed.
+    #[cfg(feature = "tracking")]
+    crate::owned::unregister(self.base);
+    let result = unsafe { os::free(self.base, self.size + self.guard) };
     debug_assert!(result.is_ok(), "freeing region: {:?}", result);
   }
 }
@@ -82,8 +538,9 @@ impl Drop for Allocation {
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
-/// - If size is zero, [`Error::InvalidParameter`] will be returned.
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
 ///
 /// # Examples
 ///
@@ -108,16 +565,7 @@ impl Drop for Allocation {
 /// ```
 #[inline]
 pub fn alloc(size: usize, protection: Protection) -> Result<Allocation> {
-  if size == 0 {
-    return Err(Error::InvalidParameter("size"));
-  }
-
-  let size = page::ceil(size as *const ()) as usize;
-
-  unsafe {
-    let base = os::alloc(std::ptr::null::<()>(), size, protection)?;
-    Ok(Allocation { base, size })
-  }
+  AllocOptions::new().alloc(size, protection)
 }
 
 /// Allocates one or more pages of memory, at a specific address, with a defined
@@ -131,7 +579,11 @@ pub fn alloc(size: usize, protection: Protection) -> Result<Allocation> {
 /// # Implementation
 ///
 /// This function is implemented using `VirtualAlloc` on Windows, and `mmap`
-/// with `MAP_FIXED` on POSIX.
+/// on POSIX. Existing mappings are never replaced (use [`alloc_at_force`] to
+/// opt out), which is enforced atomically using `MAP_FIXED_NOREPLACE` on Linux
+/// and `MAP_FIXED | MAP_EXCL` on FreeBSD. Other POSIX platforms lack such a
+/// flag, hence the range is queried beforehand, which is inherently racy if
+/// other threads are concurrently mapping memory.
 ///
 /// # Parameters
 ///
@@ -143,56 +595,1318 @@ pub fn alloc(size: usize, protection: Protection) -> Result<Allocation> {
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
-/// - If size is zero, [`Error::InvalidParameter`] will be returned.
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+/// - If any part of the range is already mapped, [`Error::AlreadyMapped`] will
+///   be returned on POSIX.
 #[inline]
 pub fn alloc_at<T>(address: *const T, size: usize, protection: Protection) -> Result<Allocation> {
-  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  AllocOptions::new().alloc_at(address, size, protection)
+}
 
-  unsafe {
-    let base = os::alloc(address.cast(), size, protection)?;
-    Ok(Allocation { base, size })
-  }
+/// Allocates one or more pages of memory, at a specific address, with a defined
+/// protection, reporting how far the address was rounded.
+///
+/// This is equivalent to [`alloc_at`], but alongside the allocation, the offset
+/// of the requested address from the allocation's base is returned. This is
+/// useful since the base may differ substantially from the requested address,
+/// e.g. on Windows, where new allocations are aligned to the allocation
+/// granularity (most commonly 64KB), instead of the page size.
+///
+/// # Parameters
+///
+/// - The address is rounded down to the closest page boundary (or the
+///   allocation granularity on Windows).
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+/// - If any part of the range is already mapped, [`Error::AlreadyMapped`] will
+///   be returned on POSIX.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+/// use std::ptr::NonNull;
+///
+/// let base = region::alloc(1, Protection::NONE)?.as_ptr::<u8>();
+/// let address = NonNull::new(unsafe { base.add(10) } as *mut u8).unwrap();
+///
+/// let (memory, offset) = region::alloc_at_with_offset(address, 1, Protection::READ_WRITE)?;
+/// assert_eq!(memory.as_ptr::<u8>(), base);
+/// assert_eq!(offset, 10);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_at_with_offset<T>(
+  address: NonNull<T>,
+  size: usize,
+  protection: Protection,
+) -> Result<(Allocation, usize)> {
+  let allocation = alloc_at(address.as_ptr(), size, protection)?;
+  let offset = (address.as_ptr() as usize).saturating_sub(allocation.base as usize);
+  Ok((allocation, offset))
 }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+/// Allocates one or more pages of memory, within a specific range of addresses,
+/// with a defined protection.
+///
+/// The lowest unmapped gap within the range that can fit the allocation is
+/// searched for, and the allocation is placed at its start. This combines
+/// [`largest_free_hole`](crate::largest_free_hole) with
+/// [`alloc_at`], for deterministic memory layouts. The allocation never
+/// extends beyond the range.
+///
+/// # Implementation
+///
+/// The gaps are found by querying the regions within the range. On Windows,
+/// the start of each gap is rounded up to the allocation granularity (most
+/// commonly 64KB), since allocations cannot be placed elsewhere. If another
+/// thread concurrently maps memory within the chosen gap, it's not replaced
+/// (see [`alloc_at`]), and [`Error::AlreadyMapped`] is returned instead.
+///
+/// # Parameters
+///
+/// - The range is shrunk to its innermost page boundaries.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range does not span a single page,
+///   [`Error::InvalidParameter`] will be returned.
+/// - If no gap within the range can fit the allocation,
+///   [`Error::AlreadyMapped`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let window = region::reserve(1024 * 1024)?.as_ptr_range::<u8>();
+/// let memory = region::alloc_in_range(window.clone(), 4096, Protection::READ_WRITE)?;
+///
+/// assert!(window.start <= memory.as_ptr() && memory.as_ptr_range().end <= window.end);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_in_range<T>(
+  range: std::ops::Range<*const T>,
+  size: usize,
+  protection: Protection,
+) -> Result<Allocation> {
+  let start = page::ceil(range.start) as usize;
+  let end = page::floor(range.end) as usize;
 
-  #[test]
-  fn alloc_size_is_aligned_to_page_size() -> Result<()> {
-    let memory = alloc(1, Protection::NONE)?;
-    assert_eq!(memory.len(), page::size());
-    Ok(())
+  if start >= end {
+    return Err(Error::InvalidParameter("range"));
   }
 
-  #[test]
-  fn alloc_rejects_empty_allocation() {
-    assert!(matches!(
-      alloc(0, Protection::NONE),
-      Err(Error::InvalidParameter(_))
-    ));
+  if size == 0 {
+    return Err(Error::InvalidParameter("size"));
   }
 
-  #[test]
-  fn alloc_obtains_correct_properties() -> Result<()> {
-    let memory = alloc(1, Protection::READ_WRITE)?;
+  let size = page::ceil(size as *const ()) as usize;
+  let granularity = os::allocation_granularity();
 
-    let region = crate::query(memory.as_ptr::<()>())?;
-    assert_eq!(region.protection(), Protection::READ_WRITE);
-    assert!(region.len() >= memory.len());
-    assert!(!region.is_guarded());
-    assert!(!region.is_shared());
-    assert!(region.is_committed());
+  for hole in crate::query::free_holes(start..end)? {
+    let address = match hole.start.checked_add(granularity - 1) {
+      Some(address) => address / granularity * granularity,
+      None => continue,
+    };
 
-    Ok(())
+    if address < hole.end && hole.end - address >= size {
+      return alloc_at(address as *const (), size, protection);
+    }
   }
 
-  #[test]
-  fn alloc_frees_memory_when_dropped() -> Result<()> {
-    let base = alloc(1, Protection::READ_WRITE)?.as_ptr::<()>();
-    let query = crate::query(base);
-    assert!(matches!(query, Err(Error::UnmappedRegion)));
+  Err(Error::AlreadyMapped)
+}
+
+/// Allocates one or more pages of memory, at a specific address, with a defined
+/// protection, replacing any existing mappings.
+///
+/// Unlike [`alloc_at`], the range is not checked for existing mappings. On
+/// POSIX, any mapping within the range is replaced (i.e. `MAP_FIXED`
+/// semantics). On Windows, this function is equivalent to [`alloc_at`], since
+/// existing allocations are never replaced.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+///
+/// # Safety
+///
+/// Any memory within the range that is still referenced (e.g. the stack, the
+/// heap or another [`Allocation`]) is irrevocably replaced.
+#[inline]
+pub unsafe fn alloc_at_force<T>(
+  address: *const T,
+  size: usize,
+  protection: Protection,
+) -> Result<Allocation> {
+  AllocOptions::new().alloc_at_force(address, size, protection)
+}
+
+/// Allocates one or more pages of memory, aligned to a specific boundary, with
+/// a defined protection.
+///
+/// This is useful when an alignment stronger than the operating system's page
+/// size is required, e.g. for DMA buffers or huge page candidates. The
+/// allocation address will otherwise be decided by the operating system.
+///
+/// # Implementation
+///
+/// On POSIX, an oversized region is mapped and subsequently trimmed to the
+/// aligned range. On Windows, the alignment is requested from `VirtualAlloc2`
+/// (Windows 10, version 1803 and later). Older versions, where parts of a
+/// reservation cannot be released, reserve an oversized region to locate an
+/// aligned address instead, which is then allocated anew.
+///
+/// # Parameters
+///
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary.
+/// - The alignment must be a power of two, and no less than the page size.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the alignment is invalid, [`Error::InvalidParameter`]
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let align = 2 * 1024 * 1024;
+/// let memory = region::alloc_aligned(100, align, Protection::READ_WRITE)?;
+/// assert_eq!(memory.as_ptr::<u8>() as usize % align, 0);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_aligned(size: usize, align: usize, protection: Protection) -> Result<Allocation> {
+  AllocOptions::new().alloc_aligned(size, align, protection)
+}
+
+/// Reserves one or more pages of address space, without committing any memory.
+///
+/// The reserved pages are inaccessible (i.e. [`Protection::NONE`]), and are
+/// reported as uncommitted by [`Region::is_committed`](crate::Region::is_committed).
+///
+/// # Implementation
+///
+/// This function is implemented using `VirtualAlloc` with `MEM_RESERVE` on
+/// Windows, and `mmap` with `PROT_NONE` (and `MAP_NORESERVE` where available)
+/// on POSIX.
+///
+/// # Parameters
+///
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let reservation = region::reserve(1024 * 1024)?;
+/// let region = region::query(reservation.as_ptr::<()>())?;
+///
+/// assert!(!region.is_committed());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn reserve(size: usize) -> Result<Allocation> {
+  AllocOptions::new()
+    .no_reserve(true)
+    .alloc(size, Protection::NONE)
+}
+
+/// Reserves one or more pages of address space, aligned to a specific
+/// boundary, without committing any memory.
+///
+/// This is the aligned equivalent of [`reserve`], intended for large arenas
+/// whose base must be aligned far beyond the page size (e.g. a terabyte
+/// aligned to a gigabyte).
+///
+/// # Implementation
+///
+/// The address space is located as by [`alloc_aligned`], and reserved as by
+/// [`reserve`]. On POSIX, an oversized reservation is trimmed to the aligned
+/// range, whilst on Windows, the alignment is requested from `VirtualAlloc2`
+/// (or on older versions, an aligned address is located using an oversized
+/// reservation, which is then reserved anew).
+///
+/// # Parameters
+///
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary.
+/// - The alignment must be a power of two, and no less than the page size.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, the alignment is invalid, or the range exceeds the
+///   address space, [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let align = 64 * 1024 * 1024;
+/// let reservation = region::reserve_aligned(align, align)?;
+///
+/// assert_eq!(reservation.as_ptr::<u8>() as usize % align, 0);
+/// assert!(!region::query(reservation.as_ptr::<()>())?.is_committed());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn reserve_aligned(size: usize, align: usize) -> Result<Allocation> {
+  AllocOptions::new()
+    .no_reserve(true)
+    .alloc_aligned(size, align, Protection::NONE)
+}
+
+/// Allocates one or more pages of memory, with a defined protection, followed
+/// by a guard page.
+///
+/// The guard page is inaccessible, so that overflowing the allocation faults,
+/// rather than corrupting adjacent memory. It can be toggled using
+/// [`Allocation::disarm_guard`] and [`Allocation::arm_guard`], e.g. to
+/// temporarily grow a buffer into it. The guard page is not part of the
+/// allocation's range (i.e. [`Allocation::len`]), but it's freed alongside it.
+///
+/// # Parameters
+///
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range (including the guard page) exceeds the
+///   address space, [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let memory = region::alloc_guarded(100, Protection::READ_WRITE)?;
+/// let guard = memory.as_ptr_range::<u8>().end;
+/// assert_eq!(region::protection_at(guard)?, Protection::NONE);
+///
+/// unsafe {
+///   memory.disarm_guard()?;
+///   guard.cast_mut().write(0xFF);
+///   memory.arm_guard()?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_guarded(size: usize, protection: Protection) -> Result<Allocation> {
+  let (_, size) = util::round_to_page_boundaries(std::ptr::null::<()>(), size)?;
+  let guarded = size
+    .checked_add(page::size())
+    .ok_or(Error::InvalidParameter("size"))?;
+
+  // The allocation is freed, including the guard page, if it cannot be armed
+  let mut allocation = alloc(guarded, protection)?;
+  allocation.size = size;
+  allocation.guard = page::size();
+  unsafe { allocation.arm_guard()? };
+  Ok(allocation)
+}
+
+/// Allocates shared memory that is mapped twice, back-to-back.
+///
+/// The allocation spans twice the (rounded) size, and both of its halves are
+/// views of the same memory, so that writing at offset `i` is also observed
+/// at offset `i + size`. This is intended for ring buffers, which can then
+/// read and write across the wrap-around as a contiguous slice. The memory is
+/// readable and writable.
+///
+/// # Implementation
+///
+/// On Linux, the memory is created using `memfd_create`, and on other POSIX
+/// platforms using `shm_open` (whose name is unlinked immediately). It's then
+/// mapped twice into a reserved range, using `mmap` with `MAP_FIXED`. On
+/// Windows, a file mapping backed by the paging file is mapped twice into a
+/// reserved placeholder, using `MapViewOfFile3` (Windows 10, version 1803 and
+/// later). Older versions use `MapViewOfFileEx` instead; since the address may
+/// then be claimed by another thread in the meantime, this is attempted more
+/// than once.
+///
+/// Haiku is not supported yet, where [`Error::UnsupportedOperation`] is
+/// returned.
+///
+/// # Parameters
+///
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary (or the allocation
+///   granularity on Windows), and it's the size of each half.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or twice the size exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let memory = region::alloc_mirrored(4096)?;
+/// let size = memory.len() / 2;
+/// let base = memory.as_ptr::<u8>().cast_mut();
+///
+/// unsafe {
+///   base.write(0xFF);
+///   assert_eq!(base.add(size).read(), 0xFF);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_mirrored(size: usize) -> Result<Allocation> {
+  if size == 0 {
+    return Err(Error::InvalidParameter("size"));
+  }
+
+  let granularity = os::allocation_granularity();
+  let size = size
+    .checked_add(granularity - 1)
+    .map(|size| size / granularity * granularity)
+    .filter(|size| *size <= isize::MAX as usize / 2)
+    .ok_or(Error::InvalidParameter("size"))?;
+
+  unsafe {
+    let base = os::alloc_mirrored(size)?;
+    Ok(Allocation::from_raw_parts(base, size * 2))
+  }
+}
+
+/// Allocates one or more pages of memory, with a defined protection and
+/// caching mode.
+///
+/// This is intended for e.g. buffers shared with devices, which may require
+/// write-combining or uncached memory. It's equivalent to
+/// [`AllocOptions::cache_mode`] followed by [`AllocOptions::alloc`].
+///
+/// # Implementation
+///
+/// On Windows, the allocation is created using `PAGE_WRITECOMBINE` or
+/// `PAGE_NOCACHE`; write-through memory is not supported. Anonymous memory
+/// cannot be assigned another caching mode on POSIX, hence only
+/// [`CacheMode::Default`] is supported there.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+/// - If the caching mode is not supported by the platform,
+///   [`Error::UnsupportedOperation`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{CacheMode, Protection};
+///
+/// match region::alloc_with_cache(4096, Protection::READ_WRITE, CacheMode::WriteCombine) {
+///   Ok(memory) => assert!(memory.len() >= 4096),
+///   Err(region::Error::UnsupportedOperation) => assert!(!cfg!(windows)),
+///   Err(error) => return Err(error),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_with_cache(
+  size: usize,
+  protection: Protection,
+  cache: CacheMode,
+) -> Result<Allocation> {
+  AllocOptions::new()
+    .cache_mode(cache)
+    .alloc(size, protection)
+}
+
+/// The caching mode of an allocation.
+///
+/// See [`AllocOptions::cache_mode`] for the platforms supporting each mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CacheMode {
+  /// The platform's default caching (i.e. write-back).
+  #[default]
+  Default,
+  /// Writes are combined in a buffer, bypassing the cache.
+  WriteCombine,
+  /// Reads and writes bypass the cache.
+  Uncached,
+  /// Writes update both the cache and memory.
+  WriteThrough,
+}
+
+/// Options which can be used to configure how memory is allocated.
+///
+/// This builder exposes the ability to configure how an [`Allocation`] is
+/// created. The [`alloc`] and [`alloc_at`] functions are aliases for
+/// allocating memory with the default options.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{AllocOptions, Protection};
+///
+/// let memory = AllocOptions::new()
+///   .no_reserve(true)
+///   .alloc(100, Protection::READ_WRITE)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AllocOptions {
+  pub(crate) no_reserve: bool,
+  pub(crate) low_memory: bool,
+  pub(crate) locked: bool,
+  pub(crate) cache_mode: CacheMode,
+  #[cfg(any(target_os = "macos", target_os = "ios"))]
+  pub(crate) jit: bool,
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  pub(crate) thp_aligned: bool,
+  // Overrides the process-wide backing (see `set_anon_backing`) when set
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  pub(crate) backing: Option<crate::AnonBacking>,
+}
+
+impl AllocOptions {
+  /// Creates a blank new set of options, equivalent to those used by
+  /// [`alloc`] and [`alloc_at`].
+  #[inline]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the option for not reserving backing storage for the allocation.
+  ///
+  /// This is useful for huge, sparsely used, allocations, since no swap space
+  /// is reserved up front. As a consequence, touching a page may trigger the
+  /// out-of-memory killer (or a `SIGSEGV`), rather than a clean error at
+  /// allocation time.
+  ///
+  /// # Implementation
+  ///
+  /// This option is implemented using `MAP_NORESERVE` on Linux, and by only
+  /// reserving (`MEM_RESERVE`) the address space on Windows; there the pages
+  /// must be committed before they can be accessed (e.g. using [`alloc_at`]
+  /// within the reservation). It has no effect on other platforms.
+  #[inline]
+  pub fn no_reserve(&mut self, no_reserve: bool) -> &mut Self {
+    self.no_reserve = no_reserve;
+    self
+  }
+
+  /// Sets the option for allocating memory within the lower 2GB of the
+  /// address space.
+  ///
+  /// This is required by e.g. JITs that emit 32-bit absolute addresses (such
+  /// as for trampolines, or the x32 ABI). The entire allocation is guaranteed
+  /// to reside below `0x8000_0000`. If no such memory is available, an error
+  /// is returned. The option only applies to [`AllocOptions::alloc`], where it
+  /// takes precedence over [`AllocOptions::thp_aligned`].
+  ///
+  /// # Implementation
+  ///
+  /// This option is implemented using `MAP_32BIT` on x86-64 Linux. Elsewhere,
+  /// the unmapped ranges of the lower 2GB are scanned, and the first fitting
+  /// range is allocated. 64-bit macOS reserves the lower 4GB of each process
+  /// (`__PAGEZERO`), therefore no low memory is available there.
+  #[inline]
+  pub fn low_memory(&mut self, low_memory: bool) -> &mut Self {
+    self.low_memory = low_memory;
+    self
+  }
+
+  /// Sets the option for locking the allocation into RAM, as part of
+  /// allocating it.
+  ///
+  /// This avoids allocating and locking in two steps, where the allocation
+  /// could be accessed before it's locked (e.g. by another thread). The
+  /// allocation is locked until it's freed. If it cannot be locked, it's freed
+  /// and an error is returned (e.g. [`Error::LockLimitExceeded`]).
+  ///
+  /// # Implementation
+  ///
+  /// This option is implemented using `MAP_LOCKED` on Linux, which also
+  /// populates the allocation. Note that the kernel does not fail the
+  /// allocation if populating it fails, and any pages that could not be
+  /// populated are faulted in once accessed. Elsewhere, the allocation is
+  /// locked (see [`lock`](crate::lock)) before it's returned.
+  #[inline]
+  pub fn locked(&mut self, locked: bool) -> &mut Self {
+    self.locked = locked;
+    self
+  }
+
+  /// Sets the caching mode of the allocation.
+  ///
+  /// If the mode is not supported by the platform, allocating fails with
+  /// [`Error::UnsupportedOperation`].
+  ///
+  /// # Implementation
+  ///
+  /// On Windows, [`CacheMode::WriteCombine`] and [`CacheMode::Uncached`] are
+  /// implemented using `PAGE_WRITECOMBINE` and `PAGE_NOCACHE`, respectively.
+  /// These cannot be combined with [`Protection::NONE`], and they are not
+  /// retained by [`protect`](crate::protect) (use
+  /// [`protect_preserve_flags`](crate::protect_preserve_flags) instead).
+  /// Anonymous memory cannot be assigned another caching mode on POSIX,
+  /// hence only [`CacheMode::Default`] is supported there.
+  #[inline]
+  pub fn cache_mode(&mut self, cache_mode: CacheMode) -> &mut Self {
+    self.cache_mode = cache_mode;
+    self
+  }
+
+  /// Sets the option for aligning the allocation for transparent huge pages.
+  ///
+  /// Transparent huge pages can only back memory that is aligned to the huge
+  /// page size (most commonly 2MB). This improves TLB behavior for large
+  /// allocations, without requiring explicitly configured huge pages.
+  ///
+  /// # Implementation
+  ///
+  /// This option is implemented on Linux by aligning the allocation to the size
+  /// reported by `/sys/kernel/mm/transparent_hugepage/hpage_pmd_size`, and
+  /// applying `madvise(MADV_HUGEPAGE)`. Allocations at a specific address are
+  /// only advised. It has no effect on other platforms.
+  #[inline]
+  pub fn thp_aligned(&mut self, thp_aligned: bool) -> &mut Self {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+      self.thp_aligned = thp_aligned;
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = thp_aligned;
+    self
+  }
+
+  /// Allocates one or more pages of memory, with a defined protection.
+  ///
+  /// See [`alloc`] for more information.
+  #[inline]
+  pub fn alloc(&self, size: usize, protection: Protection) -> Result<Allocation> {
+    #[cfg(not(all(
+      any(target_os = "linux", target_os = "android"),
+      target_arch = "x86_64"
+    )))]
+    if self.low_memory {
+      return self.alloc_low(size, protection);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if self.thp_aligned && !self.low_memory {
+      return self.alloc_aligned(size, os::huge_page_size(), protection);
+    }
+
+    let (_, size) = util::round_to_page_boundaries(std::ptr::null::<()>(), size)?;
+
+    unsafe {
+      let base = oom::retry(|| os::alloc(std::ptr::null::<()>(), size, protection, self))?;
+      self.lock(Allocation::from_raw_parts(base, size))
+    }
+  }
+
+  /// Allocates one or more pages of memory, aligned to a specific boundary,
+  /// with a defined protection.
+  ///
+  /// See [`alloc_aligned`] for more information.
+  #[inline]
+  pub fn alloc_aligned(
+    &self,
+    size: usize,
+    align: usize,
+    protection: Protection,
+  ) -> Result<Allocation> {
+    if !align.is_power_of_two() || align < page::size() {
+      return Err(Error::InvalidParameter("align"));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let align = if self.thp_aligned {
+      align.max(os::huge_page_size())
+    } else {
+      align
+    };
+
+    let (_, size) = util::round_to_page_boundaries(std::ptr::null::<()>(), size)?;
+
+    unsafe {
+      let base = oom::retry(|| os::alloc_aligned(size, align, protection, self))?;
+      let allocation = Allocation::from_raw_parts(base, size);
+      self.advise(&allocation);
+      self.lock(allocation)
+    }
+  }
+
+  /// Allocates one or more pages of memory, at a specific address, with a
+  /// defined protection.
+  ///
+  /// See [`alloc_at`] for more information.
+  #[inline]
+  pub fn alloc_at<T>(
+    &self,
+    address: *const T,
+    size: usize,
+    protection: Protection,
+  ) -> Result<Allocation> {
+    let (address, size) = util::round_to_page_boundaries(address, size)?;
+
+    unsafe {
+      let base = oom::retry(|| os::alloc_exclusive(address.cast(), size, protection, self))?;
+      self.finish(Allocation::from_raw_parts(base, size))
+    }
+  }
+
+  /// Allocates one or more pages of memory, at a specific address, with a
+  /// defined protection, replacing any existing mappings.
+  ///
+  /// See [`alloc_at_force`] for more information.
+  ///
+  /// # Safety
+  ///
+  /// See [`alloc_at_force`].
+  #[inline]
+  pub unsafe fn alloc_at_force<T>(
+    &self,
+    address: *const T,
+    size: usize,
+    protection: Protection,
+  ) -> Result<Allocation> {
+    let (address, size) = util::round_to_page_boundaries(address, size)?;
+
+    let base = oom::retry(|| os::alloc(address.cast(), size, protection, self))?;
+    self.finish(Allocation::from_raw_parts(base, size))
+  }
+
+  /// Applies the options that only take effect once memory is allocated.
+  fn finish(&self, allocation: Allocation) -> Result<Allocation> {
+    self.advise(&allocation);
+    self.lock(allocation)
+  }
+
+  /// Allocates memory within the lower 2GB, by scanning for an unmapped range.
+  #[cfg(not(all(
+    any(target_os = "linux", target_os = "android"),
+    target_arch = "x86_64"
+  )))]
+  fn alloc_low(&self, size: usize, protection: Protection) -> Result<Allocation> {
+    // The lowest addresses are generally prohibited (e.g. by `mmap_min_addr`),
+    // and Windows requires allocations to be aligned to 64KB.
+    const GRANULARITY: usize = 0x10000;
+    const LIMIT: usize = 0x8000_0000;
+
+    let (_, size) = util::round_to_page_boundaries(std::ptr::null::<()>(), size)?;
+    let mut regions = crate::query_range(std::ptr::null::<()>(), LIMIT)?;
+    let mut candidate = GRANULARITY;
+
+    while candidate < LIMIT {
+      let region = regions.next().transpose()?;
+      let gap_end = region
+        .as_ref()
+        .map_or(LIMIT, |region| region.as_range().start.min(LIMIT));
+
+      if gap_end.saturating_sub(candidate) >= size {
+        if let Ok(allocation) = self.alloc_at(candidate as *const (), size, protection) {
+          return Ok(allocation);
+        }
+      }
+
+      match region {
+        Some(region) => {
+          let end = region.as_range().end;
+          candidate = candidate.max(end.saturating_add(GRANULARITY - 1) & !(GRANULARITY - 1));
+        }
+        None => break,
+      }
+    }
+
+    Err(Error::SystemCall(std::io::Error::new(
+      std::io::ErrorKind::OutOfMemory,
+      "no low memory is available",
+    )))
+  }
+
+  /// Applies any advice regarding the usage of an allocation.
+  #[inline(always)]
+  fn advise(&self, allocation: &Allocation) {
+    // The advice is merely a hint, therefore any failure is inconsequential
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if self.thp_aligned {
+      let _ = unsafe { os::advise_huge_pages(allocation.base, allocation.size) };
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = allocation;
+  }
+
+  /// Locks an allocation, if requested, freeing it if locking fails.
+  #[inline(always)]
+  fn lock(&self, allocation: Allocation) -> Result<Allocation> {
+    // Linux locks the allocation whilst mapping it, using `MAP_LOCKED`
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    if self.locked {
+      os::lock(allocation.base, allocation.size)?;
+    }
+    Ok(allocation)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn alloc_size_is_aligned_to_page_size() -> Result<()> {
+    let memory = alloc(1, Protection::NONE)?;
+    assert_eq!(memory.len(), page::size());
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_guarded_toggles_trailing_guard_page() -> Result<()> {
+    let memory = alloc_guarded(1, Protection::READ_WRITE)?;
+    let guard = memory.as_ptr_range::<u8>().end;
+    assert_eq!(memory.len(), page::size());
+    assert_eq!(crate::protection_at(guard)?, Protection::NONE);
+
+    unsafe {
+      memory.arm_guard()?;
+      assert_eq!(crate::protection_at(guard)?, Protection::NONE);
+
+      memory.disarm_guard()?;
+      assert_eq!(crate::protection_at(guard)?, Protection::READ_WRITE);
+      guard.cast_mut().write(0xFF);
+      assert_eq!(guard.read(), 0xFF);
+
+      memory.arm_guard()?;
+    }
+    assert_eq!(crate::protection_at(guard)?, Protection::NONE);
+
+    memory.free()?;
+    assert!(matches!(crate::query(guard), Err(Error::UnmappedRegion)));
+    Ok(())
+  }
+
+  #[test]
+  fn arm_guard_rejects_unguarded_allocation() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    let result = unsafe { memory.arm_guard() };
+    assert!(matches!(result, Err(Error::UnsupportedOperation)));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(feature = "bytes")]
+  fn alloc_into_bytes_frees_memory_after_last_clone() -> Result<()> {
+    let mut memory = alloc(1, Protection::READ_WRITE)?;
+    let base = memory.as_ptr::<()>();
+    unsafe { memory.as_mut_ptr::<u8>().write(0x2A) };
+
+    let bytes = memory.into_bytes()?;
+    let clone = bytes.clone();
+    drop(bytes);
+
+    assert!(crate::query(base).is_ok());
+    assert_eq!(clone.as_ptr(), base.cast());
+    assert_eq!(clone[0], 0x2A);
+
+    drop(clone);
+    assert!(matches!(crate::query(base), Err(Error::UnmappedRegion)));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(feature = "bytes")]
+  fn alloc_into_bytes_rejects_unreadable_memory() -> Result<()> {
+    let memory = alloc(1, Protection::NONE)?;
+    let result = memory.into_bytes();
+    assert!(matches!(result, Err(Error::InvalidParameter("protection"))));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_is_not_file_backed() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    let region = crate::query(memory.as_ptr::<()>())?;
+
+    assert!(!region.is_file_backed());
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    assert_eq!(region.swap_size(), Some(0));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_free_unmaps_memory() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    let base = memory.as_ptr::<()>();
+
+    memory.free()?;
+    assert!(matches!(crate::query(base), Err(Error::UnmappedRegion)));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_rejects_empty_allocation() {
+    assert!(matches!(
+      alloc(0, Protection::NONE),
+      Err(Error::InvalidParameter(_))
+    ));
+  }
+
+  #[test]
+  fn alloc_rejects_overflowing_allocation() {
+    assert!(matches!(
+      alloc(usize::MAX, Protection::NONE),
+      Err(Error::InvalidParameter(_))
+    ));
+    assert!(matches!(
+      alloc_at(
+        page::size() as *const (),
+        usize::MAX - page::size(),
+        Protection::NONE
+      ),
+      Err(Error::InvalidParameter(_))
+    ));
+  }
+
+  #[test]
+  fn alloc_obtains_correct_properties() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+
+    let region = crate::query(memory.as_ptr::<()>())?;
+    assert_eq!(region.protection(), Protection::READ_WRITE);
+    assert!(region.len() >= memory.len());
+    assert!(!region.is_guarded());
+    assert!(!region.is_shared());
+    assert!(region.is_committed());
+
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_pages_yields_each_page() -> Result<()> {
+    let memory = alloc(page::size() * 3, Protection::READ)?;
+    let pages = memory.pages().collect::<Vec<_>>();
+
+    assert_eq!(pages.len(), memory.len() / page::size());
+    for (index, page) in pages.iter().enumerate() {
+      assert_eq!(
+        *page as usize,
+        memory.as_ptr::<u8>() as usize + index * page::size()
+      );
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_provides_non_null_pointers() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    let base = memory.as_non_null::<u8>();
+    let range = memory.as_non_null_ptr_range::<u8>();
+
+    unsafe { base.as_ptr().write(0xFF) };
+    assert_eq!(unsafe { *memory.as_ptr::<u8>() }, 0xFF);
+    assert_eq!(range.start, base);
+    assert_eq!(
+      range.end.as_ptr() as usize - range.start.as_ptr() as usize,
+      memory.len()
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_as_chunks_divides_allocation() -> Result<()> {
+    let memory = alloc(page::size(), Protection::READ)?;
+
+    let (chunks, remainder) = memory.as_chunks::<64>()?;
+    assert_eq!(chunks.len(), memory.len() / 64);
+    assert!(remainder.is_empty());
+    assert!(chunks.iter().all(|chunk| chunk == &[0; 64]));
+
+    let (chunks, remainder) = memory.as_chunks::<3000>()?;
+    assert_eq!(chunks.len(), memory.len() / 3000);
+    assert_eq!(remainder.len(), memory.len() % 3000);
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_as_pinned_mut_slice_is_stable_within_pinned_struct() -> Result<()> {
+    struct Intrusive {
+      memory: Allocation,
+      head: *const u32,
+      _pin: std::marker::PhantomPinned,
+    }
+
+    let mut intrusive = Box::pin(Intrusive {
+      memory: alloc(page::size(), Protection::READ_WRITE)?,
+      head: std::ptr::null(),
+      _pin: std::marker::PhantomPinned,
+    });
+
+    unsafe {
+      let intrusive = intrusive.as_mut().get_unchecked_mut();
+      let mut slice = Pin::new(&mut intrusive.memory).as_pinned_mut_slice::<u32>()?;
+      slice[0] = 0xDEAD;
+      intrusive.head = slice.as_ptr();
+    }
+
+    // The self-reference remains valid once the memory is accessed again
+    let intrusive = intrusive.as_mut();
+    assert_eq!(intrusive.head, intrusive.memory.as_ptr::<u32>());
+    assert_eq!(unsafe { *intrusive.head }, 0xDEAD);
+
+    let mut memory = alloc(page::size(), Protection::READ)?;
+    let result = unsafe { Pin::new(&mut memory).as_pinned_mut_slice::<u32>() };
+    assert!(matches!(result, Err(Error::InvalidParameter("protection"))));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_as_mut_slices_can_be_written_concurrently() -> Result<()> {
+    let mut memory = alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let (left, right) = unsafe { memory.as_mut_slices::<u32>(page::size() / 4)? };
+    assert_eq!(left.len(), right.len());
+
+    std::thread::scope(|scope| {
+      scope.spawn(|| left.iter_mut().for_each(|value| *value = 1));
+      scope.spawn(|| right.iter_mut().for_each(|value| *value = 2));
+    });
+
+    let (left, right) = unsafe { memory.as_mut_slices::<u32>(page::size() / 4)? };
+    assert!(left.iter().all(|&value| value == 1));
+    assert!(right.iter().all(|&value| value == 2));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_as_mut_slices_validates_parameters() -> Result<()> {
+    let mut memory = alloc(1, Protection::READ)?;
+    let elements = memory.len() / 8;
+
+    unsafe {
+      let result = memory.as_mut_slices::<u64>(0);
+      assert!(matches!(result, Err(Error::InvalidParameter("protection"))));
+      let result = memory.as_mut_slices::<u64>(elements + 1);
+      assert!(matches!(result, Err(Error::InvalidParameter("mid"))));
+      let result = memory.as_mut_slices::<()>(0);
+      assert!(matches!(result, Err(Error::InvalidParameter("T"))));
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_as_chunks_requires_readable_memory() -> Result<()> {
+    let memory = alloc(page::size(), Protection::NONE)?;
+    assert!(matches!(
+      memory.as_chunks::<64>(),
+      Err(Error::InvalidParameter("protection"))
+    ));
+    assert!(matches!(
+      alloc(1, Protection::READ)?.as_chunks::<0>(),
+      Err(Error::InvalidParameter("N"))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_protect_changes_protection() -> Result<()> {
+    let memory = alloc(page::size() * 2, Protection::READ)?;
+    unsafe { memory.protect(Protection::READ_WRITE)? };
+
+    let region = crate::query(memory.as_ptr::<()>())?;
+    assert_eq!(region.protection(), Protection::READ_WRITE);
+    assert!(region.len() >= memory.len());
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_frees_memory_when_dropped() -> Result<()> {
+    let base = alloc(1, Protection::READ_WRITE)?.as_ptr::<()>();
+    let query = crate::query(base);
+    assert!(matches!(query, Err(Error::UnmappedRegion)));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    target_pointer_width = "64"
+  ))]
+  fn alloc_without_reserve_can_exceed_available_memory() -> Result<()> {
+    let overcommit = std::fs::read_to_string("/proc/sys/vm/overcommit_memory").unwrap_or_default();
+    if overcommit.trim() == "2" {
+      // Strict accounting ignores `MAP_NORESERVE`
+      return Ok(());
+    }
+
+    let size = 1 << 40;
+    let memory = AllocOptions::new()
+      .no_reserve(true)
+      .alloc(size, Protection::READ_WRITE)?;
+    assert_eq!(memory.len(), size);
+
+    if overcommit.trim() == "0" {
+      // The heuristic rejects mappings that obviously exceed available memory
+      assert!(alloc(size, Protection::READ_WRITE).is_err());
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_aligned_obtains_aligned_base() -> Result<()> {
+    for align in &[page::size(), page::size() * 16, 2 * 1024 * 1024] {
+      let memory = alloc_aligned(page::size() + 1, *align, Protection::READ_WRITE)?;
+      assert_eq!(memory.as_ptr::<u8>() as usize % align, 0);
+      assert_eq!(memory.len(), page::size() * 2);
+
+      let region = crate::query(memory.as_ptr::<()>())?;
+      assert_eq!(region.protection(), Protection::READ_WRITE);
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn alloc_thp_aligned_obtains_huge_page_aligned_base() -> Result<()> {
+    let memory = AllocOptions::new()
+      .thp_aligned(true)
+      .alloc(page::size(), Protection::READ_WRITE)?;
+
+    assert_eq!(memory.as_ptr::<u8>() as usize % (2 * 1024 * 1024), 0);
+    assert_eq!(memory.len(), page::size());
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_locked_is_resident_immediately() -> Result<()> {
+    let memory = AllocOptions::new()
+      .locked(true)
+      .alloc(page::size() * 2, Protection::READ_WRITE)?;
+
+    assert!(crate::is_resident(memory.as_ptr::<u8>(), memory.len())?);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+  fn alloc_low_memory_obtains_32_bit_base() -> Result<()> {
+    let memory = AllocOptions::new()
+      .low_memory(true)
+      .alloc(page::size() * 2, Protection::READ_WRITE)?;
+
+    assert!(memory.as_ptr::<u8>() as usize <= u32::MAX as usize);
+    assert!(memory.as_range().end <= 0x8000_0000);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_pointer_width = "64")]
+  fn reserve_aligned_obtains_gigabyte_aligned_base() -> Result<()> {
+    let align = 1024 * 1024 * 1024;
+    let reservation = reserve_aligned(align * 4, align)?;
+
+    assert_eq!(reservation.as_ptr::<u8>() as usize % align, 0);
+    assert_eq!(reservation.len(), align * 4);
+
+    let region = crate::query(reservation.as_ptr::<()>())?;
+    assert_eq!(region.protection(), Protection::NONE);
+    assert!(!region.is_committed());
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_aligned_rejects_invalid_alignment() {
+    for align in &[0, page::size() / 2, page::size() * 3] {
+      assert!(matches!(
+        alloc_aligned(1, *align, Protection::READ_WRITE),
+        Err(Error::InvalidParameter("align"))
+      ));
+    }
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn alloc_at_rejects_mapped_region() -> Result<()> {
+    let memory = alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let address = unsafe { memory.as_ptr::<u8>().add(page::size()) };
+
+    assert!(matches!(
+      alloc_at(address, page::size(), Protection::NONE),
+      Err(Error::AlreadyMapped)
+    ));
+    assert_eq!(crate::query(address)?.protection(), Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn alloc_at_force_replaces_mapped_region() -> Result<()> {
+    let memory = alloc(page::size(), Protection::READ_WRITE)?;
+    let replacement = unsafe { alloc_at_force(memory.as_ptr::<()>(), 1, Protection::READ)? };
+
+    assert_eq!(replacement.as_ptr::<()>(), memory.as_ptr());
+    assert_eq!(
+      crate::query(memory.as_ptr::<()>())?.protection(),
+      Protection::READ
+    );
+    std::mem::forget(memory);
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_at_with_offset_reports_rounding() -> Result<()> {
+    let base = alloc(page::size() * 2, Protection::NONE)?.as_ptr::<u8>();
+    let requested = unsafe { base.add(page::size() + 123) } as *mut u8;
+    let expected = if cfg!(windows) {
+      requested as usize - base as usize
+    } else {
+      123
+    };
+
+    let (memory, offset) =
+      alloc_at_with_offset(NonNull::new(requested).unwrap(), 1, Protection::READ_WRITE)?;
+    assert_eq!(offset, expected);
+    assert_eq!(memory.as_ptr::<u8>() as usize + offset, requested as usize);
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_in_range_places_allocation_within_free_window() -> Result<()> {
+    let window = reserve(1024 * 1024)?.as_ptr_range::<u8>();
+    let blocker = alloc_at(window.start, 1, Protection::NONE)?;
+
+    let memory = alloc_in_range(window.clone(), page::size() * 2, Protection::READ_WRITE)?;
+    let range = memory.as_ptr_range::<u8>();
+    assert!(range.start >= blocker.as_ptr_range().end && range.end <= window.end);
+    assert_eq!(
+      crate::query(range.start)?.protection(),
+      Protection::READ_WRITE
+    );
+
+    let occupied = blocker.as_ptr_range::<u8>();
+    assert!(matches!(
+      alloc_in_range(occupied, 1, Protection::READ_WRITE),
+      Err(Error::AlreadyMapped)
+    ));
+    assert!(matches!(
+      alloc_in_range(window.start..window.start, 1, Protection::READ_WRITE),
+      Err(Error::InvalidParameter("range"))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(windows)]
+  fn alloc_with_cache_write_combine_succeeds() -> Result<()> {
+    let memory = alloc_with_cache(
+      page::size(),
+      Protection::READ_WRITE,
+      CacheMode::WriteCombine,
+    )?;
+    unsafe { memory.as_ptr::<u8>().cast_mut().write_volatile(0xFF) };
+
+    let mut info: winapi::um::winnt::MEMORY_BASIC_INFORMATION = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of_val(&info);
+    let address = memory.as_ptr::<u8>() as winapi::um::winnt::PVOID;
+    let bytes = unsafe { winapi::um::memoryapi::VirtualQuery(address, &mut info, size) };
+
+    assert_eq!(bytes, size);
+    assert_ne!(info.Protect & winapi::um::winnt::PAGE_WRITECOMBINE, 0);
+    Ok(())
+  }
+
+  #[test]
+  fn physical_address_is_unsupported() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    assert!(matches!(
+      memory.physical_address(),
+      Err(Error::UnsupportedOperation)
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_with_cache_rejects_unsupported_mode() -> Result<()> {
+    let memory = alloc_with_cache(1, Protection::READ_WRITE, CacheMode::Default)?;
+    assert_eq!(memory.len(), page::size());
+
+    assert!(matches!(
+      alloc_with_cache(1, Protection::READ_WRITE, CacheMode::WriteThrough),
+      Err(Error::UnsupportedOperation)
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_mirrored_reflects_writes_in_both_halves() -> Result<()> {
+    let memory = alloc_mirrored(1)?;
+    let size = memory.len() / 2;
+    let base = memory.as_ptr::<u8>().cast_mut();
+
+    assert!(size >= page::size());
+    unsafe {
+      base.write(0xAB);
+      assert_eq!(base.add(size).read(), 0xAB);
+
+      base.add(size + 1).write(0xCD);
+      assert_eq!(base.add(1).read(), 0xCD);
+    }
+
+    assert!(matches!(
+      alloc_mirrored(0),
+      Err(Error::InvalidParameter("size"))
+    ));
     Ok(())
   }
 
@@ -0,0 +1,84 @@
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+static MEMFD: AtomicBool = AtomicBool::new(false);
+
+/// The source of the memory backing anonymous allocations.
+///
+/// See [`set_anon_backing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnonBacking {
+  /// Anonymous mappings (i.e. `MAP_ANONYMOUS`).
+  #[default]
+  MmapAnon,
+  /// Private mappings of an anonymous memory file (i.e. `memfd_create`).
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  Memfd,
+}
+
+/// Sets the source of the memory backing all subsequent allocations.
+///
+/// Some sandboxes (e.g. seccomp policies) forbid `MAP_ANONYMOUS`, whilst they
+/// allow mapping files. With [`AnonBacking::Memfd`], each allocation instead
+/// creates a memory file using `memfd_create`, sizes it using `ftruncate`, and
+/// maps it privately. The file is closed once it's mapped, hence it's released
+/// along with the allocation. Existing allocations are not affected.
+///
+/// The default is [`AnonBacking::MmapAnon`]. The setting is process-wide, and
+/// it applies to the allocations of every thread.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{AnonBacking, Protection};
+///
+/// # #[cfg(target_os = "linux")]
+/// region::set_anon_backing(AnonBacking::Memfd);
+/// let memory = region::alloc(1, Protection::READ_WRITE)?;
+/// region::set_anon_backing(AnonBacking::MmapAnon);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn set_anon_backing(backing: AnonBacking) {
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  MEMFD.store(backing == AnonBacking::Memfd, Ordering::Relaxed);
+
+  // Anonymous mappings are the only backing elsewhere
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  let _ = backing;
+}
+
+/// Returns the source of the memory backing new allocations.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn anon_backing() -> AnonBacking {
+  match MEMFD.load(Ordering::Relaxed) {
+    true => AnonBacking::Memfd,
+    false => AnonBacking::MmapAnon,
+  }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+  use super::*;
+  use crate::{AllocOptions, Protection, Result};
+
+  #[test]
+  fn alloc_with_memfd_backing_maps_memory_file() -> Result<()> {
+    // The process-wide setting is left as is, since tests run concurrently
+    let memory = AllocOptions {
+      backing: Some(AnonBacking::Memfd),
+      ..AllocOptions::default()
+    }
+    .alloc(1, Protection::READ_WRITE)?;
+    unsafe { memory.as_ptr::<u8>().cast_mut().write(0xFF) };
+
+    let region = crate::query(memory.as_ptr::<u8>())?;
+    assert!(region.is_file_backed());
+    assert!(!region.is_shared());
+    assert_eq!(region.protection(), Protection::READ_WRITE);
+    Ok(())
+  }
+}
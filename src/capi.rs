@@ -0,0 +1,242 @@
+//! A C interface, enabled by the `capi` feature.
+//!
+//! The declarations are exported to `include/region.h`, which is generated by
+//! cbindgen (see `cbindgen.toml`) and must be regenerated once they change.
+//! Protection flags are passed using the crate's own representation (see
+//! [`Protection::bits`]).
+//!
+//! To build the crate as a C library, specify the crate type explicitly:
+//!
+//! ```sh
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+
+use crate::{Allocation, Error, Protection};
+use std::os::raw::c_void;
+
+/// The outcome of a C interface call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionError {
+  /// The call succeeded.
+  Ok = 0,
+  /// See [`Error::UnmappedRegion`].
+  UnmappedRegion = 1,
+  /// See [`Error::InvalidParameter`].
+  InvalidParameter = 2,
+  /// See [`Error::AlreadyMapped`].
+  AlreadyMapped = 3,
+  /// See [`Error::LockLimitExceeded`].
+  LockLimitExceeded = 4,
+  /// See [`Error::PartialFailure`].
+  PartialFailure = 5,
+  /// An interaction with the underlying operating system failed.
+  Os = 6,
+  /// See [`Error::UnsupportedOperation`].
+  UnsupportedOperation = 7,
+}
+
+impl From<&Error> for RegionError {
+  #[inline]
+  fn from(error: &Error) -> Self {
+    match error {
+      Error::UnmappedRegion => RegionError::UnmappedRegion,
+      Error::InvalidParameter(_) => RegionError::InvalidParameter,
+      Error::AlreadyMapped => RegionError::AlreadyMapped,
+      Error::LockLimitExceeded => RegionError::LockLimitExceeded,
+      Error::PartialFailure { .. } => RegionError::PartialFailure,
+      Error::UnsupportedOperation => RegionError::UnsupportedOperation,
+      Error::ProcfsInput(_) | Error::SystemCall(_) | Error::MachCall(_) | Error::Os { .. } => {
+        RegionError::Os
+      }
+    }
+  }
+}
+
+/// An opaque handle to an owned region of memory.
+///
+/// This is created by [`region_alloc`], and must be freed by [`region_free`].
+pub struct RegionAllocation(Allocation);
+
+/// A description of a mapped region, filled in by [`region_query`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RegionInfo {
+  /// The base address of the region.
+  pub base: *const c_void,
+  /// The size of the region, in bytes.
+  pub size: usize,
+  /// The protection of the region.
+  pub protection: u32,
+  /// Whether the region is committed or not.
+  pub committed: bool,
+  /// Whether the region is guarded or not.
+  pub guarded: bool,
+  /// Whether the region is shared or not.
+  pub shared: bool,
+}
+
+fn protection(bits: u32) -> Result<Protection, RegionError> {
+  Protection::from_bits(bits as usize).ok_or(RegionError::InvalidParameter)
+}
+
+/// Allocates one or more pages of memory, with a defined protection.
+///
+/// Returns a handle to the allocation, or null on failure. If `error` is not
+/// null, the outcome is written to it.
+///
+/// # Safety
+///
+/// `error` must either be null, or be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn region_alloc(
+  size: usize,
+  protection: u32,
+  error: *mut RegionError,
+) -> *mut RegionAllocation {
+  let result = self::protection(protection).and_then(|protection| {
+    crate::alloc(size, protection).map_err(|error| RegionError::from(&error))
+  });
+
+  let (handle, outcome) = match result {
+    Ok(allocation) => (
+      Box::into_raw(Box::new(RegionAllocation(allocation))),
+      RegionError::Ok,
+    ),
+    Err(outcome) => (std::ptr::null_mut(), outcome),
+  };
+
+  if !error.is_null() {
+    *error = outcome;
+  }
+  handle
+}
+
+/// Returns the base address of an allocation.
+///
+/// # Safety
+///
+/// `allocation` must be a live handle returned by [`region_alloc`].
+#[no_mangle]
+pub unsafe extern "C" fn region_allocation_base(
+  allocation: *const RegionAllocation,
+) -> *mut c_void {
+  (*allocation).0.as_ptr::<c_void>() as *mut c_void
+}
+
+/// Returns the size, in bytes, of an allocation.
+///
+/// # Safety
+///
+/// `allocation` must be a live handle returned by [`region_alloc`].
+#[no_mangle]
+pub unsafe extern "C" fn region_allocation_size(allocation: *const RegionAllocation) -> usize {
+  (*allocation).0.len()
+}
+
+/// Frees an allocation. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `allocation` must either be null, or a live handle returned by
+/// [`region_alloc`]. The handle must not be used thereafter.
+#[no_mangle]
+pub unsafe extern "C" fn region_free(allocation: *mut RegionAllocation) {
+  if !allocation.is_null() {
+    drop(Box::from_raw(allocation));
+  }
+}
+
+/// Changes the memory protection of one or more pages.
+///
+/// See [`protect`](crate::protect) for more information.
+///
+/// # Safety
+///
+/// See [`protect`](crate::protect).
+#[no_mangle]
+pub unsafe extern "C" fn region_protect(
+  address: *const c_void,
+  size: usize,
+  protection: u32,
+) -> RegionError {
+  let result = self::protection(protection).and_then(|protection| {
+    crate::protect(address, size, protection).map_err(|error| RegionError::from(&error))
+  });
+
+  result.err().unwrap_or(RegionError::Ok)
+}
+
+/// Queries the region an address resides within.
+///
+/// On success, the region is written to `info`.
+///
+/// # Safety
+///
+/// `info` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn region_query(
+  address: *const c_void,
+  info: *mut RegionInfo,
+) -> RegionError {
+  match crate::query(address) {
+    Ok(region) => {
+      *info = RegionInfo {
+        base: region.as_ptr(),
+        size: region.len(),
+        protection: region.protection().bits() as u32,
+        committed: region.is_committed(),
+        guarded: region.is_guarded(),
+        shared: region.is_shared(),
+      };
+      RegionError::Ok
+    }
+    Err(error) => RegionError::from(&error),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn capi_round_trips_allocation() {
+    let rw = Protection::READ_WRITE.bits() as u32;
+    let mut error = RegionError::Os;
+
+    unsafe {
+      let allocation = region_alloc(1, rw, &mut error);
+      assert_eq!(error, RegionError::Ok);
+      assert!(!allocation.is_null());
+
+      let base = region_allocation_base(allocation);
+      let size = region_allocation_size(allocation);
+      assert_eq!(size, crate::page::size());
+
+      let mut info = std::mem::zeroed::<RegionInfo>();
+      assert_eq!(region_query(base, &mut info), RegionError::Ok);
+      assert_eq!(info.protection, rw);
+      assert!(info.committed);
+
+      let read = Protection::READ.bits() as u32;
+      assert_eq!(region_protect(base, size, read), RegionError::Ok);
+      assert_eq!(region_query(base, &mut info), RegionError::Ok);
+      assert_eq!(info.protection, read);
+
+      region_free(allocation);
+      assert_eq!(region_query(base, &mut info), RegionError::UnmappedRegion);
+    }
+  }
+
+  #[test]
+  fn capi_reports_invalid_parameters() {
+    let mut error = RegionError::Ok;
+
+    unsafe {
+      assert!(region_alloc(0, 0, &mut error).is_null());
+      assert_eq!(error, RegionError::InvalidParameter);
+      assert!(region_alloc(1, 1, std::ptr::null_mut()).is_null());
+      region_free(std::ptr::null_mut());
+    }
+  }
+}
@@ -0,0 +1,202 @@
+use crate::Region;
+use std::cmp::Ordering;
+
+/// The regions at the front of two sets, during a [`merge`].
+pub(crate) enum Front<'a> {
+  Both(&'a Region, &'a Region),
+  Before(&'a Region),
+  After(&'a Region),
+}
+
+/// The sets to advance past their front region, during a [`merge`].
+pub(crate) enum Advance {
+  Before,
+  After,
+  Both,
+}
+
+/// Walks two sets of regions, sorted by their base address, in a single pass.
+///
+/// The step is invoked with the front regions until both sets are exhausted,
+/// and it decides which of the sets to advance. It must always advance a set
+/// that is not yet exhausted.
+pub(crate) fn merge<'a>(
+  before: &'a [Region],
+  after: &'a [Region],
+  mut step: impl FnMut(Front<'a>) -> Advance,
+) {
+  let (mut old, mut new) = (0, 0);
+
+  loop {
+    let front = match (before.get(old), after.get(new)) {
+      (Some(previous), Some(current)) => Front::Both(previous, current),
+      (Some(previous), None) => Front::Before(previous),
+      (None, Some(current)) => Front::After(current),
+      (None, None) => break,
+    };
+
+    match step(front) {
+      Advance::Before => old += 1,
+      Advance::After => new += 1,
+      Advance::Both => {
+        old += 1;
+        new += 1;
+      }
+    }
+  }
+}
+
+/// The difference between two sets of regions.
+///
+/// This `struct` is created by [`regions_difference`]. See its documentation
+/// for more.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegionDiff {
+  /// The regions whose base address only exists in the latter set.
+  pub added: Vec<Region>,
+  /// The regions whose base address only exists in the former set.
+  pub removed: Vec<Region>,
+  /// The regions that exist at the same base address in both sets, but with
+  /// other attributes (e.g. size or protection), as `(before, after)` pairs.
+  pub modified: Vec<(Region, Region)>,
+}
+
+impl RegionDiff {
+  /// Returns whether the sets of regions are equal.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+  }
+}
+
+/// Computes the difference between two sets of regions, e.g. two snapshots
+/// of a process' regions.
+///
+/// Regions are matched by their base address. A region that only exists in
+/// `after` is added, whilst one that only exists in `before` is removed. A
+/// region that exists in both, but differs in any other attribute (e.g. its
+/// size or protection), is modified. The sets are merged in a single pass,
+/// and this does not query the OS; it complements the watcher of protection
+/// changes (on Linux) for when the snapshots are taken manually.
+///
+/// # Parameters
+///
+/// - Both sets must be sorted by their base address, as yielded by
+///   [`query_process`](crate::query_process).
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let before = region::query_process()?.collect::<region::Result<Vec<_>>>()?;
+/// let memory = region::alloc(1, Protection::READ_WRITE)?;
+/// let after = region::query_process()?.collect::<region::Result<Vec<_>>>()?;
+///
+/// let diff = region::regions_difference(&before, &after);
+/// assert!(!diff.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn regions_difference(before: &[Region], after: &[Region]) -> RegionDiff {
+  let mut diff = RegionDiff::default();
+
+  merge(before, after, |front| match front {
+    Front::Both(previous, current) => match previous.base.cmp(&current.base) {
+      Ordering::Less => {
+        diff.removed.push(*previous);
+        Advance::Before
+      }
+      Ordering::Greater => {
+        diff.added.push(*current);
+        Advance::After
+      }
+      Ordering::Equal => {
+        if previous != current {
+          diff.modified.push((*previous, *current));
+        }
+        Advance::Both
+      }
+    },
+    Front::Before(previous) => {
+      diff.removed.push(*previous);
+      Advance::Before
+    }
+    Front::After(current) => {
+      diff.added.push(*current);
+      Advance::After
+    }
+  });
+
+  diff
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Protection;
+
+  fn region(base: usize, size: usize, protection: Protection) -> Region {
+    Region {
+      base: base as *const (),
+      size,
+      protection,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn regions_difference_reports_added_regions() {
+    let before = [region(0x2000, 0x1000, Protection::READ)];
+    let after = [
+      region(0x1000, 0x1000, Protection::READ),
+      region(0x2000, 0x1000, Protection::READ),
+      region(0x4000, 0x1000, Protection::READ_WRITE),
+    ];
+
+    let diff = regions_difference(&before, &after);
+    assert_eq!(diff.added, [after[0], after[2]]);
+    assert!(diff.removed.is_empty());
+    assert!(diff.modified.is_empty());
+  }
+
+  #[test]
+  fn regions_difference_reports_removed_regions() {
+    let before = [
+      region(0x1000, 0x1000, Protection::READ),
+      region(0x2000, 0x1000, Protection::READ),
+      region(0x4000, 0x1000, Protection::READ_WRITE),
+    ];
+    let after = [region(0x2000, 0x1000, Protection::READ)];
+
+    let diff = regions_difference(&before, &after);
+    assert!(diff.added.is_empty());
+    assert_eq!(diff.removed, [before[0], before[2]]);
+    assert!(diff.modified.is_empty());
+  }
+
+  #[test]
+  fn regions_difference_reports_modified_regions() {
+    let before = [
+      region(0x1000, 0x1000, Protection::READ),
+      region(0x2000, 0x1000, Protection::READ),
+      region(0x3000, 0x1000, Protection::READ),
+    ];
+    let after = [
+      region(0x1000, 0x1000, Protection::READ_WRITE),
+      region(0x2000, 0x1000, Protection::READ),
+      region(0x3000, 0x2000, Protection::READ),
+    ];
+
+    let diff = regions_difference(&before, &after);
+    assert!(diff.added.is_empty());
+    assert!(diff.removed.is_empty());
+    assert_eq!(
+      diff.modified,
+      [(before[0], after[0]), (before[2], after[2])]
+    );
+    assert!(regions_difference(&before, &before).is_empty());
+  }
+}
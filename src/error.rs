@@ -7,7 +7,11 @@ use std::{fmt, io};
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// A collection of possible errors.
+///
+/// Further variants may be added in the future, hence the enumeration is
+/// non-exhaustive.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
   /// The queried memory is unmapped.
   ///
@@ -17,12 +21,62 @@ pub enum Error {
   UnmappedRegion,
   /// A supplied parameter is invalid.
   InvalidParameter(&'static str),
+  /// The requested memory is already mapped.
+  ///
+  /// This is returned instead of replacing an existing mapping.
+  AlreadyMapped,
+  /// Locking memory would exceed the process' locked-memory limit.
+  ///
+  /// See [`memlock_limit`](crate::memlock_limit) for retrieving the limit.
+  LockLimitExceeded,
+  /// The operation is not supported on this platform.
+  UnsupportedOperation,
   /// A procfs region could not be parsed.
   ProcfsInput(String),
   /// A system call failed.
   SystemCall(io::Error),
   /// A macOS kernel call failed
   MachCall(libc::c_int),
+  /// An operation failed after it had been partially applied.
+  ///
+  /// Some OSs apply e.g. a protection change to a range region by region, and
+  /// only fail once an invalid region is encountered. This allows callers to
+  /// recover or roll back the affected range.
+  PartialFailure {
+    /// The number of bytes whose protection was changed, all of which reside
+    /// within the leading part of the range.
+    succeeded: usize,
+    /// The error that caused the operation to fail.
+    error: Box<Error>,
+  },
+  /// An OS call failed with a platform-specific error code.
+  ///
+  /// This is used when the code cannot be represented by an [`io::Error`]
+  /// without loss of information (e.g. a Haiku `status_t`).
+  Os {
+    /// The raw error code returned by the OS.
+    code: i32,
+    /// A description of the error.
+    message: String,
+  },
+}
+
+impl Error {
+  /// Returns the raw OS error code, if the error originates from the OS.
+  ///
+  /// This allows callers to match on platform-specific codes, such as `errno`
+  /// values on POSIX, `GetLastError` values on Windows, `kern_return_t` values
+  /// on macOS and `status_t` values on Haiku.
+  #[inline]
+  pub fn raw_os_error(&self) -> Option<i32> {
+    match self {
+      Error::SystemCall(error) => error.raw_os_error(),
+      Error::MachCall(code) => Some(*code),
+      Error::Os { code, .. } => Some(*code),
+      Error::PartialFailure { error, .. } => error.raw_os_error(),
+      _ => None,
+    }
+  }
 }
 
 impl fmt::Display for Error {
@@ -31,11 +85,47 @@ impl fmt::Display for Error {
     match self {
       Error::UnmappedRegion => write!(f, "Queried memory is unmapped"),
       Error::InvalidParameter(param) => write!(f, "Invalid parameter value: {}", param),
+      Error::AlreadyMapped => write!(f, "Requested memory is already mapped"),
+      Error::LockLimitExceeded => write!(f, "Locked memory limit exceeded"),
+      Error::UnsupportedOperation => write!(f, "Operation is not supported on this platform"),
       Error::ProcfsInput(ref input) => write!(f, "Invalid procfs input: {}", input),
       Error::SystemCall(ref error) => write!(f, "System call failed: {}", error),
       Error::MachCall(code) => write!(f, "macOS kernel call failed: {}", code),
+      Error::PartialFailure { succeeded, error } => {
+        write!(f, "Partially applied to {} bytes: {}", succeeded, error)
+      }
+      Error::Os { code, message } => write!(f, "OS call failed: {} ({})", message, code),
     }
   }
 }
 
 impl StdError for Error {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Protection;
+
+  #[test]
+  #[cfg(not(target_os = "haiku"))]
+  fn system_call_error_exposes_raw_os_error() -> crate::Result<()> {
+    let base = crate::alloc(1, Protection::READ)?.as_ptr::<()>();
+    let error = unsafe { crate::protect(base, 1, Protection::READ_WRITE) }.unwrap_err();
+
+    assert!(error.raw_os_error().is_some());
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    assert_eq!(error.raw_os_error(), Some(libc::ENOMEM));
+    Ok(())
+  }
+
+  #[test]
+  fn os_error_exposes_raw_code() {
+    let error = Error::Os {
+      code: -1,
+      message: "General Error".to_string(),
+    };
+
+    assert_eq!(error.raw_os_error(), Some(-1));
+    assert!(Error::UnmappedRegion.raw_os_error().is_none());
+  }
+}
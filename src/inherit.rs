@@ -0,0 +1,119 @@
+use crate::{os, util, Result};
+
+/// Sets whether one or more pages are inherited by child processes.
+///
+/// By default, a forked child process receives a copy of all of its parent's
+/// memory. Excluding a region (e.g. one containing secrets, or a large cache)
+/// leaves the range unmapped in any subsequently forked child.
+///
+/// # Implementation
+///
+/// This function is implemented using `madvise` with `MADV_DONTFORK` or
+/// `MADV_DOFORK` on Linux, and `minherit` with `INHERIT_NONE` or
+/// `INHERIT_COPY` on macOS, FreeBSD and OpenBSD.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If the platform cannot control inheritance (e.g. Windows, which has no
+///   notion of forking), [`Error::UnsupportedOperation`](crate::Error::UnsupportedOperation)
+///   will be returned.
+///
+/// # Safety
+///
+/// Any memory within the range that is excluded is unmapped in a child process.
+/// Referencing it there (e.g. the heap, or the stack of the forking thread)
+/// terminates the child.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// # if cfg!(any(target_os = "linux", target_os = "macos")) {
+/// use region::Protection;
+///
+/// let secret = region::alloc(1, Protection::READ_WRITE)?;
+/// unsafe { region::set_inherit(secret.as_ptr::<u8>(), secret.len(), false)? };
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub unsafe fn set_inherit<T>(address: *const T, size: usize, inherit: bool) -> Result<()> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  os::set_inherit(address.cast(), size, inherit)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{page, Error, Protection};
+
+  #[test]
+  #[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+  ))]
+  fn set_inherit_excludes_region_from_child() -> Result<()> {
+    let excluded = crate::alloc(page::size(), Protection::READ_WRITE)?;
+    let inherited = crate::alloc(page::size(), Protection::READ_WRITE)?;
+    unsafe {
+      set_inherit(excluded.as_ptr::<u8>(), excluded.len(), false)?;
+      set_inherit(inherited.as_ptr::<u8>(), inherited.len(), true)?;
+    }
+
+    // The child may only use async-signal-safe calls, therefore the mappings
+    // are probed using `msync`, which fails with `ENOMEM` for unmapped pages.
+    let is_mapped = |memory: &crate::Allocation| unsafe {
+      libc::msync(
+        memory.as_ptr::<u8>() as *mut _,
+        memory.len(),
+        libc::MS_ASYNC,
+      ) == 0
+    };
+
+    match unsafe { libc::fork() } {
+      -1 => panic!("forking process: {}", std::io::Error::last_os_error()),
+      0 => unsafe {
+        let success = !is_mapped(&excluded) && is_mapped(&inherited);
+        libc::_exit(if success { 0 } else { 1 })
+      },
+      child => {
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+        assert!(is_mapped(&excluded));
+      }
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn set_inherit_validates_range() -> Result<()> {
+    let memory = crate::alloc(page::size(), Protection::READ_WRITE)?;
+    let result = unsafe { set_inherit(memory.as_ptr::<u8>(), 0, false) };
+    assert!(matches!(result, Err(Error::InvalidParameter(_))));
+
+    #[cfg(windows)]
+    assert!(matches!(
+      unsafe { set_inherit(memory.as_ptr::<u8>(), memory.len(), false) },
+      Err(Error::UnsupportedOperation)
+    ));
+    Ok(())
+  }
+}
@@ -0,0 +1,418 @@
+use crate::{os, Allocation, Protection, Result};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+/// Allocates executable memory, initialized with a copy of the supplied code.
+///
+/// This encapsulates the steps required for preparing machine code to be
+/// executed, whilst respecting W^X: the memory is allocated as writable, the
+/// code is copied into it, the instruction cache is flushed, and finally the
+/// memory is made executable (and no longer writable).
+///
+/// # Implementation
+///
+/// On Apple Silicon, the memory is allocated using `MAP_JIT`, and written to
+/// using `pthread_jit_write_protect_np`. Elsewhere, the memory is allocated as
+/// [`Protection::READ_WRITE`], and subsequently altered to
+/// [`Protection::READ_EXECUTE`].
+///
+/// The `pthread_jit_write_protect_np` toggle only affects the calling thread,
+/// which is why the code is written (and the toggle restored) before this
+/// function returns; the returned memory can then be executed on any thread.
+/// See [`jit_write_protect_supported`].
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the code is empty,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// # if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+/// let ret5 = [0xB8, 0x05, 0x00, 0x00, 0x00, 0xC3u8];
+///
+/// let code = region::alloc_code(&ret5)?;
+/// let x: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code.as_ptr::<u8>()) };
+///
+/// assert_eq!(x(), 5);
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_code(code: &[u8]) -> Result<Allocation> {
+  let mut memory = WritableCode::new(code.len())?;
+  memory.copy_from_slice(code);
+  memory.finalize().map(ExecutableCode::into_allocation)
+}
+
+/// Writable memory for code, which is executable once finalized.
+///
+/// This enforces W^X using the type system: the code can only be written
+/// whilst it's a `WritableCode`, and it can only be executed once it has been
+/// converted into an [`ExecutableCode`] by [`WritableCode::finalize`]. The
+/// memory dereferences to a slice, spanning the requested size.
+///
+/// # Implementation
+///
+/// On Apple Silicon, the memory is allocated using `MAP_JIT`, and the calling
+/// thread's JIT write protection is disabled until the code is finalized (or
+/// dropped). This is why a `WritableCode` cannot be sent to another thread.
+/// Elsewhere, the memory is allocated as [`Protection::READ_WRITE`].
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// # if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+/// use region::WritableCode;
+///
+/// let mut code = WritableCode::new(6)?;
+/// code.copy_from_slice(&[0xB8, 0x05, 0x00, 0x00, 0x00, 0xC3]);
+///
+/// let code = code.finalize()?;
+/// let x: extern "C" fn() -> i32 = unsafe { code.as_fn() };
+/// assert_eq!(x(), 5);
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Writable code cannot be executed:
+///
+/// ```compile_fail
+/// # fn main() -> region::Result<()> {
+/// let code = region::WritableCode::new(6)?;
+/// let x: extern "C" fn() -> i32 = unsafe { code.as_fn() };
+/// # Ok(())
+/// # }
+/// ```
+pub struct WritableCode {
+  memory: Option<Allocation>,
+  size: usize,
+}
+
+impl WritableCode {
+  /// Allocates writable memory for code of a specific size.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If size is zero,
+  ///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+  ///   returned.
+  #[inline]
+  pub fn new(size: usize) -> Result<Self> {
+    #[cfg(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64"))]
+    let memory = {
+      let options = crate::AllocOptions {
+        jit: true,
+        ..Default::default()
+      };
+      let memory = options.alloc(size, Protection::READ_WRITE_EXECUTE)?;
+      jit_writes::enable();
+      memory
+    };
+
+    #[cfg(not(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64")))]
+    let memory = crate::alloc(size, Protection::READ_WRITE)?;
+
+    Ok(Self {
+      memory: Some(memory),
+      size,
+    })
+  }
+
+  /// Makes the code executable (and no longer writable).
+  ///
+  /// The instruction cache is flushed, so that the written code is observed
+  /// once it's executed.
+  ///
+  /// # Implementation
+  ///
+  /// On Apple Silicon, the calling thread's JIT write protection is restored.
+  /// Elsewhere, the memory is altered to [`Protection::READ_EXECUTE`].
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  #[inline]
+  pub fn finalize(mut self) -> Result<ExecutableCode> {
+    let memory = self.memory.take().expect("finalizing code once");
+
+    #[cfg(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64"))]
+    jit_writes::disable();
+
+    #[cfg(not(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64")))]
+    unsafe {
+      crate::protect(
+        memory.as_ptr::<u8>(),
+        memory.len(),
+        Protection::READ_EXECUTE,
+      )?;
+    }
+
+    os::flush_icache(memory.as_ptr(), self.size);
+    Ok(ExecutableCode {
+      memory,
+      size: self.size,
+    })
+  }
+
+  fn memory(&self) -> &Allocation {
+    self.memory.as_ref().expect("accessing finalized code")
+  }
+}
+
+impl Deref for WritableCode {
+  type Target = [u8];
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    unsafe { std::slice::from_raw_parts(self.memory().as_ptr(), self.size) }
+  }
+}
+
+impl DerefMut for WritableCode {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    let memory = self.memory().as_ptr::<u8>() as *mut u8;
+    unsafe { std::slice::from_raw_parts_mut(memory, self.size) }
+  }
+}
+
+impl Drop for WritableCode {
+  #[inline]
+  fn drop(&mut self) {
+    #[cfg(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64"))]
+    if self.memory.is_some() {
+      jit_writes::disable();
+    }
+  }
+}
+
+/// Executable code, created by [`WritableCode::finalize`].
+///
+/// The memory is no longer writable, and it dereferences to a slice, spanning
+/// the size of the code.
+pub struct ExecutableCode {
+  memory: Allocation,
+  size: usize,
+}
+
+impl ExecutableCode {
+  /// Returns the entry point of the code as a function pointer.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `F` is not the size of a pointer.
+  ///
+  /// # Safety
+  ///
+  /// `F` must be a function pointer type (e.g. `extern "C" fn() -> i32`),
+  /// whose signature and calling convention match the code. The function
+  /// pointer must not be called once the code has been dropped.
+  #[inline]
+  pub unsafe fn as_fn<F: Copy>(&self) -> F {
+    assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*const u8>());
+    std::mem::transmute_copy(&self.memory.as_ptr::<u8>())
+  }
+
+  /// Returns the underlying allocation, whose size is rounded up to the
+  /// closest page boundary.
+  #[inline(always)]
+  pub fn as_allocation(&self) -> &Allocation {
+    &self.memory
+  }
+
+  /// Converts the code into its underlying allocation.
+  #[inline(always)]
+  pub fn into_allocation(self) -> Allocation {
+    self.memory
+  }
+}
+
+impl Deref for ExecutableCode {
+  type Target = [u8];
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    unsafe { std::slice::from_raw_parts(self.memory.as_ptr(), self.size) }
+  }
+}
+
+unsafe impl Send for ExecutableCode {}
+unsafe impl Sync for ExecutableCode {}
+
+/// Tracks the `WritableCode` of each thread, on Apple Silicon, so that the
+/// JIT write protection is only restored once all of them are finalized.
+#[cfg(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64"))]
+mod jit_writes {
+  use std::cell::Cell;
+
+  thread_local! {
+    static WRITERS: Cell<usize> = const { Cell::new(0) };
+  }
+
+  pub fn enable() {
+    WRITERS.with(|writers| {
+      if writers.replace(writers.get() + 1) == 0 {
+        unsafe { libc::pthread_jit_write_protect_np(0) };
+      }
+    });
+  }
+
+  pub fn disable() {
+    WRITERS.with(|writers| {
+      if writers.replace(writers.get() - 1) == 1 {
+        unsafe { libc::pthread_jit_write_protect_np(1) };
+      }
+    });
+  }
+}
+
+/// Returns whether the current thread can toggle JIT write protection.
+///
+/// On Apple Silicon, `MAP_JIT` memory is either writable or executable for a
+/// thread, as decided by `pthread_jit_write_protect_np`. The toggle is
+/// thread-bound: writing code on one thread does not make it writable (nor
+/// executable) on another. Code must therefore be written and sealed on the
+/// same thread, which [`alloc_code`] takes care of.
+///
+/// On all other platforms, `false` is returned, since W^X is respected by
+/// altering the protection of the memory instead.
+///
+/// # Examples
+///
+/// ```
+/// let supported = region::jit_write_protect_supported();
+/// assert!(!supported || cfg!(target_vendor = "apple"));
+/// ```
+#[inline]
+pub fn jit_write_protect_supported() -> bool {
+  #[cfg(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64"))]
+  return unsafe { libc::pthread_jit_write_protect_supported_np() != 0 };
+
+  #[cfg(not(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64")))]
+  false
+}
+
+/// Returns whether executable memory can be allocated.
+///
+/// Some platforms prohibit executable memory at runtime (e.g. iOS, or
+/// hardened Android configurations), in which case a JIT cannot be used. This
+/// allows callers to choose a fallback (e.g. an interpreter) beforehand.
+///
+/// # Implementation
+///
+/// A single page of [`Protection::READ_EXECUTE`] memory is allocated, and
+/// immediately freed. The outcome of this probe is cached, so it is only
+/// performed once per process.
+///
+/// # Examples
+///
+/// ```
+/// if region::can_allocate_executable() {
+///   // Compile code with a JIT
+/// } else {
+///   // Fall back to an interpreter
+/// }
+/// ```
+#[inline]
+pub fn can_allocate_executable() -> bool {
+  static PROBE: Once = Once::new();
+  static SUPPORTED: AtomicBool = AtomicBool::new(false);
+
+  PROBE.call_once(|| {
+    let supported = crate::alloc(1, Protection::READ_EXECUTE).is_ok();
+    SUPPORTED.store(supported, Ordering::Relaxed);
+  });
+  SUPPORTED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Error;
+
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+  const RET5: &[u8] = &[0xB8, 0x05, 0x00, 0x00, 0x00, 0xC3];
+
+  #[cfg(target_arch = "aarch64")]
+  const RET5: &[u8] = &[0xA0, 0x00, 0x80, 0x52, 0xC0, 0x03, 0x5F, 0xD6];
+
+  #[test]
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+  fn alloc_code_is_executable() -> Result<()> {
+    let code = alloc_code(RET5)?;
+    let x: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code.as_ptr::<u8>()) };
+
+    assert_eq!(x(), 5);
+    assert!(!crate::query(code.as_ptr::<()>())?.is_writable());
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(
+    target_os = "macos",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+  ))]
+  fn alloc_code_writes_and_executes_on_same_thread() {
+    let result = std::thread::spawn(|| -> Result<i32> {
+      let code = alloc_code(RET5)?;
+      let x: extern "C" fn() -> i32 = unsafe { std::mem::transmute(code.as_ptr::<u8>()) };
+      Ok(x())
+    })
+    .join()
+    .expect("joining thread");
+
+    assert_eq!(result.ok(), Some(5));
+    assert_eq!(jit_write_protect_supported(), cfg!(target_arch = "aarch64"));
+  }
+
+  #[test]
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+  fn writable_code_executes_once_finalized() -> Result<()> {
+    let mut code = WritableCode::new(RET5.len())?;
+    code.copy_from_slice(RET5);
+    assert_eq!(code.len(), RET5.len());
+
+    let code = code.finalize()?;
+    let x: extern "C" fn() -> i32 = unsafe { code.as_fn() };
+
+    assert_eq!(x(), 5);
+    assert_eq!(&code[..], RET5);
+    assert!(!crate::query(code.as_allocation().as_ptr::<()>())?.is_writable());
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(all(any(target_os = "macos", target_os = "ios"), target_arch = "aarch64")))]
+  fn writable_code_is_not_executable() -> Result<()> {
+    let code = WritableCode::new(1)?;
+    let region = crate::query(code.as_ptr())?;
+
+    assert_eq!(region.protection(), Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  fn can_allocate_executable_is_idempotent() {
+    let supported = can_allocate_executable();
+    assert_eq!(can_allocate_executable(), supported);
+    assert!(supported || !cfg!(any(target_os = "linux", windows)));
+  }
+
+  #[test]
+  fn alloc_code_rejects_empty_code() {
+    assert!(matches!(alloc_code(&[]), Err(Error::InvalidParameter(_))));
+  }
+}
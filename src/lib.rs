@@ -81,25 +81,94 @@
 extern crate bitflags;
 
 #[cfg(not(target_os = "haiku"))]
-pub use alloc::{alloc, alloc_at, Allocation};
+pub use alloc::{
+  alloc, alloc_aligned, alloc_at, alloc_at_force, alloc_at_with_offset, alloc_guarded,
+  alloc_in_range, alloc_mirrored, alloc_with_cache, reserve, reserve_aligned, AllocOptions,
+  Allocation, CacheMode,
+};
 
+#[cfg(all(unix, not(target_os = "haiku")))]
+pub use backing::{set_anon_backing, AnonBacking};
+pub use diff::{regions_difference, RegionDiff};
 pub use error::{Error, Result};
-pub use lock::{lock, unlock, LockGuard};
-pub use protect::{protect, protect_with_handle, ProtectGuard};
-pub use query::{query, query_range, QueryIter};
+pub use inherit::set_inherit;
+pub use jit::{
+  alloc_code, can_allocate_executable, jit_write_protect_supported, ExecutableCode, WritableCode,
+};
+pub use lock::{
+  is_resident, lock, lock_range, lock_with_retry, try_lock, unlock, unlock_best_effort, LockGuard,
+  LockedRange,
+};
+
+#[cfg(any(
+  target_os = "linux",
+  target_os = "android",
+  target_os = "macos",
+  target_os = "ios",
+  target_os = "freebsd",
+  target_os = "openbsd"
+))]
+pub use lock::memlock_limit;
+pub use observe::{clear_protect_observer, set_protect_observer, ProtectPhase};
+pub use oom::{clear_oom_hook, set_oom_hook};
+#[cfg(feature = "tracking")]
+pub use owned::{is_owned, owned_allocations};
+pub use protect::{
+  nx_enforced, protect, protect_preserve_flags, protect_verbose, protect_with_handle, seal,
+  with_protection, PageProtection, ProtectGuard, ProtectReport,
+};
+pub use query::{
+  address_space_bounds, audit_wx, for_each_region, invalidate_query_cache, largest_free_hole,
+  nearest_region, protection_at, query, query_cached, query_many, query_process, query_range,
+  query_range_inclusive, total_mapped, total_mapped_with, QueryIter,
+};
+
+#[cfg(all(unix, not(target_os = "haiku")))]
+pub use map::{file_mapping_max_protection, map_file, MemoryMap, ProtectionPolicy};
+
+pub use stats::{memory_stats, MemoryStats};
+
+#[cfg(not(target_os = "haiku"))]
+pub use stack::{alloc_growable_stack, commit_next_guard};
+
+pub use track::{install_wp_handler, write_protect_for_tracking};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use watch::{watch_protection_changes, ProtectionWatcher, RegionChange};
 
 #[cfg(target_os = "haiku")]
-pub use os::{alloc, alloc_at, Allocation};
+pub use os::{alloc, alloc_at, alloc_mirrored, AllocOptions, Allocation, HaikuLock};
 
 #[cfg(not(target_os = "haiku"))]
 mod alloc;
 
+#[cfg(all(unix, not(target_os = "haiku")))]
+mod backing;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod diff;
 mod error;
+mod inherit;
+mod jit;
 mod lock;
+#[cfg(all(unix, not(target_os = "haiku")))]
+mod map;
+mod observe;
+mod oom;
 mod os;
+#[cfg(feature = "tracking")]
+mod owned;
 pub mod page;
+#[cfg(all(feature = "async", not(target_os = "haiku")))]
+mod prefetch;
 mod protect;
 mod query;
+#[cfg(not(target_os = "haiku"))]
+mod stack;
+mod stats;
+mod track;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod watch;
 
 mod util;
 
@@ -121,6 +190,10 @@ pub struct Region {
   shared: bool,
   /// Size of the region (multiple of page size)
   size: usize,
+  /// The team owning the region (Haiku only)
+  team: Option<i32>,
+  /// Whether the region is backed by a file or not (Linux only)
+  file_backed: bool,
 }
 
 impl Region {
@@ -165,8 +238,17 @@ impl Region {
 
   /// Returns whether the region is committed or not.
   ///
-  /// This is always true for all operating system's, the exception being
-  /// `MEM_RESERVE` pages on Windows.
+  /// A committed region is backed by physical memory (or swap), whilst an
+  /// uncommitted region merely reserves address space.
+  ///
+  /// # Platform-specific behavior
+  ///
+  /// - **Windows**: `MEM_COMMIT` regions are committed, whilst `MEM_RESERVE`
+  ///   regions are not.
+  /// - **POSIX**: there is no explicit notion of committed memory. Regions
+  ///   without any protection (i.e. [`Protection::NONE`]), such as those
+  ///   created by [`reserve`], are considered uncommitted. All other regions
+  ///   are considered committed.
   #[inline(always)]
   pub fn is_committed(&self) -> bool {
     !self.reserved
@@ -197,6 +279,8 @@ impl Region {
   }
 
   /// Returns whether the region is shared between processes or not.
+  ///
+  /// On Haiku, an area is considered shared once it has been cloned.
   #[inline(always)]
   pub fn is_shared(&self) -> bool {
     self.shared
@@ -211,7 +295,30 @@ impl Region {
     self.size
   }
 
+  /// Returns the number of pages spanned by the region.
+  ///
+  /// Since the size of a region is always a multiple of the page size, this
+  /// is exact.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// let data = [0; 100];
+  /// let region = region::query(data.as_ptr())?;
+  ///
+  /// assert_eq!(region.page_count() * region::page::size(), region.len());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn page_count(&self) -> usize {
+    self.size / page::size()
+  }
+
   /// Returns whether region is empty or not.
+  ///
+  /// This is the case for null-sized virtual pages (e.g. on OpenBSD).
   #[inline(always)]
   pub fn is_empty(&self) -> bool {
     self.size == 0
@@ -222,6 +329,362 @@ impl Region {
   pub fn protection(&self) -> Protection {
     self.protection
   }
+
+  /// Returns the ID of the team owning the region.
+  ///
+  /// This allows e.g. determining whether a cloned area belongs to another
+  /// process. It is only available on Haiku, and is always `None` on other
+  /// platforms.
+  #[inline(always)]
+  pub fn owner_team(&self) -> Option<i32> {
+    self.team
+  }
+
+  /// Returns whether the region is backed by a file or not.
+  ///
+  /// This is only available on Linux (where any mapping with an inode is
+  /// considered file-backed, except for shared anonymous memory), and is
+  /// always `false` on other platforms.
+  #[inline(always)]
+  pub fn is_file_backed(&self) -> bool {
+    self.file_backed
+  }
+
+  /// Returns the region's boolean attributes, aggregated into a bitflag.
+  ///
+  /// Each flag corresponds to one of the region's predicates (e.g.
+  /// [`RegionFlags::GUARDED`] to [`Region::is_guarded`]), which allows the
+  /// attributes to be printed or compared at once.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::{Protection, RegionFlags};
+  ///
+  /// let memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// let region = region::query(memory.as_ptr::<()>())?;
+  ///
+  /// assert_eq!(region.flags(), RegionFlags::COMMITTED);
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn flags(&self) -> RegionFlags {
+    let mut flags = RegionFlags::empty();
+    flags.set(RegionFlags::COMMITTED, self.is_committed());
+    flags.set(RegionFlags::GUARDED, self.is_guarded());
+    flags.set(RegionFlags::SHARED, self.is_shared());
+    flags.set(RegionFlags::FILE_BACKED, self.is_file_backed());
+    flags
+  }
+
+  /// Returns the size, in bytes, of the region's memory that has been swapped
+  /// out.
+  ///
+  /// Together with [`Region::is_file_backed`], this helps explain why the
+  /// resident size of a process differs from its allocated size. The value is
+  /// not part of the region's snapshot; it's read from `/proc/self/smaps` on
+  /// each invocation. It is only available on Linux, and is always `None` on
+  /// other platforms (or if the region is no longer mapped).
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// let data = [0; 100];
+  /// let region = region::query(data.as_ptr())?;
+  ///
+  /// let swapped = region.swap_size().unwrap_or(0);
+  /// assert!(swapped <= region.len());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn swap_size(&self) -> Option<usize> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return os::swap_size(self.as_range());
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return None;
+  }
+
+  /// Returns whether the region overlaps the stack of the current thread.
+  ///
+  /// This is intended for guarding against e.g. protecting the memory that
+  /// the calling code is executing on. The stack bounds are queried on each
+  /// invocation, since they differ between threads.
+  ///
+  /// # Implementation
+  ///
+  /// This function is implemented using `GetCurrentThreadStackLimits` on
+  /// Windows, `pthread_get_stackaddr_np` on macOS, `pthread_stackseg_np` on
+  /// OpenBSD, and `pthread_attr_getstack` elsewhere. If the bounds cannot be
+  /// determined, `false` is returned.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// let data = [0; 100];
+  /// let region = region::query(data.as_ptr())?;
+  /// assert!(region.is_current_thread_stack());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn is_current_thread_stack(&self) -> bool {
+    let range = self.as_range();
+    matches!(
+      os::current_thread_stack(),
+      Some(stack) if stack.start < range.end && range.start < stack.end
+    )
+  }
+
+  /// Returns the offsets of the region's pages that have been written to,
+  /// since the dirty-page tracking was last reset.
+  ///
+  /// This is intended for incremental checkpointing: the tracking is reset
+  /// using [`Region::reset_dirty`], after which only the written pages are
+  /// reported. The offsets are relative to the region's base address, and in
+  /// ascending order. The pages of new mappings are initially reported as
+  /// dirty, until the tracking is reset.
+  ///
+  /// # Implementation
+  ///
+  /// This is only available on Linux, where the soft-dirty bits are read from
+  /// `/proc/self/pagemap`. This requires a kernel with `CONFIG_MEM_SOFT_DIRTY`,
+  /// which is probed once per process.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If the platform is not Linux, or the kernel does not track soft-dirty
+  ///   pages, [`Error::UnsupportedOperation`] will be returned.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::{Protection, Region};
+  ///
+  /// let memory = region::alloc(1, Protection::READ_WRITE)?;
+  ///
+  /// if Region::reset_dirty().is_ok() {
+  ///   unsafe { memory.as_ptr::<u8>().cast_mut().write(0xFF) };
+  ///   let region = region::query(memory.as_ptr::<()>())?;
+  ///   assert_eq!(region.dirty_pages()?, [0]);
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn dirty_pages(&self) -> Result<Vec<usize>> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return os::dirty_pages(self.as_range());
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return Err(Error::UnsupportedOperation);
+  }
+
+  /// Resets the dirty-page tracking, reported by [`Region::dirty_pages`].
+  ///
+  /// The tracking is reset for every page of the process, not only for a
+  /// single region, hence this is an associated function.
+  ///
+  /// # Implementation
+  ///
+  /// This is only available on Linux, where the soft-dirty bits are cleared
+  /// by writing to `/proc/self/clear_refs`.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If the platform is not Linux, or the kernel does not track soft-dirty
+  ///   pages, [`Error::UnsupportedOperation`] will be returned.
+  #[inline]
+  pub fn reset_dirty() -> Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    return os::reset_dirty();
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    return Err(Error::UnsupportedOperation);
+  }
+
+  /// Searches the region's memory for a byte pattern, returning the offset of
+  /// the first match (relative to the region's base address).
+  ///
+  /// The region is only searched if it's readable, committed and not guarded,
+  /// otherwise `None` is returned.
+  ///
+  /// # Safety
+  ///
+  /// A region is only a snapshot of the process' memory. The caller must ensure
+  /// that the region is still mapped, with at least the same protection,
+  /// throughout the search.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// let data = *b"needle in a stack";
+  /// let region = region::query(data.as_ptr())?;
+  /// let offset = unsafe { region.find(b"needle in a stack") };
+  ///
+  /// assert!(offset.is_some());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub unsafe fn find(&self, needle: &[u8]) -> Option<usize> {
+    let haystack = self.as_slice()?;
+
+    if needle.is_empty() {
+      return Some(0);
+    }
+
+    haystack
+      .windows(needle.len())
+      .position(|window| window == needle)
+  }
+
+  /// Returns a view of the region's memory as a byte slice.
+  ///
+  /// `None` is returned unless the region is readable, committed and not
+  /// guarded, so that reading e.g. a [`Protection::NONE`] or guard region is
+  /// caught upfront, rather than faulting.
+  ///
+  /// # Safety
+  ///
+  /// A region is only a snapshot of the process' memory, and the slice is not
+  /// tied to the lifetime of the underlying mapping. The caller must ensure
+  /// that the region remains mapped, readable and unmodified (including by
+  /// other threads, or other processes if it's shared) for as long as the
+  /// slice is in use.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// let data = [0xAB_u8; 100];
+  /// let region = region::query(data.as_ptr())?;
+  /// let slice = unsafe { region.as_slice() };
+  ///
+  /// assert!(slice.is_some());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub unsafe fn as_slice(&self) -> Option<&[u8]> {
+    if !self.is_readable() || !self.is_committed() || self.is_guarded() {
+      return None;
+    }
+
+    Some(std::slice::from_raw_parts(self.as_ptr::<u8>(), self.size))
+  }
+
+  /// Combines two adjacent regions into one.
+  ///
+  /// The regions are merged if one ends where the other begins (in either
+  /// order), and all of their attributes (e.g. protection, and whether they're
+  /// committed, guarded or shared) are equal. Otherwise `None` is returned.
+  /// This does not query the OS, and is intended for building custom
+  /// coalescers over e.g. [`query_process`].
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// let mut coalesced = Vec::<region::Region>::new();
+  ///
+  /// for region in region::query_process()? {
+  ///   let region = region?;
+  ///   match coalesced.last().and_then(|last| last.merge(&region)) {
+  ///     Some(merged) => *coalesced.last_mut().unwrap() = merged,
+  ///     None => coalesced.push(region),
+  ///   }
+  /// }
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn merge(&self, other: &Region) -> Option<Region> {
+    let (lower, upper) = if self.base <= other.base {
+      (self, other)
+    } else {
+      (other, self)
+    };
+
+    let attributes = |region: &Region| Region {
+      base: std::ptr::null(),
+      size: 0,
+      ..*region
+    };
+
+    if lower.as_range().end != upper.base as usize || attributes(lower) != attributes(upper) {
+      return None;
+    }
+
+    Some(Region {
+      size: lower.size.checked_add(upper.size)?,
+      ..*lower
+    })
+  }
+
+  /// Takes ownership of the region, returning an allocation that frees it
+  /// once dropped.
+  ///
+  /// This is useful for reclaiming memory that was allocated through another
+  /// binding (e.g. FFI), which is found by walking e.g. [`query_process`].
+  ///
+  /// # Implementation
+  ///
+  /// On Haiku, the allocation refers to the area containing the region (using
+  /// `area_for`). Elsewhere, it records the region's base address and size.
+  ///
+  /// # Errors
+  ///
+  /// - If the region is empty, [`Error::InvalidParameter`] will be returned.
+  /// - If the region is no longer mapped (Haiku only),
+  ///   [`Error::UnmappedRegion`] will be returned.
+  ///
+  /// # Safety
+  ///
+  /// The region must not be owned elsewhere (e.g. by another [`Allocation`],
+  /// the global allocator, or a loaded module), since it's freed once the
+  /// returned allocation is dropped. It must also still be mapped, as
+  /// described by the region. On Windows, the region must span an entire
+  /// allocation, i.e. its base must be the one returned by `VirtualAlloc`.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::Protection;
+  ///
+  /// let memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// let region = region::query(memory.as_ptr::<()>())?;
+  /// std::mem::forget(memory);
+  ///
+  /// let memory = unsafe { region.adopt()? };
+  /// assert_eq!(memory.as_ptr::<()>(), region.as_ptr());
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub unsafe fn adopt(&self) -> Result<Allocation> {
+    if self.size == 0 {
+      return Err(Error::InvalidParameter("region"));
+    }
+
+    #[cfg(target_os = "haiku")]
+    return os::adopt(self.base);
+
+    #[cfg(not(target_os = "haiku"))]
+    Ok(Allocation::from_raw_parts(self.base, self.size))
+  }
 }
 
 impl Default for Region {
@@ -234,6 +697,8 @@ impl Default for Region {
       protection: Protection::NONE,
       shared: false,
       size: 0,
+      team: None,
+      file_backed: false,
     }
   }
 }
@@ -256,6 +721,32 @@ bitflags! {
   /// let combine = Protection::READ | Protection::WRITE;
   /// let shorthand = Protection::READ_WRITE;
   /// ```
+  ///
+  /// As with any bitflag, [`Protection::all`] and [`Protection::empty`] are
+  /// available (also in `const` contexts), for all or no access rights:
+  ///
+  /// ```
+  /// use region::Protection;
+  ///
+  /// const ALL: Protection = Protection::all();
+  /// const EMPTY: Protection = Protection::empty();
+  ///
+  /// assert_eq!(ALL, Protection::READ_WRITE_EXECUTE);
+  /// assert_eq!(EMPTY, Protection::NONE);
+  /// ```
+  ///
+  /// The raw representation is accessible through [`Protection::bits`] and
+  /// [`Protection::from_bits`] (e.g. for FFI or serialization). Note that the
+  /// bits are the crate's own representation, and they do not correspond to
+  /// any OS-native flags (such as `PROT_READ` or `PAGE_READONLY`):
+  ///
+  /// ```
+  /// use region::Protection;
+  ///
+  /// let bits = Protection::READ_EXECUTE.bits();
+  /// assert_eq!(Protection::from_bits(bits), Some(Protection::READ_EXECUTE));
+  /// assert_eq!(Protection::from_bits(1), None);
+  /// ```
   #[derive(Default)]
   pub struct Protection: usize {
     /// No access allowed at all.
@@ -277,6 +768,60 @@ bitflags! {
   }
 }
 
+impl Protection {
+  /// Returns whether the protection allows the specified access.
+  ///
+  /// This is equivalent to [`Protection::contains`], but conveys the intent of
+  /// checking access rights.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use region::Protection;
+  ///
+  /// assert!(Protection::READ_WRITE.allows(Protection::READ));
+  /// assert!(!Protection::READ.allows(Protection::READ_WRITE));
+  /// ```
+  #[inline(always)]
+  pub fn allows(&self, access: Protection) -> bool {
+    self.contains(access)
+  }
+
+  /// Returns the flags that the protection lacks, for allowing the specified
+  /// access.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use region::Protection;
+  ///
+  /// let missing = Protection::READ.missing(Protection::READ_WRITE);
+  /// assert_eq!(missing, Protection::WRITE);
+  /// ```
+  #[inline(always)]
+  pub fn missing(&self, access: Protection) -> Protection {
+    access - *self
+  }
+
+  /// Returns whether the protection is both writable and executable.
+  ///
+  /// Such memory is a common target for code injection, hence it's generally
+  /// considered a security concern (see [`audit_wx`]).
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use region::Protection;
+  ///
+  /// assert!(Protection::READ_WRITE_EXECUTE.is_wx());
+  /// assert!(!Protection::READ_EXECUTE.is_wx());
+  /// ```
+  #[inline(always)]
+  pub fn is_wx(&self) -> bool {
+    self.contains(Protection::WRITE_EXECUTE)
+  }
+}
+
 impl std::fmt::Display for Protection {
   #[inline]
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -298,6 +843,70 @@ impl std::fmt::Display for Protection {
   }
 }
 
+/// A named access intent, convertible into a [`Protection`].
+///
+/// This is merely a readable alternative to combining flags, while
+/// [`Protection`] remains the type accepted throughout the crate.
+///
+/// # Examples
+///
+/// ```
+/// use region::{Access, Protection};
+///
+/// assert_eq!(Protection::from(Access::ReadWrite), Protection::READ_WRITE);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+  /// No access allowed at all.
+  None,
+  /// Read access.
+  Read,
+  /// Write access.
+  Write,
+  /// Execute access.
+  Exec,
+  /// Read and write access.
+  ReadWrite,
+  /// Read and execute access.
+  ReadExec,
+  /// Read, write and execute access.
+  ReadWriteExec,
+}
+
+impl From<Access> for Protection {
+  #[inline]
+  fn from(access: Access) -> Self {
+    match access {
+      Access::None => Protection::NONE,
+      Access::Read => Protection::READ,
+      Access::Write => Protection::WRITE,
+      Access::Exec => Protection::EXECUTE,
+      Access::ReadWrite => Protection::READ_WRITE,
+      Access::ReadExec => Protection::READ_EXECUTE,
+      Access::ReadWriteExec => Protection::READ_WRITE_EXECUTE,
+    }
+  }
+}
+
+bitflags! {
+  /// A bitflag of a region's boolean attributes.
+  ///
+  /// This is returned by [`Region::flags`], and it's mainly intended for
+  /// compactly logging or comparing regions. Its [`Debug`](std::fmt::Debug)
+  /// representation lists the names of the flags that are set.
+  #[derive(Default)]
+  pub struct RegionFlags: usize {
+    /// The region is committed (see [`Region::is_committed`]).
+    const COMMITTED = (1 << 0);
+    /// The region is guarded (see [`Region::is_guarded`]).
+    const GUARDED = (1 << 1);
+    /// The region is shared (see [`Region::is_shared`]).
+    const SHARED = (1 << 2);
+    /// The region is backed by a file (see [`Region::is_file_backed`]).
+    const FILE_BACKED = (1 << 3);
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -310,6 +919,281 @@ mod tests {
     assert_eq!(Protection::WRITE.to_string(), "-w-");
   }
 
+  #[test]
+  fn region_is_current_thread_stack_for_local_variable() -> Result<()> {
+    let local = 0u8;
+    let region = query(&local as *const u8)?;
+    assert!(region.is_current_thread_stack());
+
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    assert!(!query(memory.as_ptr::<u8>())?.is_current_thread_stack());
+
+    // Another thread's stack is not the current one's
+    let other = std::thread::spawn(|| {
+      let local = 0u8;
+      query(&local as *const u8).map(|region| region.as_range())
+    })
+    .join()
+    .unwrap()?;
+    let stack = os::current_thread_stack().expect("current thread stack");
+    assert!(other.end <= stack.start || other.start >= stack.end);
+    Ok(())
+  }
+
+  #[test]
+  fn protection_from_access_maps_each_intent() {
+    assert_eq!(Protection::from(Access::None), Protection::NONE);
+    assert_eq!(Protection::from(Access::Read), Protection::READ);
+    assert_eq!(Protection::from(Access::Write), Protection::WRITE);
+    assert_eq!(Protection::from(Access::Exec), Protection::EXECUTE);
+    assert_eq!(Protection::from(Access::ReadWrite), Protection::READ_WRITE);
+    assert_eq!(Protection::from(Access::ReadExec), Protection::READ_EXECUTE);
+    assert_eq!(
+      Protection::from(Access::ReadWriteExec),
+      Protection::READ_WRITE_EXECUTE
+    );
+  }
+
+  #[test]
+  fn protection_allows_contained_access() {
+    let rw = Protection::READ_WRITE;
+
+    assert!(rw.allows(Protection::READ));
+    assert!(rw.allows(Protection::NONE));
+    assert!(!rw.allows(Protection::READ_EXECUTE));
+    assert_eq!(rw.missing(Protection::READ), Protection::NONE);
+    assert_eq!(rw.missing(Protection::READ_EXECUTE), Protection::EXECUTE);
+  }
+
+  #[test]
+  fn protection_bits_round_trip() {
+    for protection in &[
+      Protection::NONE,
+      Protection::READ,
+      Protection::READ_WRITE,
+      Protection::READ_WRITE_EXECUTE,
+      Protection::WRITE_EXECUTE,
+    ] {
+      assert_eq!(Protection::from_bits(protection.bits()), Some(*protection));
+    }
+
+    assert_eq!(Protection::from_bits(1 << 4), None);
+  }
+
+  #[test]
+  fn region_adopt_takes_ownership_of_allocation() -> Result<()> {
+    let memory = alloc(page::size(), Protection::READ_WRITE)?;
+    let region = query(memory.as_ptr::<()>())?;
+    std::mem::forget(memory);
+
+    let adopted = unsafe { region.adopt()? };
+    assert_eq!(adopted.as_ptr::<()>(), region.as_ptr());
+    assert_eq!(adopted.len(), region.len());
+
+    let base = region.as_ptr::<()>();
+    drop(adopted);
+    assert!(matches!(query(base), Err(Error::UnmappedRegion)));
+    assert!(matches!(
+      unsafe { Region::default().adopt() },
+      Err(Error::InvalidParameter("region"))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn region_find_locates_marker() -> Result<()> {
+    const MARKER: &[u8] = &[0x8B, 0xAD, 0xF0, 0x0D, 0xDE, 0xAD, 0xC0, 0xDE];
+
+    let memory = alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let address = unsafe { memory.as_ptr::<u8>().add(page::size() + 10) as *mut u8 };
+    unsafe { std::ptr::copy_nonoverlapping(MARKER.as_ptr(), address, MARKER.len()) };
+
+    let region = query(memory.as_ptr::<()>())?;
+    let expected = address as usize - region.as_ptr::<u8>() as usize;
+    assert_eq!(unsafe { region.find(MARKER) }, Some(expected));
+
+    unsafe { protect(memory.as_ptr::<u8>(), memory.len(), Protection::NONE)? };
+    let region = query(memory.as_ptr::<()>())?;
+    assert_eq!(unsafe { region.find(MARKER) }, None);
+    Ok(())
+  }
+
+  #[test]
+  fn region_as_slice_requires_readable_memory() -> Result<()> {
+    let memory = alloc(page::size(), Protection::READ_WRITE)?;
+    unsafe { memory.as_ptr::<u8>().cast_mut().write(0xFF) };
+
+    let region = query(memory.as_ptr::<()>())?;
+    let slice = unsafe { region.as_slice() }.expect("readable region");
+    assert_eq!(slice.as_ptr(), memory.as_ptr());
+    assert_eq!(slice[0], 0xFF);
+
+    unsafe { memory.protect(Protection::NONE)? };
+    let region = query(memory.as_ptr::<()>())?;
+    assert_eq!(unsafe { region.as_slice() }, None);
+    assert_eq!(unsafe { Region::default().as_slice() }, None);
+    Ok(())
+  }
+
+  #[test]
+  fn region_is_empty_without_size() {
+    let empty = Region {
+      size: 0,
+      ..Default::default()
+    };
+    let page = Region {
+      size: page::size(),
+      ..empty
+    };
+
+    assert!(empty.is_empty());
+    assert!(!page.is_empty());
+  }
+
+  #[test]
+  fn region_flags_match_predicates() -> Result<()> {
+    let memory = alloc(page::size(), Protection::READ_WRITE)?;
+    let reserved = reserve(page::size())?;
+    let allocated = [query(memory.as_ptr::<()>()), query(reserved.as_ptr::<()>())];
+
+    for region in query_process()?.chain(allocated) {
+      let region = region?;
+      let flags = region.flags();
+
+      assert_eq!(
+        flags.contains(RegionFlags::COMMITTED),
+        region.is_committed()
+      );
+      assert_eq!(flags.contains(RegionFlags::GUARDED), region.is_guarded());
+      assert_eq!(flags.contains(RegionFlags::SHARED), region.is_shared());
+      assert_eq!(
+        flags.contains(RegionFlags::FILE_BACKED),
+        region.is_file_backed()
+      );
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn region_dirty_pages_include_written_page() -> Result<()> {
+    let pz = page::size();
+    let memory = alloc(pz * 4, Protection::READ_WRITE)?;
+    let base = memory.as_ptr::<u8>().cast_mut();
+
+    unsafe { base.write(0xFF) };
+    match Region::reset_dirty() {
+      // The kernel may lack `CONFIG_MEM_SOFT_DIRTY`
+      Err(Error::UnsupportedOperation) => return Ok(()),
+      result => result?,
+    }
+    unsafe { base.add(pz * 2).write(0xFF) };
+
+    let region = query(memory.as_ptr::<()>())?;
+    let dirty = region.dirty_pages()?;
+    let offset = memory.as_ptr::<u8>() as usize - region.as_ptr::<u8>() as usize;
+
+    assert!(dirty.contains(&(offset + pz * 2)));
+    assert!(!dirty.contains(&offset));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  fn region_dirty_pages_is_unsupported_elsewhere() -> Result<()> {
+    let memory = alloc(page::size(), Protection::READ_WRITE)?;
+    let region = query(memory.as_ptr::<()>())?;
+
+    assert!(matches!(
+      region.dirty_pages(),
+      Err(Error::UnsupportedOperation)
+    ));
+    assert!(matches!(
+      Region::reset_dirty(),
+      Err(Error::UnsupportedOperation)
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn region_page_count_spans_allocation() -> Result<()> {
+    let pz = page::size();
+    let memory = alloc(pz * 6, Protection::NONE)?;
+    let inner = unsafe { memory.as_ptr::<u8>().add(pz) };
+
+    // The surrounding pages prevent the region from merging with others
+    unsafe { protect(inner, pz * 4, Protection::READ_WRITE)? };
+    let region = query(inner)?;
+
+    assert_eq!(region.page_count(), 4);
+    assert_eq!(Region::default().page_count(), 0);
+    Ok(())
+  }
+
+  #[test]
+  fn region_merge_combines_adjacent_regions() {
+    let pz = page::size();
+    let lower = Region {
+      base: pz as *const (),
+      size: pz,
+      protection: Protection::READ,
+      ..Default::default()
+    };
+    let upper = Region {
+      base: (pz * 2) as *const (),
+      size: pz * 2,
+      ..lower
+    };
+
+    let expected = Some(Region {
+      size: pz * 3,
+      ..lower
+    });
+    assert_eq!(lower.merge(&upper), expected);
+    assert_eq!(upper.merge(&lower), expected);
+  }
+
+  #[test]
+  fn region_merge_rejects_non_adjacent_regions() {
+    let pz = page::size();
+    let lower = Region {
+      base: pz as *const (),
+      size: pz,
+      ..Default::default()
+    };
+    let upper = Region {
+      base: (pz * 3) as *const (),
+      ..lower
+    };
+
+    assert_eq!(lower.merge(&upper), None);
+    assert_eq!(lower.merge(&lower), None);
+  }
+
+  #[test]
+  fn region_merge_rejects_differing_attributes() {
+    let pz = page::size();
+    let lower = Region {
+      base: pz as *const (),
+      size: pz,
+      protection: Protection::READ,
+      ..Default::default()
+    };
+    let upper = Region {
+      base: (pz * 2) as *const (),
+      protection: Protection::READ_WRITE,
+      ..lower
+    };
+    let shared = Region {
+      base: (pz * 2) as *const (),
+      shared: true,
+      ..lower
+    };
+
+    assert_eq!(lower.merge(&upper), None);
+    assert_eq!(lower.merge(&shared), None);
+  }
+
  // #[cfg(all(unix, not(target_os = "haiku")))]
  #[cfg(unix)]
   pub mod util {
@@ -329,6 +1213,7 @@ mod tests {
 
     #[allow(clippy::fallible_impl_from)]
     impl From<Protection> for &'static [MapOption] {
+      #[inline]
       fn from(protection: Protection) -> Self {
         match protection {
           Protection::NONE => &[],
@@ -1,4 +1,5 @@
-use crate::{os, util, Result};
+use crate::{os, page, util, Error, Result};
+use std::time::Duration;
 
 /// Locks one or more memory regions to RAM.
 ///
@@ -18,10 +19,13 @@ use crate::{os, util, Result};
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
-/// - If size is zero,
-/// [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
-/// returned.
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If locking the range would exceed the process' locked-memory limit (e.g.
+///   `RLIMIT_MEMLOCK`), [`Error::LockLimitExceeded`](crate::Error::LockLimitExceeded)
+///   will be returned.
 ///
 /// # Examples
 ///
@@ -38,6 +42,186 @@ pub fn lock<T>(address: *const T, size: usize) -> Result<LockGuard> {
   os::lock(address.cast(), size).map(|_| LockGuard::new(address, size))
 }
 
+/// Locks one or more memory regions to RAM, retrying on transient failures.
+///
+/// Under memory pressure, locking may transiently fail (i.e. `EAGAIN` on
+/// Linux). This function then retries, up to `attempts` in total, sleeping in
+/// between. The delay starts at `backoff`, and doubles after each attempt. Any
+/// other error is returned immediately. This is useful for e.g. real-time
+/// applications that lock their memory upon startup.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+/// - The number of attempts may not be zero.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned (the last one, if all attempts fail).
+/// - If size or attempts is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+/// - If locking the range would exceed the process' locked-memory limit (e.g.
+///   `RLIMIT_MEMLOCK`), [`Error::LockLimitExceeded`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use std::time::Duration;
+///
+/// let data = [0; 100];
+/// let _guard = region::lock_with_retry(data.as_ptr(), data.len(), 3, Duration::from_millis(10))?;
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn lock_with_retry<T>(
+  address: *const T,
+  size: usize,
+  attempts: u32,
+  backoff: Duration,
+) -> Result<LockGuard> {
+  if attempts == 0 {
+    return Err(Error::InvalidParameter("attempts"));
+  }
+
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  let mut delay = backoff;
+
+  let mut attempt = 1;
+
+  loop {
+    match os::lock(address.cast(), size) {
+      Ok(()) => return Ok(LockGuard::new(address, size)),
+      Err(error) if attempt < attempts && is_transient(&error) => {
+        std::thread::sleep(delay);
+        delay = delay.saturating_mul(2);
+        attempt += 1;
+      }
+      Err(error) => return Err(error),
+    }
+  }
+}
+
+/// Returns whether a failure to lock memory may succeed when retried.
+#[cfg_attr(windows, allow(unused_variables))]
+fn is_transient(error: &Error) -> bool {
+  #[cfg(unix)]
+  return error.raw_os_error() == Some(libc::EAGAIN);
+
+  // Windows has no transient failures, only an exhausted working set quota
+  #[cfg(windows)]
+  return false;
+}
+
+/// Attempts to lock one or more memory regions to RAM, without blocking.
+///
+/// Locking memory faults in any pages that are not resident, which may block
+/// the calling thread. This function instead returns `None` if any page within
+/// the range is not resident, allowing e.g. an async task to yield and retry
+/// later. Otherwise the regions are locked, and a [`LockGuard`] is returned.
+///
+/// The guard is returned rather than merely whether the regions were locked
+/// (i.e. a `bool`), since the range would otherwise remain locked without any
+/// means of unlocking it. `Some` corresponds to `true`, and `None` to `false`.
+///
+/// # Implementation
+///
+/// Residency is determined using [`is_resident`] before locking. Since pages
+/// may be paged out in between, a lock is not strictly guaranteed to never
+/// block, but it will not fault in a range that is known to be absent.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If locking the range would exceed the process' locked-memory limit (e.g.
+///   `RLIMIT_MEMLOCK`), [`Error::LockLimitExceeded`](crate::Error::LockLimitExceeded)
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let data = [0; 100];
+/// if let Some(_guard) = region::try_lock(data.as_ptr(), data.len())? {
+///   // The data is locked to RAM
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn try_lock<T>(address: *const T, size: usize) -> Result<Option<LockGuard>> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  if !os::is_resident(address.cast(), size)? {
+    return Ok(None);
+  }
+
+  os::lock(address.cast(), size).map(|_| Some(LockGuard::new(address, size)))
+}
+
+/// Locks one or more memory regions to RAM, returning a handle to the locked
+/// range.
+///
+/// Unlike [`lock`], the regions are not unlocked automatically. Instead the
+/// returned [`LockedRange`] records the exact (page-aligned) range that was
+/// locked, and [`LockedRange::unlock`] guarantees that the very same range is
+/// unlocked.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If locking the range would exceed the process' locked-memory limit (e.g.
+///   `RLIMIT_MEMLOCK`), [`Error::LockLimitExceeded`](crate::Error::LockLimitExceeded)
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let data = [0; 100];
+/// let locked = region::lock_range(data.as_ptr(), data.len())?;
+/// locked.unlock()?;
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn lock_range<T>(address: *const T, size: usize) -> Result<LockedRange> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  os::lock(address.cast(), size).map(|_| LockedRange {
+    address: address.cast(),
+    size,
+  })
+}
+
 /// Unlocks one or more memory regions from RAM.
 ///
 /// If possible, prefer to use [`lock`] combined with the [`LockGuard`].
@@ -53,16 +237,153 @@ pub fn lock<T>(address: *const T, size: usize) -> Result<LockGuard> {
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
-/// - If size is zero,
-/// [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
-/// returned.
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
 #[inline]
 pub fn unlock<T>(address: *const T, size: usize) -> Result<()> {
   let (address, size) = util::round_to_page_boundaries(address, size)?;
   os::unlock(address.cast(), size)
 }
 
+/// Unlocks one or more memory regions from RAM, ignoring any page that fails
+/// to be unlocked.
+///
+/// This is intended for cleanup, when it's unknown which subset of a range
+/// has been locked; [`unlock`] may then fail for the entire range (e.g. on
+/// Windows, where unlocking a page that isn't locked is an error). The entire
+/// range is unlocked at once, and only if that fails is it unlocked one page
+/// at a time. The number of bytes for which unlocking did not fail is
+/// returned; on POSIX, this includes pages that were never locked.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let data = [0; 100];
+/// std::mem::forget(region::lock(data.as_ptr(), data.len())?);
+///
+/// let unlocked = region::unlock_best_effort(data.as_ptr(), data.len())?;
+/// assert!(unlocked >= data.len());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn unlock_best_effort<T>(address: *const T, size: usize) -> Result<usize> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  if os::unlock(address.cast(), size).is_ok() {
+    return Ok(size);
+  }
+
+  let page_size = page::size();
+  Ok(
+    (0..size / page_size)
+      .map(|index| address.cast::<u8>().wrapping_add(index * page_size))
+      .filter(|page| os::unlock(page.cast(), page_size).is_ok())
+      .count()
+      * page_size,
+  )
+}
+
+/// Returns whether all pages within an address range are resident in RAM.
+///
+/// This can be used to verify that a [`lock`] had the intended effect. Note
+/// that the result is only a snapshot; unless the pages are locked, they may
+/// be paged out at any time.
+///
+/// # Implementation
+///
+/// This function is implemented using `QueryWorkingSetEx` on Windows, and
+/// `mincore` on POSIX. On Haiku, residency is determined for the enclosing area
+/// as a whole.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let data = [0; 100];
+/// let _guard = region::lock(data.as_ptr(), data.len())?;
+/// assert!(region::is_resident(data.as_ptr(), data.len())?);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn is_resident<T>(address: *const T, size: usize) -> Result<bool> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  os::is_resident(address.cast(), size)
+}
+
+/// Returns the soft and hard limits, in bytes, of memory that may be locked.
+///
+/// The soft limit is the one enforced by [`lock`]; exceeding it results in
+/// [`Error::LockLimitExceeded`](crate::Error::LockLimitExceeded). This allows
+/// callers to pre-flight large locks. An unlimited limit is represented by
+/// [`usize::MAX`].
+///
+/// # Implementation
+///
+/// This function is implemented using `getrlimit(RLIMIT_MEMLOCK)`. Note that
+/// privileged processes (e.g. with `CAP_IPC_LOCK` on Linux) are not bound by
+/// the limits.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let (soft, hard) = region::memlock_limit()?;
+/// assert!(soft <= hard);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(any(
+  target_os = "linux",
+  target_os = "android",
+  target_os = "macos",
+  target_os = "ios",
+  target_os = "freebsd",
+  target_os = "openbsd"
+))]
+#[inline]
+pub fn memlock_limit() -> Result<(usize, usize)> {
+  os::memlock_limit()
+}
+
 /// A RAII implementation of a scoped lock.
 ///
 /// When this structure is dropped (falls out of scope), the virtual lock will be
@@ -94,6 +415,50 @@ impl Drop for LockGuard {
 unsafe impl Send for LockGuard {}
 unsafe impl Sync for LockGuard {}
 
+/// A handle to a range of memory locked to RAM.
+///
+/// This `struct` is created by [`lock_range`]. See its documentation for more.
+#[must_use]
+#[derive(Debug)]
+pub struct LockedRange {
+  address: *const (),
+  size: usize,
+}
+
+impl LockedRange {
+  /// Returns a pointer to the locked range's base address.
+  ///
+  /// The address is always aligned to the operating system's page size.
+  #[inline(always)]
+  pub fn as_ptr<T>(&self) -> *const T {
+    self.address.cast()
+  }
+
+  /// Returns the size of the locked range in bytes.
+  ///
+  /// The size is always aligned to a multiple of the operating system's page
+  /// size.
+  #[allow(clippy::len_without_is_empty)]
+  #[inline(always)]
+  pub fn len(&self) -> usize {
+    self.size
+  }
+
+  /// Unlocks the exact range that was locked.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  #[inline]
+  pub fn unlock(self) -> Result<()> {
+    os::unlock(self.address, self.size)
+  }
+}
+
+unsafe impl Send for LockedRange {}
+unsafe impl Sync for LockedRange {}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -107,6 +472,96 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn lock_makes_pages_resident() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ_WRITE, Protection::READ_WRITE]);
+    let _guard = lock(map.as_ptr(), page::size() * 2)?;
+    assert!(is_resident(map.as_ptr(), page::size() * 2)?);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn lock_with_retry_succeeds_after_transient_failures() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ_WRITE]);
+    let backoff = Duration::from_millis(1);
+
+    os::fail(os::Call::Mlock, libc::EAGAIN, 2);
+    let _guard = lock_with_retry(map.as_ptr(), page::size(), 3, backoff)?;
+
+    os::fail(os::Call::Mlock, libc::EAGAIN, 2);
+    let result = lock_with_retry(map.as_ptr(), page::size(), 2, backoff);
+    assert_eq!(
+      result.err().and_then(|error| error.raw_os_error()),
+      Some(libc::EAGAIN)
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn lock_with_retry_rejects_zero_attempts() {
+    let data = [0; 100];
+    let result = lock_with_retry(data.as_ptr(), data.len(), 0, Duration::ZERO);
+    assert!(matches!(result, Err(Error::InvalidParameter("attempts"))));
+  }
+
+  #[test]
+  fn try_lock_locks_resident_pages() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ_WRITE]);
+    unsafe { std::ptr::write_volatile(map.as_ptr() as *mut u8, 1) };
+
+    assert!(try_lock(map.as_ptr(), page::size())?.is_some());
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn try_lock_skips_absent_pages() -> Result<()> {
+    // Anonymous memory is not faulted in until it's accessed
+    let memory = crate::alloc(page::size(), Protection::READ_WRITE)?;
+    assert!(try_lock(memory.as_ptr::<u8>(), memory.len())?.is_none());
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd"
+  ))]
+  fn memlock_limit_returns_ordered_limits() -> Result<()> {
+    let (soft, hard) = memlock_limit()?;
+    assert!(soft <= hard);
+    Ok(())
+  }
+
+  #[test]
+  fn lock_range_records_locked_range() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ_WRITE, Protection::READ_WRITE]);
+    let address = unsafe { map.as_ptr().add(1) };
+    let locked = lock_range(address, page::size())?;
+
+    assert_eq!(locked.as_ptr(), map.as_ptr());
+    assert_eq!(locked.len(), page::size() * 2);
+    assert!(is_resident(map.as_ptr(), page::size() * 2)?);
+    locked.unlock()
+  }
+
+  #[test]
+  fn unlock_best_effort_tolerates_partially_locked_range() -> Result<()> {
+    let pz = page::size();
+    let memory = crate::alloc(pz * 4, Protection::READ_WRITE)?;
+    std::mem::forget(lock(memory.as_ptr::<u8>(), pz * 2)?);
+
+    // Windows refuses to unlock pages that aren't locked, unlike POSIX
+    let unlocked = unlock_best_effort(memory.as_ptr::<u8>(), memory.len())?;
+    assert_eq!(unlocked, if cfg!(windows) { pz * 2 } else { pz * 4 });
+    Ok(())
+  }
+
   #[test]
   fn unlock_mapped_pages_succeeds() -> Result<()> {
     let map = alloc_pages(&[Protection::READ_WRITE]);
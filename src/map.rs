@@ -0,0 +1,486 @@
+use crate::{os, util, Allocation, Error, Protection, Result};
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+
+/// The policy for mapping a file with a protection its open mode does not
+/// permit.
+///
+/// This `enum` is used by [`map_file`]. See its documentation for more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectionPolicy {
+  /// Fail with [`Error::InvalidParameter`].
+  Strict,
+  /// Downgrade to the strongest protection permitted, by removing any
+  /// disallowed flags.
+  BestEffort,
+}
+
+/// Maps a file into memory, with a defined protection.
+///
+/// The mapping is shared, which means that any writes are carried through to
+/// the underlying file. Alongside the allocation, the effective protection of
+/// the mapping is returned.
+///
+/// Which protections are permitted is determined by the file's open mode:
+///
+/// | Open mode  | Permitted protection                 |
+/// |------------|--------------------------------------|
+/// | Read-only  | [`Protection::READ`]                 |
+/// | Read-write | [`Protection::READ_WRITE`]           |
+/// | Write-only | None, since a file must be readable  |
+///
+/// Whether a mapping may also be executable is not determined by the open
+/// mode (but e.g. by the file system's mount options), hence
+/// [`Protection::EXECUTE`] is never reported as permitted.
+///
+/// If the requested protection is not permitted, the `policy` decides whether
+/// to fail, or to downgrade the protection (e.g. [`Protection::READ_WRITE`] to
+/// [`Protection::READ`] for a read-only file).
+///
+/// # Parameters
+///
+/// - The file is mapped from its beginning.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary. Accessing pages
+///   beyond the end of the file results in undefined behavior.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or exceeds the address space, [`Error::InvalidParameter`]
+///   will be returned.
+/// - If the file is write-only, or the protection is not permitted with the
+///   [`ProtectionPolicy::Strict`] policy, [`Error::InvalidParameter`] will be
+///   returned.
+///
+/// # Safety
+///
+/// The mapping is shared with the underlying file. If the file is modified
+/// (e.g. truncated) by this or another process whilst mapped, the mapping's
+/// contents may change, or accessing it may terminate the process.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{Protection, ProtectionPolicy};
+///
+/// let file = std::fs::File::open("Cargo.toml").expect("opening file");
+/// let (_, protection) = unsafe {
+///   region::map_file(&file, 100, Protection::READ_WRITE, ProtectionPolicy::BestEffort)?
+/// };
+///
+/// assert_eq!(protection, Protection::READ);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub unsafe fn map_file(
+  file: &File,
+  size: usize,
+  protection: Protection,
+  policy: ProtectionPolicy,
+) -> Result<(Allocation, Protection)> {
+  let (_, size) = util::round_to_page_boundaries(std::ptr::null::<()>(), size)?;
+  let permitted = os::file_protection(file.as_raw_fd())?;
+
+  let protection = match policy {
+    ProtectionPolicy::Strict if !permitted.contains(protection) => {
+      return Err(Error::InvalidParameter("protection"))
+    }
+    ProtectionPolicy::Strict => protection,
+    ProtectionPolicy::BestEffort => protection & permitted,
+  };
+
+  let base = os::map_file(file.as_raw_fd(), size, protection)?;
+  Ok((Allocation::from_raw_parts(base, size), protection))
+}
+
+/// Returns the strongest protection a mapping of a file may have.
+///
+/// The protection is derived from the file's open mode (see [`map_file`]), so
+/// that callers can choose a permitted protection instead of having
+/// [`map_file`] fail.
+///
+/// # Implementation
+///
+/// The open mode is retrieved using `fcntl(F_GETFL)`.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the file is write-only, [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let file = std::fs::File::open("Cargo.toml").expect("opening file");
+/// let protection = region::file_mapping_max_protection(&file)?;
+///
+/// assert_eq!(protection, Protection::READ);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn file_mapping_max_protection(file: &File) -> Result<Protection> {
+  os::file_protection(file.as_raw_fd())
+}
+
+/// A file-backed memory mapping, accessible as a slice.
+///
+/// The mapping is accessible as `&[u8]` if it's readable, and as `&mut [u8]`
+/// if it's also writable (see [`MemoryMap::as_slice`] and
+/// [`MemoryMap::as_mut_slice`]).
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{MemoryMap, Protection, ProtectionPolicy};
+///
+/// let file = std::fs::File::open("Cargo.toml").expect("opening file");
+/// let map = unsafe { MemoryMap::new(&file, 9, Protection::READ, ProtectionPolicy::Strict)? };
+///
+/// assert_eq!(map.as_slice(), Some(&b"[package]"[..]));
+/// # Ok(())
+/// # }
+/// ```
+pub struct MemoryMap {
+  allocation: Allocation,
+  protection: Protection,
+  size: usize,
+}
+
+impl MemoryMap {
+  /// Maps the beginning of a file into memory, with a defined protection.
+  ///
+  /// See [`map_file`] for more information.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If size is zero, or exceeds the size of the file,
+  ///   [`Error::InvalidParameter`] will be returned.
+  /// - If the file is write-only, or the protection is not permitted with the
+  ///   [`ProtectionPolicy::Strict`] policy, [`Error::InvalidParameter`] will be
+  ///   returned.
+  ///
+  /// # Safety
+  ///
+  /// The mapping is shared with the underlying file. If the file is modified
+  /// (e.g. truncated) by this or another process whilst mapped, the slice's
+  /// contents may change, or accessing it may terminate the process.
+  #[inline]
+  pub unsafe fn new(
+    file: &File,
+    size: usize,
+    protection: Protection,
+    policy: ProtectionPolicy,
+  ) -> Result<Self> {
+    let length = file.metadata().map_err(Error::SystemCall)?.len();
+    if size as u64 > length {
+      return Err(Error::InvalidParameter("size"));
+    }
+
+    map_file(file, size, protection, policy).map(|(allocation, protection)| Self {
+      allocation,
+      protection,
+      size,
+    })
+  }
+
+  /// Returns the effective protection of the mapping.
+  #[inline(always)]
+  pub fn protection(&self) -> Protection {
+    self.protection
+  }
+
+  /// Returns the mapping as a slice, if it's readable.
+  #[inline]
+  pub fn as_slice(&self) -> Option<&[u8]> {
+    if !self.protection.contains(Protection::READ) {
+      return None;
+    }
+
+    Some(unsafe { std::slice::from_raw_parts(self.allocation.as_ptr(), self.size) })
+  }
+
+  /// Returns the mapping as a mutable slice, if it's readable and writable.
+  #[inline]
+  pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+    if !self.protection.contains(Protection::READ_WRITE) {
+      return None;
+    }
+
+    Some(unsafe { std::slice::from_raw_parts_mut(self.allocation.as_mut_ptr(), self.size) })
+  }
+
+  /// Returns the underlying allocation, whose size is rounded up to the
+  /// closest page boundary.
+  #[inline(always)]
+  pub fn as_allocation(&self) -> &Allocation {
+    &self.allocation
+  }
+}
+
+unsafe impl Send for MemoryMap {}
+unsafe impl Sync for MemoryMap {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs::OpenOptions;
+  use std::io::{Read, Seek, SeekFrom, Write};
+  use std::path::PathBuf;
+
+  const CONTENTS: &[u8] = b"region-rs";
+
+  struct TempFile(PathBuf);
+
+  impl TempFile {
+    fn new(name: &str) -> Self {
+      let path = std::env::temp_dir().join(format!("region-{}-{}", std::process::id(), name));
+      File::create(&path)
+        .and_then(|mut file| file.write_all(CONTENTS))
+        .expect("creating file");
+      Self(path)
+    }
+
+    fn open(&self, write: bool) -> File {
+      OpenOptions::new()
+        .read(true)
+        .write(write)
+        .open(&self.0)
+        .expect("opening file")
+    }
+  }
+
+  impl Drop for TempFile {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_file(&self.0);
+    }
+  }
+
+  #[test]
+  fn file_mapping_max_protection_reflects_open_mode() -> Result<()> {
+    let file = TempFile::new("max-protection");
+
+    let protection = file_mapping_max_protection(&file.open(false))?;
+    assert!(!protection.contains(Protection::WRITE));
+    assert!(protection.contains(Protection::READ));
+
+    let protection = file_mapping_max_protection(&file.open(true))?;
+    assert_eq!(protection, Protection::READ_WRITE);
+
+    let write_only = OpenOptions::new()
+      .write(true)
+      .open(&file.0)
+      .expect("opening file");
+    let result = file_mapping_max_protection(&write_only);
+    assert!(matches!(result, Err(Error::InvalidParameter("file"))));
+    Ok(())
+  }
+
+  #[test]
+  fn map_file_strict_rejects_unpermitted_protection() {
+    let file = TempFile::new("strict");
+    let result = unsafe {
+      map_file(
+        &file.open(false),
+        CONTENTS.len(),
+        Protection::READ_WRITE,
+        ProtectionPolicy::Strict,
+      )
+    };
+    assert!(matches!(result, Err(Error::InvalidParameter("protection"))));
+  }
+
+  #[test]
+  fn map_file_best_effort_downgrades_protection() -> Result<()> {
+    let file = TempFile::new("best-effort");
+    let (map, protection) = unsafe {
+      map_file(
+        &file.open(false),
+        CONTENTS.len(),
+        Protection::READ_WRITE,
+        ProtectionPolicy::BestEffort,
+      )?
+    };
+
+    let data = unsafe { std::slice::from_raw_parts(map.as_ptr::<u8>(), CONTENTS.len()) };
+    assert_eq!(protection, Protection::READ);
+    assert_eq!(
+      crate::query(map.as_ptr::<()>())?.protection(),
+      Protection::READ
+    );
+    assert_eq!(data, CONTENTS);
+    Ok(())
+  }
+
+  #[test]
+  fn memory_map_reads_through_slice() -> Result<()> {
+    let file = TempFile::new("map-read");
+    let handle = file.open(false);
+    let map = unsafe {
+      MemoryMap::new(
+        &handle,
+        CONTENTS.len(),
+        Protection::READ,
+        ProtectionPolicy::Strict,
+      )?
+    };
+
+    assert_eq!(map.as_slice(), Some(CONTENTS));
+    assert!(map.as_allocation().len() >= CONTENTS.len());
+    Ok(())
+  }
+
+  #[test]
+  fn memory_map_writes_through_slice() -> Result<()> {
+    let file = TempFile::new("map-write");
+    let handle = file.open(true);
+    let mut map = unsafe {
+      MemoryMap::new(
+        &handle,
+        CONTENTS.len(),
+        Protection::READ_WRITE,
+        ProtectionPolicy::Strict,
+      )?
+    };
+
+    map.as_mut_slice().expect("writable map")[..6].copy_from_slice(b"REGION");
+    drop(map);
+    assert_eq!(std::fs::read(&file.0).expect("reading file"), b"REGION-rs");
+    Ok(())
+  }
+
+  #[test]
+  fn memory_map_checks_protection_and_size() -> Result<()> {
+    let file = TempFile::new("map-none");
+    let handle = file.open(false);
+    let mut map = unsafe {
+      MemoryMap::new(
+        &handle,
+        CONTENTS.len(),
+        Protection::READ,
+        ProtectionPolicy::BestEffort,
+      )?
+    };
+
+    assert!(map.as_slice().is_some());
+    assert!(map.as_mut_slice().is_none());
+    assert!(matches!(
+      unsafe {
+        MemoryMap::new(
+          &handle,
+          CONTENTS.len() + 1,
+          Protection::READ,
+          ProtectionPolicy::Strict,
+        )
+      },
+      Err(Error::InvalidParameter("size"))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn memory_map_flushes_to_file() -> Result<()> {
+    let file = TempFile::new("map-flush");
+    let handle = file.open(true);
+    let mut map = unsafe {
+      MemoryMap::new(
+        &handle,
+        CONTENTS.len(),
+        Protection::READ_WRITE,
+        ProtectionPolicy::Strict,
+      )?
+    };
+
+    map.as_mut_slice().expect("writable map")[0] = b'R';
+    map.as_allocation().flush(None)?;
+    map.as_allocation().flush_async(Some(0..1))?;
+    assert_eq!(std::fs::read(&file.0).expect("reading file"), b"Region-rs");
+
+    let out_of_bounds = map.as_allocation().len() + 1;
+    assert!(matches!(
+      map.as_allocation().flush(Some(0..out_of_bounds)),
+      Err(Error::InvalidParameter("range"))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn map_file_writes_through_to_file() -> Result<()> {
+    let file = TempFile::new("write");
+    let mut handle = file.open(true);
+    let (map, protection) = unsafe {
+      map_file(
+        &handle,
+        CONTENTS.len(),
+        Protection::READ_WRITE,
+        ProtectionPolicy::Strict,
+      )?
+    };
+
+    assert_eq!(protection, Protection::READ_WRITE);
+    unsafe { *(map.as_ptr::<u8>() as *mut u8) = b'R' };
+    drop(map);
+
+    let mut contents = Vec::new();
+    handle.seek(SeekFrom::Start(0)).expect("seeking file");
+    handle.read_to_end(&mut contents).expect("reading file");
+    assert_eq!(contents, b"Region-rs");
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(feature = "async", not(target_os = "haiku")))]
+  fn prefetch_async_makes_mapping_resident() -> Result<()> {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+      fn wake(self: Arc<Self>) {
+        self.0.unpark();
+      }
+    }
+
+    let file = TempFile::new("prefetch");
+    let (map, _) = unsafe {
+      map_file(
+        &file.open(false),
+        CONTENTS.len(),
+        Protection::READ,
+        ProtectionPolicy::Strict,
+      )?
+    };
+
+    // A minimal executor, so that no particular runtime is required
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+      let mut future = Box::pin(future);
+      let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+      loop {
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+          Poll::Ready(result) => break result,
+          Poll::Pending => std::thread::park(),
+        }
+      }
+    }
+
+    block_on(map.prefetch_async(None))?;
+    assert!(crate::is_resident(map.as_ptr::<u8>(), map.len())?);
+
+    let result = block_on(map.prefetch_async(Some(0..map.len() + 1)));
+    assert!(matches!(result, Err(Error::InvalidParameter("range"))));
+    Ok(())
+  }
+}
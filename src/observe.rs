@@ -0,0 +1,138 @@
+use crate::{Protection, Result};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+type Observer = Arc<dyn Fn(ProtectPhase, Range<usize>, Protection) + Send + Sync>;
+
+static OBSERVER: RwLock<Option<Observer>> = RwLock::new(None);
+
+// Whether an observer is set, so that the lock is only acquired once it is
+static OBSERVED: AtomicBool = AtomicBool::new(false);
+
+/// The phase of a protection change, reported to the observer set by
+/// [`set_protect_observer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectPhase {
+  /// The protection is about to be changed.
+  Before,
+  /// The protection change has been attempted, whether it succeeded or not.
+  After,
+}
+
+/// Sets an observer that is invoked before and after each protection change.
+///
+/// The observer receives the phase, the page-aligned address range, and the
+/// protection being applied. It's invoked by [`protect`](crate::protect),
+/// [`protect_preserve_flags`](crate::protect_preserve_flags), and
+/// [`Allocation::protect`](crate::Allocation::protect), including the calls
+/// made on their behalf (e.g. when a [`ProtectGuard`](crate::ProtectGuard) is
+/// dropped). This allows sanitizer shims (e.g. for ASan or Valgrind) to keep
+/// their shadow memory in sync.
+///
+/// Any previously set observer is replaced. The observer is invoked on the
+/// thread that is changing the protection, and it must not change any
+/// protection itself, since it would be invoked recursively.
+///
+/// # Examples
+///
+/// ```
+/// use region::ProtectPhase;
+///
+/// region::set_protect_observer(|phase, range, protection| {
+///   if phase == ProtectPhase::After {
+///     eprintln!("{:x?} is now {}", range, protection);
+///   }
+/// });
+/// # region::clear_protect_observer();
+/// ```
+#[inline]
+pub fn set_protect_observer(
+  observer: impl Fn(ProtectPhase, Range<usize>, Protection) + Send + Sync + 'static,
+) {
+  let mut current = OBSERVER.write().unwrap_or_else(|error| error.into_inner());
+  *current = Some(Arc::new(observer));
+  OBSERVED.store(true, Ordering::Release);
+}
+
+/// Removes the observer set by [`set_protect_observer`], if any.
+#[inline]
+pub fn clear_protect_observer() {
+  let mut current = OBSERVER.write().unwrap_or_else(|error| error.into_inner());
+  current.take();
+  OBSERVED.store(false, Ordering::Release);
+}
+
+/// Invokes a protection change, notifying the observer before and after it.
+pub(crate) fn protect(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+  change: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+  if !OBSERVED.load(Ordering::Acquire) {
+    return change();
+  }
+
+  // The lock is released before invoking the observer, so that it may query
+  let observer = OBSERVER
+    .read()
+    .unwrap_or_else(|error| error.into_inner())
+    .clone();
+
+  let observer = match observer {
+    Some(observer) => observer,
+    None => return change(),
+  };
+
+  let range = base as usize..(base as usize).saturating_add(size);
+  observer(ProtectPhase::Before, range.clone(), protection);
+  let result = change();
+  observer(ProtectPhase::After, range, protection);
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::page;
+  use std::sync::Mutex;
+
+  #[test]
+  fn protect_observer_is_invoked_around_protection_change() -> Result<()> {
+    let memory = crate::alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let range = memory.as_range();
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    // Other tests may change protections concurrently, hence events are filtered
+    let (recorded, expected) = (events.clone(), range.clone());
+    set_protect_observer(move |phase, range, protection| {
+      if range.start >= expected.start && range.end <= expected.end {
+        recorded.lock().unwrap().push((phase, range, protection));
+      }
+    });
+
+    let result = unsafe {
+      crate::protect(memory.as_ptr::<u8>(), 1, Protection::READ)
+        .and_then(|_| memory.protect(Protection::NONE))
+    };
+    clear_protect_observer();
+    result?;
+
+    let first = range.start..range.start + page::size();
+    assert_eq!(
+      *events.lock().unwrap(),
+      vec![
+        (ProtectPhase::Before, first.clone(), Protection::READ),
+        (ProtectPhase::After, first, Protection::READ),
+        (ProtectPhase::Before, range.clone(), Protection::NONE),
+        (ProtectPhase::After, range, Protection::NONE),
+      ]
+    );
+
+    // Once cleared, the observer is no longer invoked
+    unsafe { memory.protect(Protection::READ) }?;
+    assert_eq!(events.lock().unwrap().len(), 4);
+    Ok(())
+  }
+}
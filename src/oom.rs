@@ -0,0 +1,116 @@
+use crate::{os, Result};
+use std::sync::{Arc, RwLock};
+
+type Hook = Arc<dyn Fn() -> bool + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Sets a hook that is invoked when an allocation runs out of memory.
+///
+/// Once an allocation fails due to insufficient memory (e.g. `ENOMEM` on
+/// POSIX, or `B_NO_MEMORY` on Haiku), the hook is invoked. If it returns
+/// `true` (i.e. memory has been reclaimed, such as by trimming caches), the
+/// allocation is retried once. Otherwise the original error is returned.
+///
+/// Any previously set hook is replaced. The hook is invoked on the thread that
+/// is allocating, and it may allocate memory itself. Should an allocation
+/// within the hook run out of memory, the hook is invoked recursively.
+///
+/// # Examples
+///
+/// ```
+/// region::set_oom_hook(|| {
+///   // Release any cached memory here
+///   false
+/// });
+/// # region::clear_oom_hook();
+/// ```
+#[inline]
+pub fn set_oom_hook(hook: impl Fn() -> bool + Send + Sync + 'static) {
+  *HOOK.write().unwrap_or_else(|error| error.into_inner()) = Some(Arc::new(hook));
+}
+
+/// Removes the hook set by [`set_oom_hook`], if any.
+#[inline]
+pub fn clear_oom_hook() {
+  HOOK
+    .write()
+    .unwrap_or_else(|error| error.into_inner())
+    .take();
+}
+
+/// Invokes an allocation, retrying it once if it runs out of memory and the
+/// out-of-memory hook has reclaimed memory.
+pub(crate) fn retry<T>(mut allocate: impl FnMut() -> Result<T>) -> Result<T> {
+  match allocate() {
+    Err(error) if os::is_out_of_memory(&error) => {
+      // The lock is released before invoking the hook, so that it may allocate
+      let hook = HOOK
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+        .clone();
+
+      match hook {
+        Some(hook) if hook() => allocate(),
+        _ => Err(error),
+      }
+    }
+    result => result,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{Error, Protection};
+  use std::cell::RefCell;
+
+  thread_local! {
+    static CACHE: RefCell<Option<crate::Allocation>> = const { RefCell::new(None) };
+  }
+
+  #[test]
+  fn oom_hook_allows_retry_after_reclaiming_memory() -> Result<()> {
+    let cached = crate::alloc(1, Protection::READ_WRITE)?;
+    CACHE.with(|cache| *cache.borrow_mut() = Some(cached));
+
+    // The hook runs on the allocating thread, hence it releases this thread's cache
+    set_oom_hook(|| CACHE.with(|cache| cache.borrow_mut().take().is_some()));
+
+    let mut attempts = 0;
+    let memory = retry(|| {
+      attempts += 1;
+      if CACHE.with(|cache| cache.borrow().is_some()) {
+        // Simulate running out of memory until the cache has been released
+        return Err(out_of_memory());
+      }
+      crate::alloc(1, Protection::READ_WRITE)
+    });
+
+    assert!(memory.is_ok());
+    assert_eq!(attempts, 2);
+    assert!(retry(|| Err::<(), _>(out_of_memory())).is_err());
+    assert!(matches!(
+      retry(|| Err::<(), _>(Error::UnmappedRegion)),
+      Err(Error::UnmappedRegion)
+    ));
+    clear_oom_hook();
+    Ok(())
+  }
+
+  fn out_of_memory() -> Error {
+    #[cfg(all(unix, not(target_os = "haiku")))]
+    return Error::SystemCall(std::io::Error::from_raw_os_error(libc::ENOMEM));
+
+    #[cfg(windows)]
+    return Error::SystemCall(std::io::Error::from_raw_os_error(
+      winapi::shared::winerror::ERROR_NOT_ENOUGH_MEMORY as i32,
+    ));
+
+    #[cfg(target_os = "haiku")]
+    return Error::Os {
+      code: libc::B_NO_MEMORY,
+      message: "No memory".to_string(),
+    };
+  }
+}
@@ -1,13 +1,16 @@
 use crate::{Error, Protection, Region, Result, page, util};
-use libc::{c_uint, c_void, area_info, area_id, get_area_info, get_next_area_info,
+use libc::{c_uint, c_void, area_info, area_id, area_for, get_area_info, get_next_area_info,
   set_area_protection, create_area, delete_area,
   B_WRITE_AREA, B_READ_AREA, B_EXECUTE_AREA, B_BAD_VALUE, B_OK, B_PAGE_SIZE,
-  B_ANY_ADDRESS, B_EXACT_ADDRESS, B_NO_LOCK, B_NO_MEMORY, B_BAD_ADDRESS };
+  B_ANY_ADDRESS, B_EXACT_ADDRESS, B_NO_LOCK, B_LAZY_LOCK, B_FULL_LOCK, B_CONTIGUOUS, B_LOMEM,
+  B_NO_MEMORY, B_BAD_ADDRESS, B_NOT_ALLOWED,
+  status_t };
 use std::io;
-use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use std::sync::atomic::AtomicPtr;
+use std::sync::atomic::{AtomicI32, Ordering};
+#[cfg(feature = "registry")]
 use dashmap::DashMap;
+#[cfg(feature = "registry")]
 use lazy_static::lazy_static;
 
 // alloc.rs is incompatible with Haiku because of Protection::NONE and must be
@@ -21,26 +24,83 @@ use lazy_static::lazy_static;
 /// This handle does not dereference to a slice, since the underlying memory may
 /// have been created with [`Protection::NONE`].
 #[derive(Clone)]
-pub struct Allocation(Arc<area_id>);
+pub struct Allocation(Arc<AtomicI32>);
 
-struct KeyType(Arc<AtomicPtr<()> >);
+// The area ID of an allocation that has been freed explicitly, shared by all
+//   of its clones, so that none of them deletes the area (or any area that
+//   reuses its ID) once again.
+const CONSUMED: area_id = -1;
 
-impl PartialEq for KeyType {
-  fn eq(&self, other: &Self) -> bool {
-    Arc::as_ptr(&self.0) == Arc::as_ptr(&other.0)
+// The key of a registered allocation. Only the value of its base address is
+//   stored, which (unlike a pointer) is `Send` and `Sync`, and it is hashed and
+//   compared without allocating.
+#[cfg(feature = "registry")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct KeyType(usize);
+
+#[cfg(feature = "registry")]
+impl KeyType {
+  fn new(address: *const ()) -> Self {
+    KeyType(address as usize)
   }
 }
 
-impl Eq for KeyType {}
+// The registry of all allocations created by the crate, keyed by their base
+//   address. Every allocation and deallocation has to synchronize on it, so it
+//   can be disabled (by opting out of the `registry` feature) in favor of
+//   asking the kernel for the area of an address.
+#[cfg(feature = "registry")]
+lazy_static! {
+  static ref ALLPAGES: DashMap<KeyType, area_id> = DashMap::new();
+}
 
-impl Hash for KeyType {
-  fn hash<H: Hasher>(&self, state: &mut H) {
-  	Arc::as_ptr(&self.0).hash(state)
-  }
+/// Registers an allocation's area by its base address.
+///
+/// Only the area ID is stored, since a registered [`Allocation`] would delete
+/// the area once it is evicted from the registry.
+#[cfg(feature = "registry")]
+fn register(address: *const (), id: area_id) {
+  let addy = KeyType::new(address);
+  ALLPAGES.insert(addy, id);
 }
 
-lazy_static! {
-  static ref ALLPAGES: DashMap<KeyType, Allocation> = DashMap::new();
+#[cfg(not(feature = "registry"))]
+fn register(_address: *const (), _id: area_id) {}
+
+/// Removes an allocation from the registry by its base address.
+#[cfg(feature = "registry")]
+fn unregister(address: *const ()) {
+  let addy = KeyType::new(address);
+  ALLPAGES.remove(&addy);
+}
+
+#[cfg(not(feature = "registry"))]
+fn unregister(_address: *const ()) {}
+
+/// Looks up the area of an allocation by its base address.
+#[cfg(feature = "registry")]
+fn area_of(base: *const ()) -> Option<area_id> {
+  let addy = KeyType::new(base);
+  ALLPAGES.get(&addy).map(|id| *id)
+}
+
+/// Looks up the area containing an address, without any global state.
+#[cfg(not(feature = "registry"))]
+fn area_of(base: *const ()) -> Option<area_id> {
+  let id = unsafe { area_for(base as *mut c_void) };
+  if id < B_OK { None } else { Some(id) }
+}
+
+/// Returns the address ranges of all registered allocations.
+#[cfg(feature = "registry")]
+pub fn owned_allocations() -> Vec<std::ops::Range<usize>> {
+  ALLPAGES.iter().filter_map(|entry| {
+    let mut info: area_info = unsafe { std::mem::zeroed() };
+    match unsafe { get_area_info(*entry.value(), &mut info) } {
+      B_OK => Some(info.address as usize..info.address as usize + info.size),
+      _ => None
+    }
+  }).collect()
 }
 
 impl Protection {
@@ -72,34 +132,135 @@ impl Protection {
 }
 
 pub unsafe fn protect(base: *const (), _size: usize, protection: Protection) -> Result<()> {
-  let addy = KeyType(Arc::new(AtomicPtr::new(base as *mut () )));
-  match ALLPAGES.get(&addy) {
-    Some(alloc) => match alloc.refresh_info() {
-      Ok (info) => {
-        if set_area_protection(info.area, protection.to_native()) < B_OK {
-          Err(Error::InvalidParameter("bad value"))
-        } else {
-          Ok(())
-        }
-      },
-      Err(e) => Err(e)
+  match area_of(base) {
+    Some(id) => match set_area_protection(id, protection.to_native()) {
+      status if status < B_OK => Err(os_error(status)),
+      _ => Ok(())
     },
     None => Err(Error::UnmappedRegion)
   }
 }
 
+pub fn protection_at(address: *const ()) -> Result<Protection> {
+  let id = unsafe { area_for(address as *mut c_void) };
+  if id < B_OK {
+    return Err(Error::UnmappedRegion);
+  }
+
+  let mut info: area_info = unsafe { std::mem::zeroed() };
+  match unsafe { get_area_info(id, &mut info) } {
+    B_OK => Ok(Protection::from_native(info.protection)),
+    _ => Err(Error::UnmappedRegion)
+  }
+}
+
+pub fn flush_icache(base: *const (), size: usize) {
+  unsafe { libc::clear_caches(base as *mut c_void, size, libc::B_FLUSH_DCACHE | libc::B_INVALIDATE_ICACHE) }
+}
+
+pub fn is_resident(base: *const (), size: usize) -> Result<bool> {
+  let id = unsafe { area_for(base as *mut c_void) };
+  if id < B_OK {
+    return Err(Error::UnmappedRegion);
+  }
+
+  // Haiku only reports residency for an area as a whole
+  let mut info: area_info = unsafe { std::mem::zeroed() };
+  match unsafe { get_area_info(id, &mut info) } {
+    B_OK => Ok(info.ram_size as usize >= size.min(info.size)),
+    _ => Err(Error::UnmappedRegion)
+  }
+}
+
+pub fn resident_size() -> Result<usize> {
+  let mut cookie = 0;
+  let mut info: area_info = unsafe { std::mem::zeroed() };
+  let mut total = 0usize;
+
+  while unsafe { get_next_area_info(0, &mut cookie, &mut info) } == B_OK {
+    total = total.saturating_add(info.ram_size as usize);
+  }
+  Ok(total)
+}
+
+pub unsafe fn protect_preserve_flags(base: *const (), size: usize, protection: Protection) -> Result<()> {
+  // Haiku areas have no page modifiers beyond their protection
+  protect(base, size, protection)
+}
+
+/// Converts a `status_t` into an error, preserving the original code.
+fn os_error(status: status_t) -> Error {
+  let message = unsafe { std::ffi::CStr::from_ptr(libc::strerror(status)) };
+  Error::Os {
+    code: status,
+    message: message.to_string_lossy().into_owned(),
+  }
+}
+
+#[inline]
+pub fn is_out_of_memory(error: &Error) -> bool {
+  error.raw_os_error() == Some(B_NO_MEMORY)
+}
+
+/// Converts a failed `create_area` status into an error.
+///
+/// Haiku has no dedicated status for an exhausted area table; it is reported
+/// as `B_NO_MEMORY`, like any other resource shortage. The codes that stem
+/// from the caller's input are reported as invalid parameters, whilst all
+/// other codes are preserved as-is.
+fn create_area_error(status: status_t) -> Error {
+  match status {
+    B_BAD_ADDRESS => Error::InvalidParameter("bad address"),
+    B_BAD_VALUE => Error::InvalidParameter("bad value"),
+    B_NOT_ALLOWED => Error::InvalidParameter("protection"),
+    _ => os_error(status)
+  }
+}
+
 #[inline(always)]
 pub fn page_size() -> usize {
   return B_PAGE_SIZE;
 }
 
+pub fn current_thread_stack() -> Option<std::ops::Range<usize>> {
+  let mut attr = std::mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+  let (mut base, mut size) = (std::ptr::null_mut(), 0);
+
+  unsafe {
+    if libc::pthread_getattr_np(libc::pthread_self(), attr.as_mut_ptr()) != 0 {
+      return None;
+    }
+    let result = libc::pthread_attr_getstack(attr.as_ptr(), &mut base, &mut size);
+    libc::pthread_attr_destroy(attr.as_mut_ptr());
+    if result == 0 { Some(base as usize..base as usize + size) } else { None }
+  }
+}
+
+pub fn address_space_bounds() -> std::ops::Range<usize> {
+  // The highest area (typically the main thread's stack) bounds the team
+  page_size()..util::highest_mapped_address()
+}
+
+
+// A run of physical memory backing part of an area, as reported by
+//   `get_memory_map` (which the libc crate does not expose).
+#[repr(C)]
+struct PhysicalEntry {
+  address: u64,
+  size: u64,
+}
+
+extern "C" {
+  fn get_memory_map(address: *const c_void, size: usize, table: *mut PhysicalEntry,
+    entries: i32) -> status_t;
+}
 
 impl Allocation {
   // private helper function
   #[inline(always)]
   fn refresh_info(&self) -> Result<area_info> {
     let mut info = area_info {
-      area: *(self.0),
+      area: self.id(),
       address: std::ptr::null_mut() as *mut c_void,
       size: 0,
       name: [0; 32],
@@ -112,6 +273,10 @@ impl Allocation {
       team: 0
   	};
   	
+  	if info.area == CONSUMED {
+      return Err(Error::UnmappedRegion);
+    }
+
   	match unsafe { get_area_info(info.area, &mut info) } {
       B_OK => Ok(info),
       _ => Err(Error::UnmappedRegion)
@@ -120,7 +285,12 @@ impl Allocation {
 
   #[inline(always)]
   fn new(my_id: area_id) -> Result<Allocation> {
-    Ok(Allocation(Arc::<area_id>::new(my_id)))
+    Ok(Allocation(Arc::new(AtomicI32::new(my_id))))
+  }
+
+  #[inline(always)]
+  fn id(&self) -> area_id {
+    self.0.load(Ordering::Acquire)
   }
   
   /// Returns a pointer to the allocation's base address.
@@ -143,6 +313,47 @@ impl Allocation {
   	}
   }
   
+  /// Returns the physical address of the allocation's first page.
+  ///
+  /// This is intended for physically contiguous allocations (i.e. those
+  /// created with [`HaikuLock::Contiguous`] or [`HaikuLock::LoMem`]).
+  ///
+  /// # Implementation
+  ///
+  /// This function is implemented using `get_memory_map`.
+  ///
+  /// # Errors
+  ///
+  /// - If the allocation is not backed by a single run of physical memory,
+  ///   [`Error::InvalidParameter`] will be returned.
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  pub fn physical_address(&self) -> Result<u64> {
+    let info = self.refresh_info()?;
+
+    // A second entry is only filled in if the memory is not contiguous
+    let mut table = [PhysicalEntry { address: 0, size: 0 }, PhysicalEntry { address: 0, size: 0 }];
+    let status = unsafe { get_memory_map(info.address, info.size, table.as_mut_ptr(), 2) };
+    if status < B_OK {
+      return Err(os_error(status));
+    }
+
+    if table[0].size < info.size as u64 {
+      Err(Error::InvalidParameter("allocation"))
+    } else {
+      Ok(table[0].address)
+    }
+  }
+
+  /// Returns a non-null pointer to the allocation's base address.
+  ///
+  /// This is useful for FFI, since an allocation's base is never null.
+  #[inline(always)]
+  pub fn as_non_null<T>(&self) -> std::ptr::NonNull<T> {
+    // SAFETY: the kernel never creates an area at the null address
+    unsafe { std::ptr::NonNull::new_unchecked(self.as_mut_ptr()) }
+  }
+
   /// Returns two raw pointers spanning the allocation's address space.
   ///
   /// The returned range is half-open, which means that the end pointer points
@@ -162,6 +373,16 @@ impl Allocation {
   	(range.start as *mut T)..(range.end as *mut T)
   }
   
+  /// Returns two non-null pointers spanning the allocation's address space.
+  #[inline(always)]
+  pub fn as_non_null_ptr_range<T>(&self) -> std::ops::Range<std::ptr::NonNull<T>> {
+    let range = self.as_mut_ptr_range::<T>();
+    // SAFETY: neither the base nor the (saturated) end of an area is null
+    unsafe {
+      std::ptr::NonNull::new_unchecked(range.start)..std::ptr::NonNull::new_unchecked(range.end)
+    }
+  }
+
   /// Returns a range spanning the allocation's address space.
   #[inline(always)]
   pub fn as_range<T>(&self) -> std::ops::Range<usize> {
@@ -185,19 +406,166 @@ impl Allocation {
   	  _ => 0 // Is returning 0 length right for an UnmappedRegion error?
   	}
   }
+
+  /// Returns an iterator over the base address of each page in the
+  /// allocation.
+  ///
+  /// This is useful for per-page operations, such as selectively locking or
+  /// protecting individual pages of an allocation.
+  #[inline]
+  pub fn pages(&self) -> impl Iterator<Item = *const ()> {
+    self.as_range::<()>().step_by(page::size()).map(|page| page as *const ())
+  }
+
+  /// Returns a view of the allocation as chunks of `N` bytes, along with the
+  /// remainder that does not fill an entire chunk.
+  ///
+  /// The allocation is queried beforehand, to verify that it's readable.
+  ///
+  /// # Errors
+  ///
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  /// - If `N` is zero, or the allocation is not readable (e.g.
+  ///   [`Protection::NONE`]), [`Error::InvalidParameter`] will be returned.
+  #[inline]
+  pub fn as_chunks<const N: usize>(&self) -> Result<(&[[u8; N]], &[u8])> {
+    if N == 0 {
+      return Err(Error::InvalidParameter("N"));
+    }
+
+    let (data, size) = (self.as_ptr::<u8>(), self.len());
+    util::ensure_readable(data, size)?;
+
+    let chunks = size / N;
+    unsafe {
+      Ok((
+        std::slice::from_raw_parts(data.cast::<[u8; N]>(), chunks),
+        std::slice::from_raw_parts(data.add(chunks * N), size % N),
+      ))
+    }
+  }
+
+  /// Changes the memory protection of the entire allocation.
+  ///
+  /// The allocation's own area is used directly, so (unlike
+  /// [`protect`](crate::protect)) this never consults the global registry.
+  ///
+  /// # Safety
+  ///
+  /// See [`protect`](crate::protect).
+  #[inline]
+  pub unsafe fn protect(&self, protection: Protection) -> Result<()> {
+    crate::observe::protect(self.as_ptr(), self.len(), protection, || {
+      match set_area_protection(self.id(), protection.to_native()) {
+        status if status < B_OK => Err(os_error(status)),
+        _ => Ok(())
+      }
+    })
+  }
+
+  /// Frees the allocation, reporting whether it succeeded.
+  ///
+  /// Dropping an allocation frees it as well, but any failure is silently
+  /// ignored (in release builds). Once freed, the area is removed from the
+  /// registry and marked as consumed, so neither this allocation nor any of
+  /// its clones deletes it again.
+  ///
+  /// # Errors
+  ///
+  /// - If the area no longer exists, [`Error::UnmappedRegion`] will be
+  ///   returned.
+  /// - If an interaction with the underlying operating system fails, an error
+  ///   will be returned.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// # fn main() -> region::Result<()> {
+  /// use region::Protection;
+  ///
+  /// let memory = region::alloc(1, Protection::READ_WRITE)?;
+  /// memory.free()?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  #[inline]
+  pub fn free(self) -> Result<()> {
+    let info = self.refresh_info()?;
+    unregister(info.address as *const ());
+
+    match unsafe { delete_area(info.area) } {
+      B_OK => {
+        self.0.store(CONSUMED, Ordering::Release);
+        Ok(())
+      }
+      status => Err(os_error(status))
+    }
+  }
 }
 
 impl Drop for Allocation {
   #[inline]
   fn drop(&mut self) {
-    match self.refresh_info() {
-      Ok(inner) => {
-        let addy = KeyType(Arc::new(AtomicPtr::new(inner.address as *mut () )));
-        ALLPAGES.remove(&addy);
-        let result = unsafe { delete_area(inner.area) };
-        debug_assert!(result == B_OK, "freeing region: B_BAD_ADDRESS");
-  	  },
-  	  _ => panic!("refresh_info() failed during a Drop")
+    // The area may have been deleted externally, in which case there is
+    // nothing left to free. Panicking here would abort the process if the drop
+    // occurs during unwinding, so the deletion is simply skipped.
+    let inner = match self.refresh_info() {
+      Ok(inner) => inner,
+      Err(error) => {
+        debug_assert!(
+          matches!(error, Error::UnmappedRegion) || std::thread::panicking(),
+          "refreshing area info: {:?}",
+          error
+        );
+        return;
+      }
+    };
+
+    unregister(inner.address as *const ());
+    let result = unsafe { delete_area(inner.area) };
+    debug_assert!(
+      result == B_OK || std::thread::panicking(),
+      "freeing region: {}",
+      result
+    );
+  }
+}
+
+#[cfg(test)]
+thread_local! {
+  static FAIL_AFTER_CREATE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+  static LAST_CREATED: std::cell::Cell<area_id> = const { std::cell::Cell::new(0) };
+}
+
+/// Takes ownership of a newly created area, and registers it.
+///
+/// If anything fails after the area has been created, the area is deleted
+/// before the error is returned, so that a failed allocation never leaks it.
+fn adopt_area(id: area_id) -> Result<Allocation> {
+  let allocation = Allocation::new(id)?;
+
+  #[cfg(test)]
+  LAST_CREATED.with(|last| last.set(id));
+
+  let result = allocation.refresh_info().and_then(|info| {
+    #[cfg(test)]
+    if FAIL_AFTER_CREATE.with(|fail| fail.replace(false)) {
+      return Err(Error::UnmappedRegion);
+    }
+    Ok(info)
+  });
+
+  match result {
+    Ok(info) => {
+      register(info.address as *const (), info.area);
+      Ok(allocation)
+    }
+    Err(error) => {
+      // Dropping the allocation skips areas that cannot be queried, therefore
+      // the area is deleted explicitly (this is a no-op if it's already gone).
+      unsafe { delete_area(id) };
+      Err(error)
     }
   }
 }
@@ -216,7 +584,7 @@ impl Drop for Allocation {
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
+///   will be returned.
 /// - If size is zero, [`Error::InvalidParameter`] will be returned.
 ///
 /// # Examples
@@ -241,38 +609,7 @@ impl Drop for Allocation {
 /// # }
 /// ```
 pub fn alloc(size: usize, protection: Protection) -> Result<Allocation> {
-  if size == 0 {
-    return Err(Error::InvalidParameter("size"));
-  }
-  let size = page::ceil(size as *const ()) as usize;
-
-  let address = std::ptr::NonNull::<c_void>::dangling().as_ptr();
-  let status = unsafe { create_area(b"region" as *const u8 as *const i8,
-    &address as *const *mut c_void as *mut *mut c_void,
-    B_ANY_ADDRESS, size, B_NO_LOCK, protection.to_native()) };
-  if status < B_OK {
-    match status {
-      B_BAD_ADDRESS => Err(Error::InvalidParameter("bad address")),
-      B_BAD_VALUE => Err(Error::InvalidParameter("bad value")),
-      B_NO_MEMORY => Err(Error::SystemCall(io::Error::new(io::ErrorKind::OutOfMemory, "allocation failed"))),
-      _ => Err(Error::SystemCall(io::Error::new(io::ErrorKind::Other, "General Error")))
-  	}
-  } else {
-  // allocation succeeded
-    match Allocation::new(status) {
-      Ok(inner) => {
-        match inner.refresh_info() {
-          Ok(a) => {
-            let addy = KeyType(Arc::new(AtomicPtr::new(a.address as *mut () )));
-            ALLPAGES.insert(addy, inner.clone());
-            return Ok( inner );
-          },
-         Err(e) => Err(e)
-       }
-      },
-      Err(e) => Err(e)
-    }
-  }
+  AllocOptions::new().alloc(size, protection)
 }
 
 /// Allocates one or more pages of memory, at a specific address, with a defined
@@ -298,37 +635,158 @@ pub fn alloc(size: usize, protection: Protection) -> Result<Allocation> {
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
+///   will be returned.
 /// - If size is zero, [`Error::InvalidParameter`] will be returned.
 pub fn alloc_at<T>(address: *const T, size: usize, protection: Protection) -> Result<Allocation> {
-  let (address, size) = util::round_to_page_boundaries(address, size)?;
-
-  let status = unsafe { create_area(b"region" as *const u8 as *const i8, 
-      &address as &*const T as *const *const T as *mut *mut T as *mut *mut c_void,
-      B_EXACT_ADDRESS, size, B_NO_LOCK, protection.to_native()) };
-  if status < B_OK {
-    match status {
-      B_BAD_ADDRESS => Err(Error::InvalidParameter("bad address")),
-      B_BAD_VALUE => Err(Error::InvalidParameter("bad value")),
-      B_NO_MEMORY => Err(Error::SystemCall(io::Error::new(io::ErrorKind::OutOfMemory, "allocation failed"))),
-      _ => Err(Error::SystemCall(io::Error::new(io::ErrorKind::Other, "General Error")))
-    }
-  } else {
-    // allocation succeeded
-    match Allocation::new(status) {
-      Ok(inner) => match inner.refresh_info() {
-        Ok(a) => {
-          let addy = KeyType(Arc::new(AtomicPtr::new(a.address as *mut () )));
-          ALLPAGES.insert(addy, inner.clone()); 
-          Ok ( inner )
-        },
-        Err(e) => Err(e)
-      },
-      Err(e) => Err(e)
+  AllocOptions::new().alloc_at(address, size, protection)
+}
+
+/// Allocates shared memory that is mapped twice, back-to-back.
+///
+/// This is not supported on Haiku yet. Mirroring the memory requires cloning
+/// its area (using `clone_area`) directly after it, whilst an [`Allocation`]
+/// owns a single area.
+///
+/// # Errors
+///
+/// - [`Error::UnsupportedOperation`] is always returned.
+pub fn alloc_mirrored(_size: usize) -> Result<Allocation> {
+  Err(Error::UnsupportedOperation)
+}
+
+/// The lock type of an area, deciding when (and how) its pages are backed by
+/// physical memory.
+///
+/// See [`AllocOptions::lock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaikuLock {
+  /// Pages are backed once they're touched, and may be swapped out
+  /// (`B_NO_LOCK`).
+  #[default]
+  NoLock,
+  /// Pages are backed once they're touched, and locked in memory from then
+  /// on (`B_LAZY_LOCK`).
+  LazyLock,
+  /// All pages are backed up front, and locked in memory (`B_FULL_LOCK`).
+  FullLock,
+  /// All pages are backed up front by physically contiguous memory, and
+  /// locked in memory (`B_CONTIGUOUS`).
+  Contiguous,
+  /// Like [`HaikuLock::Contiguous`], but backed by memory below 16MB, for
+  /// legacy DMA (`B_LOMEM`).
+  LoMem,
+}
+
+impl HaikuLock {
+  fn to_native(self) -> u32 {
+    match self {
+      HaikuLock::NoLock => B_NO_LOCK,
+      HaikuLock::LazyLock => B_LAZY_LOCK,
+      HaikuLock::FullLock => B_FULL_LOCK,
+      HaikuLock::Contiguous => B_CONTIGUOUS,
+      HaikuLock::LoMem => B_LOMEM,
     }
   }
 }
 
+/// Options which can be used to configure how memory is allocated.
+///
+/// This builder exposes the ability to configure how an [`Allocation`] is
+/// created. The [`alloc`] and [`alloc_at`] functions are aliases for
+/// allocating memory with the default options.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{AllocOptions, HaikuLock, Protection};
+///
+/// let memory = AllocOptions::new()
+///   .lock(HaikuLock::FullLock)
+///   .alloc(100, Protection::READ_WRITE)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AllocOptions {
+  lock: HaikuLock,
+}
+
+impl AllocOptions {
+  /// Creates a blank new set of options, equivalent to those used by
+  /// [`alloc`] and [`alloc_at`].
+  #[inline]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the lock type of the area, which defaults to [`HaikuLock::NoLock`].
+  ///
+  /// E.g. [`HaikuLock::FullLock`] is suitable for DMA buffers, whilst
+  /// [`HaikuLock::Contiguous`] provides physically contiguous memory.
+  #[inline]
+  pub fn lock(&mut self, lock: HaikuLock) -> &mut Self {
+    self.lock = lock;
+    self
+  }
+
+  /// Allocates one or more pages of memory, with a defined protection.
+  ///
+  /// See [`alloc`] for the parameters and errors.
+  #[inline]
+  pub fn alloc(&self, size: usize, protection: Protection) -> Result<Allocation> {
+    let (_, size) = util::round_to_page_boundaries(std::ptr::null::<()>(), size)?;
+
+    crate::oom::retry(|| {
+      let address = std::ptr::NonNull::<c_void>::dangling().as_ptr();
+      let status = unsafe { create_area(b"region" as *const u8 as *const i8,
+        &address as *const *mut c_void as *mut *mut c_void,
+        B_ANY_ADDRESS, size, self.lock.to_native(), protection.to_native()) };
+      if status < B_OK {
+        Err(create_area_error(status))
+      } else {
+        adopt_area(status)
+      }
+    })
+  }
+
+  /// Allocates one or more pages of memory, at a specific address, with a
+  /// defined protection.
+  ///
+  /// See [`alloc_at`] for the parameters and errors.
+  #[inline]
+  pub fn alloc_at<T>(&self, address: *const T, size: usize, protection: Protection) -> Result<Allocation> {
+    let (address, size) = util::round_to_page_boundaries(address, size)?;
+
+    crate::oom::retry(|| {
+      let status = unsafe { create_area(b"region" as *const u8 as *const i8,
+          &address as &*const T as *const *const T as *mut *mut T as *mut *mut c_void,
+          B_EXACT_ADDRESS, size, self.lock.to_native(), protection.to_native()) };
+      if status < B_OK {
+        Err(create_area_error(status))
+      } else {
+        adopt_area(status)
+      }
+    })
+  }
+}
+
+/// Takes ownership of the area containing an address.
+pub unsafe fn adopt(base: *const ()) -> Result<Allocation> {
+  match area_for(base as *mut c_void) {
+    id if id < B_OK => Err(Error::UnmappedRegion),
+    id => adopt_area(id),
+  }
+}
+
+pub unsafe fn seal(_base: *const (), _size: usize) -> Result<()> {
+  Err(Error::UnsupportedOperation)
+}
+
+pub unsafe fn set_inherit(_base: *const (), _size: usize, _inherit: bool) -> Result<()> {
+  Err(Error::UnsupportedOperation)
+}
+
 pub fn lock(base: *const (), size: usize) -> Result<()> {
   match unsafe { libc::mlock(base.cast(), size) } {
     0 => Ok(()),
@@ -346,40 +804,38 @@ pub fn unlock(base: *const (), size: usize) -> Result<()> {
 pub struct QueryIter {
   info: area_info,
   cookie: isize,
+  upper_bound: usize,
 }
 
 impl QueryIter {
-  pub fn new(origin: *const (), _size: usize) -> Result<QueryIter> {
-    let addy = KeyType(Arc::new(AtomicPtr::new(origin as *mut () )));
-    let id = match ALLPAGES.get(&addy) {
-      Some(v) => *(v.0), // fetch area_id
-      None => return Err(Error::InvalidParameter("Could not find any allocated pages"))
-    };
-    let qi = QueryIter {
-      cookie: 0,
-      info: area_info {
-        area: id,
-        address: std::ptr::null_mut() as *mut c_void,
-        size: 0,
-        name: [0; 32],
-        lock: B_NO_LOCK,
-        protection: 0,
-        ram_size: 0,
-        copy_count: 0,
-        in_count: 0,
-        out_count: 0,
-        team: 0
+  pub fn new(origin: *const (), size: usize) -> Result<QueryIter> {
+    // Addresses outside of the crate's own allocations (e.g. the text segment)
+    //   are absent from the registry, hence the kernel is consulted as well.
+    //   An unmapped origin is not an error, since the iteration walks all of the
+    //   team's areas, and the range may still overlap with subsequent ones.
+    let id = area_of(origin).or_else(|| match unsafe { area_for(origin as *mut c_void) } {
+      id if id < B_OK => None,
+      id => Some(id)
+    });
+
+    let mut info: area_info = unsafe { std::mem::zeroed() };
+    if let Some(id) = id {
+      match unsafe { get_area_info(id, &mut info) } {
+        B_OK => (),
+        status => return Err(os_error(status))
       }
-  	};
-    match unsafe{ get_area_info(qi.info.area, &[qi.info] as *const area_info as *mut area_info) } {
-      B_OK => Ok( qi ),
-      _ => Err(Error::SystemCall(io::Error::new(io::ErrorKind::Other, "area_info failed")))
     }
+
+    Ok(QueryIter {
+      cookie: 0,
+      upper_bound: (origin as usize).saturating_add(size),
+      info,
+    })
   }
 
   #[inline(always)]
   pub fn upper_bound(&self) -> usize {
-    self.info.size as usize
+    self.upper_bound
   }
 }
 
@@ -387,8 +843,8 @@ impl Iterator for QueryIter {
   type Item = Result<Region>;
 
   fn next(&mut self) -> Option<Self::Item> {
-    let status = unsafe { get_next_area_info(0, &[self.cookie] as *const isize as *mut isize,
-      &[self.info] as *const area_info as *mut area_info ) };
+    // The cookie & info must be updated in place, to advance the iteration
+    let status = unsafe { get_next_area_info(0, &mut self.cookie, &mut self.info) };
     if status != B_OK {
       return None;
     }
@@ -396,7 +852,11 @@ impl Iterator for QueryIter {
     Some(Ok(Region {
       base: self.info.address as *const _,
       protection: Protection::from_native(self.info.protection),
+      // Haiku has no lock type implying sharing, instead an area is shared once
+      //   it has been cloned (i.e through `clone_area`).
+      shared: self.info.copy_count > 0,
       size: self.info.size,
+      team: Some(self.info.team),
       ..Default::default()
     }))
   }
@@ -417,6 +877,11 @@ mod tests {
     Ok(())
   }
   
+  #[test]
+  fn alloc_mirrored_is_unsupported() {
+    assert!(matches!(alloc_mirrored(1), Err(Error::UnsupportedOperation)));
+  }
+
   #[test]
   fn alloc_rejects_empty_allocation() {
     assert!(matches!(
@@ -439,6 +904,16 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn alloc_pages_yields_each_page() -> Result<()> {
+    let memory = alloc(page::size() * 3, Protection::READ)?;
+    let pages = memory.pages().collect::<Vec<_>>();
+
+    assert_eq!(pages.len(), memory.len() / page::size());
+    assert_eq!(pages[0], memory.as_ptr());
+    Ok(())
+  }
+
   #[test]
   fn alloc_frees_memory_when_dropped() -> Result<()> {
     let base = alloc(1, Protection::READ_WRITE)?.as_ptr::<()>();
@@ -447,6 +922,34 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn alloc_with_full_lock_is_resident() -> Result<()> {
+    let memory = AllocOptions::new()
+      .lock(HaikuLock::FullLock)
+      .alloc(page::size() * 4, Protection::READ_WRITE)?;
+
+    // The pages are backed up front, without ever being touched
+    assert!(is_resident(memory.as_ptr(), memory.len())?);
+
+    let mut info: area_info = unsafe { std::mem::zeroed() };
+    let id = unsafe { area_for(memory.as_ptr::<c_void>() as *mut c_void) };
+    assert_eq!(unsafe { get_area_info(id, &mut info) }, B_OK);
+    assert_eq!(info.lock, B_FULL_LOCK);
+    Ok(())
+  }
+
+  #[test]
+  fn physical_address_of_contiguous_allocation() -> Result<()> {
+    let memory = AllocOptions::new()
+      .lock(HaikuLock::Contiguous)
+      .alloc(page::size() * 4, Protection::READ_WRITE)?;
+
+    let address = memory.physical_address()?;
+    assert_ne!(address, 0);
+    assert_eq!(address % page::size() as u64, 0);
+    Ok(())
+  }
+
   #[test]
   fn alloc_can_allocate_unused_region() -> Result<()> {
     let base = alloc(1, Protection::NONE)?.as_ptr::<()>();
@@ -455,6 +958,146 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn alloc_drop_tolerates_externally_deleted_area() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    assert_eq!(unsafe { delete_area(memory.id()) }, B_OK);
+    assert!(matches!(memory.refresh_info(), Err(Error::UnmappedRegion)));
+    drop(memory);
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_free_consumes_area_of_all_clones() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    let (clone, id) = (memory.clone(), memory.id());
+
+    memory.free()?;
+    let mut info: area_info = unsafe { std::mem::zeroed() };
+    assert_ne!(unsafe { get_area_info(id, &mut info) }, B_OK);
+    assert_eq!(clone.id(), CONSUMED);
+    assert!(matches!(clone.refresh_info(), Err(Error::UnmappedRegion)));
+
+    // Dropping the clone must not attempt to delete the area a second time
+    drop(clone);
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_deletes_area_on_failure_after_create() {
+    FAIL_AFTER_CREATE.with(|fail| fail.set(true));
+    assert!(matches!(alloc(1, Protection::READ_WRITE), Err(Error::UnmappedRegion)));
+
+    let id = LAST_CREATED.with(|last| last.get());
+    let mut info: area_info = unsafe { std::mem::zeroed() };
+    assert_ne!(unsafe { get_area_info(id, &mut info) }, B_OK);
+  }
+
+  #[test]
+  fn region_reports_cloned_area_as_shared() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    assert!(!crate::query(memory.as_ptr::<()>())?.is_shared());
+
+    let mut address = std::ptr::null_mut::<c_void>();
+    let clone = unsafe { libc::clone_area(b"region clone\0" as *const u8 as *const i8,
+      &mut address, B_ANY_ADDRESS, B_READ_AREA | B_WRITE_AREA, memory.id()) };
+    assert!(clone >= B_OK);
+
+    let region = crate::query(memory.as_ptr::<()>());
+    unsafe { delete_area(clone) };
+    assert!(region?.is_shared());
+    Ok(())
+  }
+
+  #[test]
+  fn region_reports_current_team_as_owner() -> Result<()> {
+    let memory = alloc(1, Protection::READ_WRITE)?;
+    let region = crate::query(memory.as_ptr::<()>())?;
+
+    // The team ID of a process is equivalent to its PID
+    assert_eq!(region.owner_team(), Some(unsafe { libc::getpid() }));
+    Ok(())
+  }
+
+  #[test]
+  fn protect_resolves_area_of_allocation() -> Result<()> {
+    let memory = alloc(1, Protection::READ)?;
+    assert_eq!(area_of(memory.as_ptr()), Some(memory.id()));
+
+    unsafe { protect(memory.as_ptr(), memory.len(), Protection::READ_WRITE)? };
+    assert_eq!(protection_at(memory.as_ptr())?, Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  fn query_resolves_area_outside_of_allocations() -> Result<()> {
+    let text = query_resolves_area_outside_of_allocations as *const ();
+    let region = crate::query(text)?;
+
+    assert!(region.as_range().contains(&(text as usize)));
+    assert_eq!(region.protection(), Protection::READ_EXECUTE);
+    Ok(())
+  }
+
+  #[test]
+  fn query_returns_unmapped_for_null_address_with_allocations() -> Result<()> {
+    // A live allocation ensures that the registry is consulted, and not empty
+    let _memory = alloc(1, Protection::READ)?;
+    let null = std::ptr::null::<()>();
+
+    assert!(matches!(crate::query(null), Err(Error::UnmappedRegion)));
+    assert!(matches!(protection_at(null), Err(Error::UnmappedRegion)));
+    assert!(matches!(unsafe { protect(null, 1, Protection::READ) }, Err(Error::UnmappedRegion)));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(feature = "registry")]
+  fn registry_lookups_match_stored_allocations() -> Result<()> {
+    let first = alloc(1, Protection::READ)?;
+    let second = alloc(1, Protection::READ_WRITE)?;
+
+    // Keys constructed separately from the same address are equal
+    let key = KeyType::new(first.as_ptr());
+    assert!(ALLPAGES.contains_key(&key));
+    assert_eq!(area_of(first.as_ptr()), Some(first.id()));
+    assert_eq!(area_of(second.as_ptr()), Some(second.id()));
+
+    drop(first);
+    assert!(!ALLPAGES.contains_key(&key));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(feature = "registry")]
+  fn alloc_protect_bypasses_registry() -> Result<()> {
+    let memory = alloc(1, Protection::READ)?;
+    ALLPAGES.clear();
+
+    unsafe { memory.protect(Protection::READ_WRITE)? };
+    assert_eq!(protection_at(memory.as_ptr())?, Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  fn os_error_preserves_status() {
+    let error = os_error(B_NO_MEMORY);
+    assert_eq!(error.raw_os_error(), Some(B_NO_MEMORY));
+  }
+
+  #[test]
+  fn create_area_error_maps_each_status() {
+    assert!(matches!(create_area_error(B_BAD_ADDRESS), Error::InvalidParameter("bad address")));
+    assert!(matches!(create_area_error(B_BAD_VALUE), Error::InvalidParameter("bad value")));
+    assert!(matches!(create_area_error(B_NOT_ALLOWED), Error::InvalidParameter("protection")));
+
+    for &status in &[B_NO_MEMORY, libc::B_ERROR] {
+      let error = create_area_error(status);
+      assert!(matches!(error, Error::Os { .. }));
+      assert_eq!(error.raw_os_error(), Some(status));
+    }
+  }
+
   #[test]
   fn protection_flags_are_mapped_from_native() {
     let rw = B_READ_AREA | B_WRITE_AREA;
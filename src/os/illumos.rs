@@ -2,6 +2,23 @@ use crate::{Error, Protection, Region, Result};
 use std::fs::File;
 use std::io::Read;
 
+pub fn current_thread_stack() -> Option<std::ops::Range<usize>> {
+  let mut attr = std::mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+  let (mut base, mut size) = (std::ptr::null_mut(), 0);
+
+  unsafe {
+    if libc::pthread_attr_init(attr.as_mut_ptr()) != 0 {
+      return None;
+    }
+    let result = match libc::pthread_attr_get_np(libc::pthread_self(), attr.as_mut_ptr()) {
+      0 => libc::pthread_attr_getstack(attr.as_ptr(), &mut base, &mut size),
+      error => error,
+    };
+    libc::pthread_attr_destroy(attr.as_mut_ptr());
+    (result == 0).then(|| base as usize..base as usize + size)
+  }
+}
+
 pub struct QueryIter {
   vmmap: Vec<u8>,
   vmmap_index: usize,
@@ -1,5 +1,104 @@
-use crate::{Error, Protection, Region, Result};
+use crate::{page, Error, Protection, Region, Result};
 use std::fs;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+pub fn resident_size() -> Result<usize> {
+  let statm = fs::read_to_string("/proc/self/statm").map_err(Error::SystemCall)?;
+
+  // The second field is the number of resident pages
+  statm
+    .split_whitespace()
+    .nth(1)
+    .and_then(|pages| pages.parse::<usize>().ok())
+    .map(|pages| pages * page::size())
+    .ok_or_else(|| Error::ProcfsInput(statm.clone()))
+}
+
+/// Returns the swapped out size of all mappings that start within a range,
+/// according to /proc/self/smaps.
+pub fn swap_size(range: Range<usize>) -> Option<usize> {
+  let smaps = fs::read_to_string("/proc/self/smaps").ok()?;
+  parse_smaps_swap(&smaps, range)
+}
+
+/// Returns the offsets of the soft-dirty pages within a range, according to
+/// /proc/self/pagemap.
+pub fn dirty_pages(range: Range<usize>) -> Result<Vec<usize>> {
+  if !soft_dirty_supported() {
+    return Err(Error::UnsupportedOperation);
+  }
+  read_dirty_pages(range)
+}
+
+/// Clears the soft-dirty bits of all pages in the process, using
+/// /proc/self/clear_refs.
+pub fn reset_dirty() -> Result<()> {
+  if !soft_dirty_supported() {
+    return Err(Error::UnsupportedOperation);
+  }
+  fs::write("/proc/self/clear_refs", "4").map_err(Error::SystemCall)
+}
+
+/// Returns whether the kernel tracks soft-dirty pages (i.e.
+/// `CONFIG_MEM_SOFT_DIRTY`). Otherwise the bits are always clear, even for a
+/// page that has just been mapped and written to.
+fn soft_dirty_supported() -> bool {
+  static PROBE: Once = Once::new();
+  static SUPPORTED: AtomicBool = AtomicBool::new(false);
+
+  PROBE.call_once(|| {
+    let supported = crate::alloc(1, Protection::READ_WRITE).and_then(|memory| {
+      unsafe { memory.as_ptr::<u8>().cast_mut().write_volatile(0xFF) };
+      read_dirty_pages(memory.as_range()).map(|pages| !pages.is_empty())
+    });
+    SUPPORTED.store(supported.unwrap_or(false), Ordering::Relaxed);
+  });
+  SUPPORTED.load(Ordering::Relaxed)
+}
+
+fn read_dirty_pages(range: Range<usize>) -> Result<Vec<usize>> {
+  use std::os::unix::fs::FileExt;
+
+  // Each page is described by a 64-bit entry, with bit 55 set if soft-dirty
+  const ENTRY_SIZE: usize = std::mem::size_of::<u64>();
+  const SOFT_DIRTY: u64 = 1 << 55;
+
+  let page_size = page::size();
+  let mut entries = vec![0; range.len() / page_size * ENTRY_SIZE];
+  let offset = (range.start / page_size * ENTRY_SIZE) as u64;
+
+  fs::File::open("/proc/self/pagemap")
+    .and_then(|pagemap| pagemap.read_exact_at(&mut entries, offset))
+    .map_err(Error::SystemCall)?;
+
+  Ok(
+    entries
+      .chunks_exact(ENTRY_SIZE)
+      .enumerate()
+      .filter(|(_, entry)| {
+        let entry = <[u8; ENTRY_SIZE] as std::convert::TryFrom<_>>::try_from(*entry);
+        u64::from_ne_bytes(entry.expect("entry size")) & SOFT_DIRTY != 0
+      })
+      .map(|(index, _)| index * page_size)
+      .collect(),
+  )
+}
+
+pub fn current_thread_stack() -> Option<Range<usize>> {
+  let mut attr = std::mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+  let (mut base, mut size) = (std::ptr::null_mut(), 0);
+
+  unsafe {
+    if libc::pthread_getattr_np(libc::pthread_self(), attr.as_mut_ptr()) != 0 {
+      return None;
+    }
+    let result = libc::pthread_attr_getstack(attr.as_ptr(), &mut base, &mut size);
+    libc::pthread_attr_destroy(attr.as_mut_ptr());
+    (result == 0).then(|| base as usize..base as usize + size)
+  }
+}
 
 pub struct QueryIter {
   proc_maps: String,
@@ -61,18 +160,50 @@ fn parse_procfs_line(input: &str) -> Option<Region> {
   let flags = parts.next()?;
   let (protection, shared) = parse_procfs_flags(flags);
 
+  // Shared anonymous memory is backed by an (unlinked) inode of /dev/zero
+  let inode = parts.nth(2)?;
+  let path = parts.next().unwrap_or_default();
+  let file_backed = inode != "0" && !path.starts_with("/dev/zero");
+
   Some(Region {
     base: lower as *const _,
     protection,
     shared,
     size: upper - lower,
+    file_backed,
     ..Region::default()
   })
 }
 
+/// Parses the total swap (in bytes) of mappings starting within a range, from
+/// /proc/[pid]/smaps.
+fn parse_smaps_swap(input: &str, range: Range<usize>) -> Option<usize> {
+  let mut total = None;
+  let mut within = false;
+
+  for line in input.lines() {
+    let mut parts = line.split_whitespace();
+    let key = match parts.next() {
+      Some(key) => key,
+      None => continue,
+    };
+
+    if let Some((lower, _)) = key.split_once('-') {
+      // Each mapping is introduced by its /proc/[pid]/maps line
+      let lower = usize::from_str_radix(lower, 16);
+      within = matches!(lower, Ok(lower) if range.contains(&lower));
+    } else if within && key == "Swap:" {
+      let kilobytes = parts.next()?.parse::<usize>().ok()?;
+      total = Some(total.unwrap_or(0) + kilobytes * 1024);
+    }
+  }
+
+  total
+}
+
 #[cfg(test)]
 mod tests {
-  use super::{parse_procfs_flags, parse_procfs_line};
+  use super::{parse_procfs_flags, parse_procfs_line, parse_smaps_swap};
   use crate::Protection;
 
   #[test]
@@ -97,5 +228,38 @@ mod tests {
     assert_eq!(region.len(), 0x9000);
     assert!(!region.is_guarded());
     assert!(region.is_shared());
+    assert!(region.is_file_backed());
+  }
+
+  #[test]
+  fn procfs_anonymous_regions_are_not_file_backed() {
+    let lines = [
+      "7f0000000000-7f0000001000 rw-p 00000000 00:00 0",
+      "7f0000001000-7f0000002000 rw-p 00000000 00:00 0 [heap]",
+      "7f0000002000-7f0000003000 rw-s 00000000 00:01 1024 /dev/zero (deleted)",
+    ];
+
+    for line in &lines {
+      assert!(!parse_procfs_line(line).unwrap().is_file_backed());
+    }
+  }
+
+  #[test]
+  fn smaps_swap_is_summed_for_range() {
+    let smaps = "\
+00400000-00401000 r-xp 00000000 08:00 16088 /usr/bin/head
+Size:                  4 kB
+Swap:                  0 kB
+00401000-00403000 rw-p 00000000 00:00 0
+Size:                  8 kB
+Swap:                  8 kB
+00403000-00404000 rw-p 00000000 00:00 0
+Swap:                  4 kB
+VmFlags: rd wr mr mw me ac
+";
+
+    assert_eq!(parse_smaps_swap(smaps, 0x40_0000..0x40_1000), Some(0));
+    assert_eq!(parse_smaps_swap(smaps, 0x40_1000..0x40_4000), Some(0x3000));
+    assert_eq!(parse_smaps_swap(smaps, 0x50_0000..0x50_1000), None);
   }
 }
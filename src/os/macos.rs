@@ -1,6 +1,14 @@
 use crate::{Error, Protection, Region, Result};
 use mach::vm_prot::*;
 
+pub fn current_thread_stack() -> Option<std::ops::Range<usize>> {
+  // The reported address is the top of the stack, which grows downwards
+  let thread = unsafe { libc::pthread_self() };
+  let top = unsafe { libc::pthread_get_stackaddr_np(thread) } as usize;
+  let size = unsafe { libc::pthread_get_stacksize_np(thread) };
+  Some(top.checked_sub(size)?..top)
+}
+
 pub struct QueryIter {
   region_address: mach::vm_types::mach_vm_address_t,
   upper_bound: usize,
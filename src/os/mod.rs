@@ -4,9 +4,16 @@ mod windows;
 #[cfg(windows)]
 pub use self::windows::*;
 
+#[cfg(all(unix, not(target_os = "haiku")))]
+mod sys;
+
 #[cfg(all(unix, not(target_os = "haiku")))]
 mod unix;
 
+#[cfg(all(test, unix, not(target_os = "haiku")))]
+#[allow(unused_imports)]
+pub use self::sys::{fail, record, Call};
+
 #[cfg(all(unix, not(target_os = "haiku")))]
 pub use self::unix::*;
 
@@ -22,6 +29,12 @@ mod linux;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use self::linux::*;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod uffd;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use self::uffd::*;
+
 #[cfg(target_os = "freebsd")]
 mod freebsd;
 
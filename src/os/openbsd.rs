@@ -2,6 +2,19 @@ use crate::{Error, Protection, Region, Result};
 use libc::{c_int, c_uint, c_ulong, getpid, sysctl, CTL_KERN, KERN_PROC_VMMAP};
 use std::io;
 
+pub fn current_thread_stack() -> Option<std::ops::Range<usize>> {
+  // The reported address is the top of the stack, which grows downwards
+  let mut info = std::mem::MaybeUninit::<libc::stack_t>::uninit();
+  match unsafe { libc::pthread_stackseg_np(libc::pthread_self(), info.as_mut_ptr()) } {
+    0 => {
+      let info = unsafe { info.assume_init() };
+      let top = info.ss_sp as usize;
+      Some(top.checked_sub(info.ss_size)?..top)
+    }
+    _ => None,
+  }
+}
+
 pub struct QueryIter {
   mib: [c_int; 3],
   vmentry: kinfo_vmentry,
@@ -0,0 +1,411 @@
+//! The system calls used for managing memory on POSIX.
+//!
+//! By default, these are issued through the C library. With the
+//! `direct-mm-syscalls` feature, they are instead issued directly using the
+//! `syscall` instruction on Linux x86-64 and AArch64, for environments where
+//! the C library's memory management wrappers are unavailable or undesirable.
+//! Other targets always use the C library.
+//!
+//! Only the calls of this layer are affected. The crate still depends on the
+//! C library, for its types and for other calls (e.g. `sysconf`,
+//! `memfd_create` and `minherit`).
+
+use libc::{c_int, c_void, off_t};
+use std::io;
+
+/// The implementation used by the POSIX backend.
+#[cfg(not(test))]
+pub type Native = Backend;
+
+/// The implementation used by the POSIX backend, whose calls are recorded
+/// during testing.
+#[cfg(test)]
+pub type Native = Recorded<Backend>;
+
+/// Selects the implementation used by the POSIX backend.
+#[cfg(not(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+type Backend = Libc;
+
+/// Selects the implementation used by the POSIX backend.
+#[cfg(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+type Backend = Raw;
+
+/// A layer of memory management system calls.
+///
+/// Each call reports a failure using its `errno` value, instead of relying on
+/// thread-local state.
+pub trait Syscalls {
+  unsafe fn mmap(
+    base: *mut c_void,
+    size: usize,
+    protection: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+  ) -> io::Result<*mut c_void>;
+  unsafe fn munmap(base: *mut c_void, size: usize) -> io::Result<()>;
+  unsafe fn mprotect(base: *mut c_void, size: usize, protection: c_int) -> io::Result<()>;
+  unsafe fn msync(base: *mut c_void, size: usize, flags: c_int) -> io::Result<()>;
+  unsafe fn mlock(base: *const c_void, size: usize) -> io::Result<()>;
+  unsafe fn munlock(base: *const c_void, size: usize) -> io::Result<()>;
+  unsafe fn mincore(base: *mut c_void, size: usize, residency: *mut u8) -> io::Result<()>;
+  #[cfg(any(target_os = "linux", target_os = "android", feature = "async"))]
+  unsafe fn madvise(base: *mut c_void, size: usize, advice: c_int) -> io::Result<()>;
+}
+
+/// The system calls, issued through the C library.
+#[cfg(not(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+pub struct Libc;
+
+#[cfg(not(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn check(result: c_int) -> io::Result<()> {
+  match result {
+    0 => Ok(()),
+    _ => Err(io::Error::last_os_error()),
+  }
+}
+
+#[cfg(not(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+impl Syscalls for Libc {
+  unsafe fn mmap(
+    base: *mut c_void,
+    size: usize,
+    protection: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+  ) -> io::Result<*mut c_void> {
+    match libc::mmap(base, size, protection, flags, fd, offset) {
+      libc::MAP_FAILED => Err(io::Error::last_os_error()),
+      address => Ok(address),
+    }
+  }
+
+  unsafe fn munmap(base: *mut c_void, size: usize) -> io::Result<()> {
+    check(libc::munmap(base, size))
+  }
+
+  unsafe fn mprotect(base: *mut c_void, size: usize, protection: c_int) -> io::Result<()> {
+    check(libc::mprotect(base, size, protection))
+  }
+
+  unsafe fn msync(base: *mut c_void, size: usize, flags: c_int) -> io::Result<()> {
+    check(libc::msync(base, size, flags))
+  }
+
+  unsafe fn mlock(base: *const c_void, size: usize) -> io::Result<()> {
+    check(libc::mlock(base, size))
+  }
+
+  unsafe fn munlock(base: *const c_void, size: usize) -> io::Result<()> {
+    check(libc::munlock(base, size))
+  }
+
+  unsafe fn mincore(base: *mut c_void, size: usize, residency: *mut u8) -> io::Result<()> {
+    check(libc::mincore(base.cast(), size, residency.cast()))
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "android", feature = "async"))]
+  unsafe fn madvise(base: *mut c_void, size: usize, advice: c_int) -> io::Result<()> {
+    check(libc::madvise(base.cast(), size, advice))
+  }
+}
+
+/// The system calls, issued directly to the Linux kernel.
+#[cfg(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub struct Raw;
+
+#[cfg(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+impl Raw {
+  unsafe fn syscall(number: libc::c_long, args: [usize; 6]) -> io::Result<usize> {
+    let result: isize;
+
+    #[cfg(target_arch = "x86_64")]
+    std::arch::asm!(
+      "syscall",
+      inlateout("rax") number as isize => result,
+      in("rdi") args[0],
+      in("rsi") args[1],
+      in("rdx") args[2],
+      in("r10") args[3],
+      in("r8") args[4],
+      in("r9") args[5],
+      lateout("rcx") _,
+      lateout("r11") _,
+      options(nostack),
+    );
+
+    #[cfg(target_arch = "aarch64")]
+    std::arch::asm!(
+      "svc 0",
+      in("x8") number,
+      inlateout("x0") args[0] as isize => result,
+      in("x1") args[1],
+      in("x2") args[2],
+      in("x3") args[3],
+      in("x4") args[4],
+      in("x5") args[5],
+      options(nostack),
+    );
+
+    // The kernel reports failures as a negated `errno`, within the last page
+    if (-4095..0).contains(&result) {
+      Err(io::Error::from_raw_os_error(-result as i32))
+    } else {
+      Ok(result as usize)
+    }
+  }
+
+  unsafe fn call(
+    number: libc::c_long,
+    base: *const c_void,
+    size: usize,
+    arg: usize,
+  ) -> io::Result<()> {
+    Self::syscall(number, [base as usize, size, arg, 0, 0, 0]).map(|_| ())
+  }
+}
+
+#[cfg(all(
+  feature = "direct-mm-syscalls",
+  any(target_os = "linux", target_os = "android"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+impl Syscalls for Raw {
+  unsafe fn mmap(
+    base: *mut c_void,
+    size: usize,
+    protection: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+  ) -> io::Result<*mut c_void> {
+    let args = [
+      base as usize,
+      size,
+      protection as usize,
+      flags as usize,
+      fd as usize,
+      offset as usize,
+    ];
+    Self::syscall(libc::SYS_mmap, args).map(|address| address as *mut c_void)
+  }
+
+  unsafe fn munmap(base: *mut c_void, size: usize) -> io::Result<()> {
+    Self::call(libc::SYS_munmap, base, size, 0)
+  }
+
+  unsafe fn mprotect(base: *mut c_void, size: usize, protection: c_int) -> io::Result<()> {
+    Self::call(libc::SYS_mprotect, base, size, protection as usize)
+  }
+
+  unsafe fn msync(base: *mut c_void, size: usize, flags: c_int) -> io::Result<()> {
+    Self::call(libc::SYS_msync, base, size, flags as usize)
+  }
+
+  unsafe fn mlock(base: *const c_void, size: usize) -> io::Result<()> {
+    Self::call(libc::SYS_mlock, base, size, 0)
+  }
+
+  unsafe fn munlock(base: *const c_void, size: usize) -> io::Result<()> {
+    Self::call(libc::SYS_munlock, base, size, 0)
+  }
+
+  unsafe fn mincore(base: *mut c_void, size: usize, residency: *mut u8) -> io::Result<()> {
+    Self::call(libc::SYS_mincore, base, size, residency as usize)
+  }
+
+  unsafe fn madvise(base: *mut c_void, size: usize, advice: c_int) -> io::Result<()> {
+    Self::call(libc::SYS_madvise, base, size, advice as usize)
+  }
+}
+
+/// A system call of the [`Syscalls`] layer.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+  Mmap,
+  Munmap,
+  Mprotect,
+  Msync,
+  Mlock,
+  Munlock,
+  Mincore,
+  #[cfg(any(target_os = "linux", target_os = "android", feature = "async"))]
+  Madvise,
+}
+
+#[cfg(test)]
+thread_local! {
+  static CALLS: std::cell::RefCell<Option<Vec<Call>>> = const { std::cell::RefCell::new(None) };
+  static FAILURES: std::cell::RefCell<Vec<(Call, c_int)>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Returns the system calls issued by the current thread, whilst invoking the
+/// closure.
+#[cfg(test)]
+pub fn record<F: FnOnce()>(f: F) -> Vec<Call> {
+  let previous = CALLS.with(|calls| calls.replace(Some(Vec::new())));
+  f();
+  CALLS
+    .with(|calls| calls.replace(previous))
+    .unwrap_or_default()
+}
+
+/// Makes the next `count` invocations of a system call, by the current
+/// thread, fail with an `errno` value, without being issued.
+#[cfg(test)]
+pub fn fail(call: Call, errno: c_int, count: usize) {
+  FAILURES.with(|failures| {
+    (0..count).for_each(|_| failures.borrow_mut().push((call, errno)));
+  });
+}
+
+/// A layer recording each call, before issuing it to another layer.
+#[cfg(test)]
+pub struct Recorded<S>(std::marker::PhantomData<S>);
+
+#[cfg(test)]
+impl<S> Recorded<S> {
+  fn issue<T>(call: Call, issue: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    CALLS.with(|calls| {
+      if let Some(calls) = calls.borrow_mut().as_mut() {
+        calls.push(call);
+      }
+    });
+
+    let failure = FAILURES.with(|failures| {
+      let mut failures = failures.borrow_mut();
+      let index = failures.iter().position(|&(failing, _)| failing == call)?;
+      Some(failures.remove(index).1)
+    });
+
+    match failure {
+      Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+      None => issue(),
+    }
+  }
+}
+
+#[cfg(test)]
+impl<S: Syscalls> Syscalls for Recorded<S> {
+  unsafe fn mmap(
+    base: *mut c_void,
+    size: usize,
+    protection: c_int,
+    flags: c_int,
+    fd: c_int,
+    offset: off_t,
+  ) -> io::Result<*mut c_void> {
+    Self::issue(Call::Mmap, || {
+      S::mmap(base, size, protection, flags, fd, offset)
+    })
+  }
+
+  unsafe fn munmap(base: *mut c_void, size: usize) -> io::Result<()> {
+    Self::issue(Call::Munmap, || S::munmap(base, size))
+  }
+
+  unsafe fn mprotect(base: *mut c_void, size: usize, protection: c_int) -> io::Result<()> {
+    Self::issue(Call::Mprotect, || S::mprotect(base, size, protection))
+  }
+
+  unsafe fn msync(base: *mut c_void, size: usize, flags: c_int) -> io::Result<()> {
+    Self::issue(Call::Msync, || S::msync(base, size, flags))
+  }
+
+  unsafe fn mlock(base: *const c_void, size: usize) -> io::Result<()> {
+    Self::issue(Call::Mlock, || S::mlock(base, size))
+  }
+
+  unsafe fn munlock(base: *const c_void, size: usize) -> io::Result<()> {
+    Self::issue(Call::Munlock, || S::munlock(base, size))
+  }
+
+  unsafe fn mincore(base: *mut c_void, size: usize, residency: *mut u8) -> io::Result<()> {
+    Self::issue(Call::Mincore, || S::mincore(base, size, residency))
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "android", feature = "async"))]
+  unsafe fn madvise(base: *mut c_void, size: usize, advice: c_int) -> io::Result<()> {
+    Self::issue(Call::Madvise, || S::madvise(base, size, advice))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn recorded_syscalls_can_be_made_to_fail() {
+    let size = crate::page::size();
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANON;
+
+    let calls = record(|| unsafe {
+      fail(Call::Mprotect, libc::EAGAIN, 1);
+      let base = Native::mmap(std::ptr::null_mut(), size, libc::PROT_READ, flags, -1, 0).unwrap();
+      let error = Native::mprotect(base, size, libc::PROT_NONE).unwrap_err();
+      assert_eq!(error.raw_os_error(), Some(libc::EAGAIN));
+      Native::mprotect(base, size, libc::PROT_NONE).unwrap();
+      Native::munmap(base, size).unwrap();
+    });
+
+    let expected = [Call::Mmap, Call::Mprotect, Call::Mprotect, Call::Munmap];
+    assert_eq!(calls, expected);
+  }
+
+  #[test]
+  fn native_syscalls_round_trip_mapping() -> io::Result<()> {
+    let size = crate::page::size();
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANON;
+
+    unsafe {
+      let base = Native::mmap(std::ptr::null_mut(), size, libc::PROT_READ, flags, -1, 0)?;
+      Native::mprotect(base, size, libc::PROT_READ | libc::PROT_WRITE)?;
+      base.cast::<u8>().write(0xFF);
+      Native::munmap(base, size)?;
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn native_syscalls_report_errno() {
+    let result = unsafe {
+      Native::mprotect(
+        std::ptr::null_mut::<u8>().wrapping_add(1).cast(),
+        1,
+        libc::PROT_READ,
+      )
+    };
+    let error = result.unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(libc::EINVAL));
+  }
+}
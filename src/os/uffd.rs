@@ -0,0 +1,238 @@
+//! Write-protect tracking using `userfaultfd` (see `userfaultfd(2)`).
+//!
+//! The libc crate does not expose the `userfaultfd` interface, hence the
+//! structures and `ioctl` requests are declared here.
+
+use crate::{page, Error, Result};
+use libc::{c_int, c_ulong};
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+const UFFD_API: u64 = 0xAA;
+const UFFD_USER_MODE_ONLY: c_int = 1;
+
+const UFFD_FEATURE_PAGEFAULT_FLAG_WP: u64 = 1 << 0;
+const UFFD_FEATURE_WP_UNPOPULATED: u64 = 1 << 13;
+
+const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+const UFFD_PAGEFAULT_FLAG_WP: u64 = 1 << 1;
+
+const UFFDIO_REGISTER_MODE_WP: u64 = 1 << 1;
+const UFFDIO_WRITEPROTECT_MODE_WP: u64 = 1 << 0;
+
+// The requests are encoded as `_IOWR(0xAA, nr, size)`
+const UFFDIO_API: c_ulong = 0xC018_AA3F;
+const UFFDIO_REGISTER: c_ulong = 0xC020_AA00;
+const UFFDIO_WRITEPROTECT: c_ulong = 0xC018_AA06;
+// Encoded as `_IOR(0xAA, nr, size)`
+const UFFDIO_UNREGISTER: c_ulong = 0x8010_AA01;
+const UFFDIO_WAKE: c_ulong = 0x8010_AA02;
+
+#[repr(C)]
+struct UffdioApi {
+  api: u64,
+  features: u64,
+  ioctls: u64,
+}
+
+#[repr(C)]
+struct UffdioRange {
+  start: u64,
+  len: u64,
+}
+
+#[repr(C)]
+struct UffdioRegister {
+  range: UffdioRange,
+  mode: u64,
+  ioctls: u64,
+}
+
+#[repr(C)]
+struct UffdioWriteprotect {
+  range: UffdioRange,
+  mode: u64,
+}
+
+#[repr(C)]
+struct UffdMsg {
+  event: u8,
+  reserved: [u8; 7],
+  flags: u64,
+  address: u64,
+  feature: u64,
+}
+
+// The descriptor of the process' tracker, once it has been created. A failed
+//   creation is retried by the next invocation.
+static TRACKER: Mutex<Option<c_int>> = Mutex::new(None);
+
+// The registered ranges, as `(start, end)`, which are unregistered if the
+//   handler panics.
+static RANGES: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+
+/// Write-protects a range of pages, reporting each page's first write to
+/// [`crate::track::notify`].
+pub fn write_protect(base: *const (), size: usize) -> Result<()> {
+  let fd = tracker()?;
+  let range = || UffdioRange {
+    start: base as u64,
+    len: size as u64,
+  };
+
+  let mut register = UffdioRegister {
+    range: range(),
+    mode: UFFDIO_REGISTER_MODE_WP,
+    ioctls: 0,
+  };
+  ioctl(fd, UFFDIO_REGISTER, &mut register)?;
+
+  let mut protect = UffdioWriteprotect {
+    range: range(),
+    mode: UFFDIO_WRITEPROTECT_MODE_WP,
+  };
+
+  if let Err(error) = ioctl(fd, UFFDIO_WRITEPROTECT, &mut protect) {
+    let _ = ioctl(fd, UFFDIO_UNREGISTER, &mut range());
+    return Err(error);
+  }
+
+  let end = base as usize + size;
+  lock(&RANGES).push((base as usize, end));
+  Ok(())
+}
+
+/// Returns the process' `userfaultfd` descriptor, creating it (and the thread
+/// serving its faults) on first use.
+pub fn tracker() -> Result<c_int> {
+  let mut tracker = lock(&TRACKER);
+  if let Some(fd) = *tracker {
+    return Ok(fd);
+  }
+
+  let fd = open()?;
+  let spawned = std::thread::Builder::new()
+    .name("region-uffd".to_string())
+    .spawn(move || serve(fd));
+
+  if let Err(error) = spawned {
+    unsafe { libc::close(fd) };
+    return Err(Error::SystemCall(error));
+  }
+
+  *tracker = Some(fd);
+  Ok(fd)
+}
+
+fn open() -> Result<c_int> {
+  // Unprivileged processes may be limited to user-mode faults
+  let fd = match userfaultfd(libc::O_CLOEXEC) {
+    Err(error) if error.raw_os_error() == Some(libc::EPERM) => {
+      userfaultfd(libc::O_CLOEXEC | UFFD_USER_MODE_ONLY)
+    }
+    result => result,
+  }
+  .map_err(|error| match error.raw_os_error() {
+    Some(libc::ENOSYS) | Some(libc::EPERM) | Some(libc::EINVAL) => Error::UnsupportedOperation,
+    _ => Error::SystemCall(error),
+  })?;
+
+  // Unpopulated pages must be write-protected as well (i.e. Linux 6.4)
+  let mut api = UffdioApi {
+    api: UFFD_API,
+    features: UFFD_FEATURE_PAGEFAULT_FLAG_WP | UFFD_FEATURE_WP_UNPOPULATED,
+    ioctls: 0,
+  };
+
+  match ioctl(fd, UFFDIO_API, &mut api) {
+    Ok(()) => Ok(fd),
+    Err(error) => {
+      unsafe { libc::close(fd) };
+      match error {
+        Error::SystemCall(error) if error.raw_os_error() == Some(libc::EINVAL) => {
+          Err(Error::UnsupportedOperation)
+        }
+        error => Err(error),
+      }
+    }
+  }
+}
+
+/// Serves the write-protect faults of the tracker, until it fails.
+fn serve(fd: c_int) {
+  let page_size = page::size();
+
+  loop {
+    let mut message = std::mem::MaybeUninit::<UffdMsg>::uninit();
+    let size = std::mem::size_of::<UffdMsg>();
+    let read = unsafe { libc::read(fd, message.as_mut_ptr().cast(), size) };
+
+    if read != size as isize {
+      match io::Error::last_os_error().raw_os_error() {
+        Some(libc::EINTR) | Some(libc::EAGAIN) if read == -1 => continue,
+        _ => return,
+      }
+    }
+
+    let message = unsafe { message.assume_init() };
+    if message.event != UFFD_EVENT_PAGEFAULT || message.flags & UFFD_PAGEFAULT_FLAG_WP == 0 {
+      continue;
+    }
+
+    let page = message.address as usize & !(page_size - 1);
+    let handled = crate::track::notify(page as *const ());
+
+    // A panicking handler can no longer be relied upon, hence its range is untracked
+    let untracked = match handled {
+      Ok(()) => None,
+      Err(_) => untrack(page),
+    };
+    let (start, end) = untracked.unwrap_or((page, page + page_size));
+
+    // Removing the protection also wakes the faulting thread
+    let range = || UffdioRange {
+      start: start as u64,
+      len: (end - start) as u64,
+    };
+    let mut unprotect = UffdioWriteprotect {
+      range: range(),
+      mode: 0,
+    };
+    // The faulting thread must never remain blocked, e.g. if the range has
+    //   been unregistered in the meantime.
+    if ioctl(fd, UFFDIO_WRITEPROTECT, &mut unprotect).is_err() {
+      let _ = ioctl(fd, UFFDIO_WAKE, &mut range());
+    }
+
+    if untracked.is_some() {
+      let _ = ioctl(fd, UFFDIO_UNREGISTER, &mut range());
+    }
+  }
+}
+
+/// Removes the registered range containing an address, returning it.
+fn untrack(address: usize) -> Option<(usize, usize)> {
+  let mut ranges = lock(&RANGES);
+  let index = ranges
+    .iter()
+    .position(|&(start, end)| (start..end).contains(&address))?;
+  Some(ranges.swap_remove(index))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+fn userfaultfd(flags: c_int) -> io::Result<c_int> {
+  match unsafe { libc::syscall(libc::SYS_userfaultfd, flags) } {
+    -1 => Err(io::Error::last_os_error()),
+    fd => Ok(fd as c_int),
+  }
+}
+
+fn ioctl<T>(fd: c_int, request: c_ulong, argument: &mut T) -> Result<()> {
+  match unsafe { libc::ioctl(fd, request as _, argument as *mut T) } {
+    -1 => Err(Error::SystemCall(io::Error::last_os_error())),
+    _ => Ok(()),
+  }
+}
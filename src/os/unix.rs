@@ -1,53 +1,529 @@
-use crate::{Error, Protection, Result};
-use libc::{MAP_ANON, MAP_FAILED, MAP_FIXED, MAP_PRIVATE};
+use super::sys::{Native, Syscalls};
+use crate::{AllocOptions, CacheMode, Error, Protection, Result};
+use libc::{MAP_ANON, MAP_FIXED, MAP_PRIVATE, MAP_SHARED};
 use libc::{PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE};
 use std::io;
+use std::os::unix::io::AsRawFd;
 
 pub fn page_size() -> usize {
   unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
 }
 
-pub unsafe fn alloc(base: *const (), size: usize, protection: Protection) -> Result<*const ()> {
-  let mut flags = MAP_PRIVATE | MAP_ANON;
+pub fn allocation_granularity() -> usize {
+  page_size()
+}
+
+pub fn address_space_bounds() -> std::ops::Range<usize> {
+  // Linux refuses to map anything below `mmap_min_addr`
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  let lower = std::fs::read_to_string("/proc/sys/vm/mmap_min_addr")
+    .ok()
+    .and_then(|value| value.trim().parse::<usize>().ok())
+    .unwrap_or(0)
+    .max(page_size());
+
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  let lower = page_size();
+
+  // x86-64 systems provide (approximately) 47 bits of user space, elsewhere
+  //   the highest mapping (typically the main stack) is the bound.
+  #[cfg(all(target_arch = "x86_64", target_pointer_width = "64"))]
+  let upper = (1usize << 47) - page_size();
+
+  #[cfg(not(all(target_arch = "x86_64", target_pointer_width = "64")))]
+  let upper = 0;
+
+  lower..upper.max(crate::util::highest_mapped_address())
+}
+
+#[inline]
+pub fn is_out_of_memory(error: &Error) -> bool {
+  error.raw_os_error() == Some(libc::ENOMEM)
+}
 
-  if !base.is_null() {
-    flags |= MAP_FIXED;
+// Not exposed by the libc crate on Android
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAP_FIXED_NOREPLACE: libc::c_int = 0x100000;
+
+pub unsafe fn alloc(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+  options: &AllocOptions,
+) -> Result<*const ()> {
+  let placement = if base.is_null() { 0 } else { MAP_FIXED };
+  map_anon(base, size, protection, options, placement)
+}
+
+/// Allocates memory at a specific address, without replacing any existing
+/// mapping within the range.
+pub unsafe fn alloc_exclusive(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+  options: &AllocOptions,
+) -> Result<*const ()> {
+  // A null address is never mapped, hence the allocation is placed anywhere
+  if base.is_null() {
+    return alloc(base, size, protection, options);
   }
 
-  match libc::mmap(base as *mut _, size, protection.to_native(), flags, -1, 0) {
-    MAP_FAILED => Err(Error::SystemCall(io::Error::last_os_error())),
-    address => Ok(address as *const ()),
+  // Linux prior to 4.17 ignores the flag, and treats the address as a hint
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  {
+    let address = match map_anon(base, size, protection, options, MAP_FIXED_NOREPLACE) {
+      Err(error) if error.raw_os_error() == Some(libc::EEXIST) => return Err(Error::AlreadyMapped),
+      result => result?,
+    };
+
+    if address != base {
+      free(address, size)?;
+      return Err(Error::AlreadyMapped);
+    }
+    Ok(address)
+  }
+
+  #[cfg(target_os = "freebsd")]
+  match map_anon(base, size, protection, options, MAP_FIXED | libc::MAP_EXCL) {
+    Err(error) if error.raw_os_error() == Some(libc::EINVAL) => Err(Error::AlreadyMapped),
+    result => result,
+  }
+
+  // Without a flag for it, the range is queried beforehand, which is racy
+  #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+  {
+    if let Some(region) = crate::query_range(base, size)?.next() {
+      return Err(region.err().unwrap_or(Error::AlreadyMapped));
+    }
+    alloc(base, size, protection, options)
   }
 }
 
-pub unsafe fn free(base: *const (), size: usize) -> Result<()> {
-  match libc::munmap(base as *mut _, size) {
-    0 => Ok(()),
-    _ => Err(Error::SystemCall(io::Error::last_os_error())),
+unsafe fn map_anon(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+  options: &AllocOptions,
+  placement: libc::c_int,
+) -> Result<*const ()> {
+  // Anonymous memory always uses the default caching mode
+  if options.cache_mode != CacheMode::Default {
+    return Err(Error::UnsupportedOperation);
+  }
+
+  let mut flags = MAP_PRIVATE | MAP_ANON | placement;
+
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  if options.no_reserve {
+    flags |= libc::MAP_NORESERVE;
+  }
+
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  if options.locked {
+    flags |= libc::MAP_LOCKED;
+  }
+
+  #[cfg(any(target_os = "macos", target_os = "ios"))]
+  if options.jit {
+    flags |= libc::MAP_JIT;
   }
+
+  #[cfg(all(
+    any(target_os = "linux", target_os = "android"),
+    target_arch = "x86_64"
+  ))]
+  if options.low_memory {
+    flags |= libc::MAP_32BIT;
+  }
+
+  let file = backing_file(size, options)?;
+  if file.is_some() {
+    flags &= !MAP_ANON;
+  }
+
+  let fd = file.as_ref().map_or(-1, AsRawFd::as_raw_fd);
+  Native::mmap(base as *mut _, size, protection.to_native(), flags, fd, 0)
+    .map(|address| address as *const ())
+    .map_err(|error| match error.raw_os_error() {
+      // Linux reports an exceeded lock limit of `MAP_LOCKED` as `EAGAIN`
+      #[cfg(any(target_os = "linux", target_os = "android"))]
+      Some(libc::EAGAIN) if options.locked => Error::LockLimitExceeded,
+      _ => Error::SystemCall(error),
+    })
 }
 
-pub unsafe fn protect(base: *const (), size: usize, protection: Protection) -> Result<()> {
-  match libc::mprotect(base as *mut _, size, protection.to_native()) {
+pub unsafe fn alloc_aligned(
+  size: usize,
+  align: usize,
+  protection: Protection,
+  options: &AllocOptions,
+) -> Result<*const ()> {
+  // Since the mapping is page aligned, an excess of this size is sufficient
+  let padded_size = size
+    .checked_add(align - page_size())
+    .ok_or(Error::InvalidParameter("size"))?;
+
+  let padded_base = alloc(std::ptr::null(), padded_size, protection, options)? as usize;
+  let base = (padded_base + align - 1) & !(align - 1);
+
+  // Trim the excess memory before and after the aligned allocation
+  if base > padded_base {
+    free(padded_base as *const (), base - padded_base)?;
+  }
+
+  let (end, padded_end) = (base + size, padded_base + padded_size);
+  if padded_end > end {
+    free(end as *const (), padded_end - end)?;
+  }
+
+  Ok(base as *const ())
+}
+
+pub fn file_protection(fd: libc::c_int) -> Result<Protection> {
+  let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+  if flags == -1 {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  // A file must always be readable to be mapped
+  match flags & libc::O_ACCMODE {
+    libc::O_RDONLY => Ok(Protection::READ),
+    libc::O_RDWR => Ok(Protection::READ_WRITE),
+    _ => Err(Error::InvalidParameter("file")),
+  }
+}
+
+pub unsafe fn map_file(fd: libc::c_int, size: usize, protection: Protection) -> Result<*const ()> {
+  let flags = MAP_SHARED;
+  Native::mmap(
+    std::ptr::null_mut(),
+    size,
+    protection.to_native(),
+    flags,
+    fd,
+    0,
+  )
+  .map(|address| address as *const ())
+  .map_err(Error::SystemCall)
+}
+
+pub unsafe fn alloc_mirrored(size: usize) -> Result<*const ()> {
+  let file = shared_memory()?;
+  file.set_len(size as u64).map_err(Error::SystemCall)?;
+
+  // The address space is reserved up front, so that both views of the shared
+  //   memory can be mapped over it, back-to-back.
+  let flags = MAP_PRIVATE | MAP_ANON;
+  let base = Native::mmap(std::ptr::null_mut(), size * 2, PROT_NONE, flags, -1, 0)
+    .map_err(Error::SystemCall)?;
+
+  for offset in [0, size] {
+    let protection = PROT_READ | PROT_WRITE;
+    let flags = MAP_SHARED | MAP_FIXED;
+    let address = base.cast::<u8>().add(offset).cast();
+
+    if let Err(error) = Native::mmap(address, size, protection, flags, file.as_raw_fd(), 0) {
+      let _ = Native::munmap(base, size * 2);
+      return Err(Error::SystemCall(error));
+    }
+  }
+  Ok(base as *const ())
+}
+
+/// Returns the memory file backing a new allocation, if the options (or else
+/// the process-wide [`AnonBacking`](crate::AnonBacking)) are configured to use
+/// one.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn backing_file(size: usize, options: &AllocOptions) -> Result<Option<std::fs::File>> {
+  let backing = options.backing.unwrap_or_else(crate::backing::anon_backing);
+  if backing != crate::AnonBacking::Memfd {
+    return Ok(None);
+  }
+
+  let file = shared_memory()?;
+  file.set_len(size as u64).map_err(Error::SystemCall)?;
+  Ok(Some(file))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn backing_file(_size: usize, _options: &AllocOptions) -> Result<Option<std::fs::File>> {
+  Ok(None)
+}
+
+/// Creates an anonymous shared memory object, which is closed once dropped.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn shared_memory() -> Result<std::fs::File> {
+  use std::os::unix::io::FromRawFd;
+
+  match unsafe { libc::memfd_create(b"region\0".as_ptr().cast(), libc::MFD_CLOEXEC) } {
+    -1 => Err(Error::SystemCall(io::Error::last_os_error())),
+    fd => Ok(unsafe { std::fs::File::from_raw_fd(fd) }),
+  }
+}
+
+/// Creates an anonymous shared memory object, which is closed once dropped.
+///
+/// A uniquely named object is created, and immediately unlinked, since
+/// `memfd_create` is not available on every platform.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn shared_memory() -> Result<std::fs::File> {
+  use std::os::unix::io::FromRawFd;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+  let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+  let name = format!("/region-{}-{}\0", std::process::id(), id);
+  let flags = libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC;
+
+  #[cfg(any(target_os = "macos", target_os = "ios"))]
+  let fd = unsafe { libc::shm_open(name.as_ptr().cast(), flags, 0o600 as libc::c_uint) };
+
+  #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+  let fd = unsafe { libc::shm_open(name.as_ptr().cast(), flags, 0o600 as libc::mode_t) };
+
+  if fd == -1 {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  unsafe { libc::shm_unlink(name.as_ptr().cast()) };
+  Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn huge_page_size() -> usize {
+  const DEFAULT_HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+  std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/hpage_pmd_size")
+    .ok()
+    .and_then(|size| size.trim().parse::<usize>().ok())
+    .filter(|size| size.is_power_of_two())
+    .unwrap_or(DEFAULT_HUGE_PAGE_SIZE)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub unsafe fn advise_huge_pages(base: *const (), size: usize) -> Result<()> {
+  Native::madvise(base as *mut _, size, libc::MADV_HUGEPAGE).map_err(Error::SystemCall)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub unsafe fn set_inherit(base: *const (), size: usize, inherit: bool) -> Result<()> {
+  let advice = if inherit {
+    libc::MADV_DOFORK
+  } else {
+    libc::MADV_DONTFORK
+  };
+
+  Native::madvise(base as *mut _, size, advice).map_err(Error::SystemCall)
+}
+
+#[cfg(any(
+  target_os = "macos",
+  target_os = "ios",
+  target_os = "freebsd",
+  target_os = "openbsd"
+))]
+pub unsafe fn set_inherit(base: *const (), size: usize, inherit: bool) -> Result<()> {
+  #[cfg(any(target_os = "macos", target_os = "ios"))]
+  use libc::{VM_INHERIT_COPY as INHERIT_COPY, VM_INHERIT_NONE as INHERIT_NONE};
+
+  #[cfg(target_os = "freebsd")]
+  use libc::{INHERIT_COPY, INHERIT_NONE};
+
+  #[cfg(target_os = "openbsd")]
+  use libc::{MAP_INHERIT_COPY as INHERIT_COPY, MAP_INHERIT_NONE as INHERIT_NONE};
+
+  let inheritance = if inherit { INHERIT_COPY } else { INHERIT_NONE };
+  match libc::minherit(base as *mut _, size, inheritance) {
     0 => Ok(()),
     _ => Err(Error::SystemCall(io::Error::last_os_error())),
   }
 }
 
-pub fn lock(base: *const (), size: usize) -> Result<()> {
-  match unsafe { libc::mlock(base.cast(), size) } {
+#[cfg(not(any(
+  target_os = "linux",
+  target_os = "android",
+  target_os = "macos",
+  target_os = "ios",
+  target_os = "freebsd",
+  target_os = "openbsd"
+)))]
+pub unsafe fn set_inherit(_base: *const (), _size: usize, _inherit: bool) -> Result<()> {
+  Err(Error::UnsupportedOperation)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub unsafe fn seal(base: *const (), size: usize) -> Result<()> {
+  // The number is shared by all architectures, but absent from older libc
+  const SYS_MSEAL: libc::c_long = 462;
+
+  match libc::syscall(SYS_MSEAL, base, size, 0) {
     0 => Ok(()),
-    _ => Err(Error::SystemCall(io::Error::last_os_error())),
+    _ => match io::Error::last_os_error() {
+      error if error.raw_os_error() == Some(libc::ENOSYS) => Err(Error::UnsupportedOperation),
+      error => Err(Error::SystemCall(error)),
+    },
   }
 }
 
-pub fn unlock(base: *const (), size: usize) -> Result<()> {
-  match unsafe { libc::munlock(base.cast(), size) } {
+#[cfg(target_os = "openbsd")]
+pub unsafe fn seal(base: *const (), size: usize) -> Result<()> {
+  match mimmutable(base as *mut _, size) {
     0 => Ok(()),
     _ => Err(Error::SystemCall(io::Error::last_os_error())),
   }
 }
 
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "openbsd")))]
+pub unsafe fn seal(_base: *const (), _size: usize) -> Result<()> {
+  Err(Error::UnsupportedOperation)
+}
+
+pub unsafe fn free(base: *const (), size: usize) -> Result<()> {
+  Native::munmap(base as *mut _, size).map_err(Error::SystemCall)
+}
+
+pub unsafe fn commit(base: *const (), size: usize, guard: bool) -> Result<()> {
+  // POSIX lacks guard pages, instead an inaccessible page faults on any access
+  let protection = if guard {
+    Protection::NONE
+  } else {
+    Protection::READ_WRITE
+  };
+  protect(base, size, protection)
+}
+
+pub unsafe fn flush(base: *const (), size: usize, asynchronous: bool) -> Result<()> {
+  let flags = if asynchronous {
+    libc::MS_ASYNC
+  } else {
+    libc::MS_SYNC
+  };
+
+  Native::msync(base as *mut _, size, flags).map_err(Error::SystemCall)
+}
+
+pub unsafe fn protect(base: *const (), size: usize, protection: Protection) -> Result<()> {
+  Native::mprotect(base as *mut _, size, protection.to_native()).map_err(Error::SystemCall)
+}
+
+pub unsafe fn protect_preserve_flags(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+) -> Result<()> {
+  // POSIX does not distinguish between protection and page modifiers
+  protect(base, size, protection)
+}
+
+pub fn lock(base: *const (), size: usize) -> Result<()> {
+  unsafe { Native::mlock(base.cast(), size) }.map_err(|error| lock_error(error, base, size))
+}
+
+fn lock_error(error: io::Error, base: *const (), size: usize) -> Error {
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  let _ = (base, size);
+
+  match error.raw_os_error() {
+    Some(libc::EPERM) => Error::LockLimitExceeded,
+    // On Linux, `EAGAIN` is a transient failure to lock the range, whilst it
+    // indicates an exceeded limit elsewhere.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    Some(libc::EAGAIN) => Error::LockLimitExceeded,
+    // Linux reports an exceeded limit as `ENOMEM`, which is otherwise used for
+    // unmapped ranges.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Some(libc::ENOMEM) if is_mapped(base, size) => Error::LockLimitExceeded,
+    _ => Error::SystemCall(error),
+  }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_mapped(base: *const (), size: usize) -> bool {
+  let end = (base as usize).saturating_add(size);
+  let mapped = crate::query_range(base, size).map(|regions| {
+    regions.fold(0, |total, region| match region {
+      Ok(region) => {
+        let range = region.as_range();
+        total + range.end.min(end) - range.start.max(base as usize)
+      }
+      Err(_) => 0,
+    })
+  });
+
+  matches!(mapped, Ok(mapped) if mapped == size)
+}
+
+#[cfg(any(
+  target_os = "linux",
+  target_os = "android",
+  target_os = "macos",
+  target_os = "ios",
+  target_os = "freebsd",
+  target_os = "openbsd"
+))]
+pub fn memlock_limit() -> Result<(usize, usize)> {
+  let mut limit = std::mem::MaybeUninit::<libc::rlimit>::uninit();
+  if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, limit.as_mut_ptr()) } != 0 {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  let limit = unsafe { limit.assume_init() };
+  let convert = |value: libc::rlim_t| {
+    if value == libc::RLIM_INFINITY {
+      usize::MAX
+    } else {
+      std::convert::TryFrom::try_from(value).unwrap_or(usize::MAX)
+    }
+  };
+  Ok((convert(limit.rlim_cur), convert(limit.rlim_max)))
+}
+
+pub fn unlock(base: *const (), size: usize) -> Result<()> {
+  unsafe { Native::munlock(base.cast(), size) }.map_err(Error::SystemCall)
+}
+
+pub fn flush_icache(base: *const (), size: usize) {
+  #[cfg(any(target_os = "macos", target_os = "ios"))]
+  unsafe {
+    sys_icache_invalidate(base as *mut _, size)
+  };
+
+  #[cfg(not(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_arch = "x86",
+    target_arch = "x86_64"
+  )))]
+  unsafe {
+    __clear_cache(base as *mut _, (base as usize + size) as *mut _)
+  };
+
+  // The instruction cache is coherent on x86
+  #[cfg(all(
+    not(any(target_os = "macos", target_os = "ios")),
+    any(target_arch = "x86", target_arch = "x86_64")
+  ))]
+  let _ = (base, size);
+}
+
+pub fn is_resident(base: *const (), size: usize) -> Result<bool> {
+  resident_pages(base, size).map(|pages| pages == size / page_size())
+}
+
+pub fn resident_pages(base: *const (), size: usize) -> Result<usize> {
+  let mut residency = vec![0u8; size / page_size()];
+
+  unsafe { Native::mincore(base as *mut _, size, residency.as_mut_ptr()) }
+    .map(|_| residency.iter().filter(|&page| page & 1 == 1).count())
+    .map_err(Error::SystemCall)
+}
+
+#[cfg(feature = "async")]
+pub unsafe fn prefetch(base: *const (), size: usize) -> Result<()> {
+  Native::madvise(base as *mut _, size, libc::MADV_WILLNEED).map_err(Error::SystemCall)
+}
+
 impl Protection {
   fn to_native(self) -> libc::c_int {
     const MAPPINGS: &[(Protection, libc::c_int)] = &[
@@ -63,6 +539,26 @@ impl Protection {
   }
 }
 
+#[cfg(target_os = "openbsd")]
+extern "C" {
+  fn mimmutable(addr: *mut libc::c_void, len: libc::size_t) -> libc::c_int;
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+extern "C" {
+  fn sys_icache_invalidate(start: *mut libc::c_void, len: libc::size_t);
+}
+
+#[cfg(not(any(
+  target_os = "macos",
+  target_os = "ios",
+  target_arch = "x86",
+  target_arch = "x86_64"
+)))]
+extern "C" {
+  fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
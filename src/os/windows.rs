@@ -1,13 +1,26 @@
-use crate::{Error, Protection, Region, Result};
+use crate::{AllocOptions, CacheMode, Error, Protection, Region, Result};
 use std::cmp::{max, min};
 use std::io;
 use std::mem::{size_of, MaybeUninit};
-use std::sync::Once;
+use std::sync::{Once, OnceLock};
+use winapi::shared::basetsd::SIZE_T;
+use winapi::shared::minwindef::{DWORD, FARPROC, ULONG};
+use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
 use winapi::um::memoryapi::{
-  VirtualAlloc, VirtualFree, VirtualLock, VirtualProtect, VirtualQuery, VirtualUnlock,
+  FlushViewOfFile, VirtualAlloc, VirtualFree, VirtualLock, VirtualProtect, VirtualQuery,
+  VirtualUnlock,
+};
+use winapi::um::processthreadsapi::{
+  FlushInstructionCache, GetCurrentProcess, GetCurrentThreadStackLimits,
+};
+use winapi::um::psapi::{
+  GetProcessMemoryInfo, QueryWorkingSetEx, PROCESS_MEMORY_COUNTERS,
+  PSAPI_WORKING_SET_EX_INFORMATION,
+};
+use winapi::um::sysinfoapi::{GetNativeSystemInfo, GetSystemInfo, SYSTEM_INFO};
+use winapi::um::winnt::{
+  HANDLE, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PVOID,
 };
-use winapi::um::sysinfoapi::{GetNativeSystemInfo, SYSTEM_INFO};
-use winapi::um::winnt::{MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE};
 
 pub struct QueryIter {
   region_address: usize,
@@ -80,23 +93,477 @@ pub fn page_size() -> usize {
   system_info().dwPageSize as usize
 }
 
-pub unsafe fn alloc(base: *const (), size: usize, protection: Protection) -> Result<*const ()> {
+pub fn allocation_granularity() -> usize {
+  system_info().dwAllocationGranularity as usize
+}
+
+pub fn address_space_bounds() -> std::ops::Range<usize> {
+  // The native system information reports the bounds of a native process,
+  //   which differ from those of a WOW64 process.
+  let mut info = MaybeUninit::<SYSTEM_INFO>::uninit();
+  let info = unsafe {
+    GetSystemInfo(info.as_mut_ptr());
+    info.assume_init()
+  };
+
+  let lower = info.lpMinimumApplicationAddress as usize;
+  lower..(info.lpMaximumApplicationAddress as usize).saturating_add(1)
+}
+
+#[inline]
+pub fn current_thread_stack() -> Option<std::ops::Range<usize>> {
+  let (mut low, mut high) = (0, 0);
+  unsafe { GetCurrentThreadStackLimits(&mut low, &mut high) };
+  Some(low..high)
+}
+
+pub fn is_out_of_memory(error: &Error) -> bool {
+  use winapi::shared::winerror::{
+    ERROR_COMMITMENT_LIMIT, ERROR_NOT_ENOUGH_MEMORY, ERROR_OUTOFMEMORY,
+  };
+
+  matches!(
+    error.raw_os_error().map(|code| code as u32),
+    Some(ERROR_NOT_ENOUGH_MEMORY | ERROR_OUTOFMEMORY | ERROR_COMMITMENT_LIMIT)
+  )
+}
+
+pub unsafe fn alloc(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+  options: &AllocOptions,
+) -> Result<*const ()> {
+  let (allocation_type, protection) = allocation_flags(protection, options)?;
+  let allocation = VirtualAlloc(base as PVOID, size, allocation_type, protection);
+
+  if allocation.is_null() {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  Ok(allocation as *const ())
+}
+
+/// Returns the allocation type and page protection of an allocation.
+fn allocation_flags(protection: Protection, options: &AllocOptions) -> Result<(DWORD, DWORD)> {
+  let allocation_type = if options.no_reserve {
+    MEM_RESERVE
+  } else {
+    MEM_COMMIT | MEM_RESERVE
+  };
+
+  let modifiers = match options.cache_mode {
+    CacheMode::Default => 0,
+    CacheMode::WriteCombine => winapi::um::winnt::PAGE_WRITECOMBINE,
+    CacheMode::Uncached => winapi::um::winnt::PAGE_NOCACHE,
+    CacheMode::WriteThrough => return Err(Error::UnsupportedOperation),
+  };
+
+  Ok((allocation_type, protection.to_native() | modifiers))
+}
+
+/// Allocates memory at a specific address, without replacing any existing
+/// mapping within the range.
+pub unsafe fn alloc_exclusive(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+  options: &AllocOptions,
+) -> Result<*const ()> {
+  // `VirtualAlloc` never replaces existing allocations
+  alloc(base, size, protection, options)
+}
+
+pub unsafe fn alloc_aligned(
+  size: usize,
+  align: usize,
+  protection: Protection,
+  options: &AllocOptions,
+) -> Result<*const ()> {
+  // Allocations are always aligned to the allocation granularity
+  if align <= system_info().dwAllocationGranularity as usize {
+    return alloc(std::ptr::null(), size, protection, options);
+  }
+
+  if let Some(extended) = extended() {
+    let (allocation_type, protection) = allocation_flags(protection, options)?;
+    let mut requirements = MemAddressRequirements {
+      lowest_starting_address: std::ptr::null_mut(),
+      highest_ending_address: std::ptr::null_mut(),
+      alignment: align,
+    };
+    let mut parameter = MemExtendedParameter::address_requirements(&mut requirements);
+
+    let allocation = (extended.virtual_alloc2)(
+      GetCurrentProcess(),
+      std::ptr::null_mut(),
+      size,
+      allocation_type,
+      protection,
+      &mut parameter,
+      1,
+    );
+
+    if allocation.is_null() {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+    return Ok(allocation as *const ());
+  }
+
+  let padded_size = size
+    .checked_add(align)
+    .ok_or(Error::InvalidParameter("size"))?;
+
+  // Without `VirtualAlloc2`, Windows does not support releasing parts of a reservation. Instead, an
+  // oversized region is reserved to locate an aligned address, which is then
+  // released and allocated anew. Since another thread may claim the address in
+  // the meantime, this is attempted more than once.
+  for _ in 0..ALIGNED_ALLOC_ATTEMPTS {
+    let padded_base = VirtualAlloc(
+      std::ptr::null_mut(),
+      padded_size,
+      MEM_RESERVE,
+      winapi::um::winnt::PAGE_NOACCESS,
+    );
+
+    if padded_base.is_null() {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+
+    free(padded_base as *const (), padded_size)?;
+
+    let base = (padded_base as usize + align - 1) & !(align - 1);
+    if let Ok(allocation) = alloc(base as *const (), size, protection, options) {
+      return Ok(allocation);
+    }
+  }
+
+  Err(Error::SystemCall(io::Error::last_os_error()))
+}
+
+const ALIGNED_ALLOC_ATTEMPTS: usize = 8;
+
+pub unsafe fn alloc_mirrored(size: usize) -> Result<*const ()> {
+  use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+  use winapi::um::memoryapi::CreateFileMappingW;
+
+  let mapping = CreateFileMappingW(
+    INVALID_HANDLE_VALUE,
+    std::ptr::null_mut(),
+    winapi::um::winnt::PAGE_READWRITE,
+    (size as u64 >> 32) as u32,
+    size as u32,
+    std::ptr::null(),
+  );
+
+  if mapping.is_null() {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  let result = match extended() {
+    Some(extended) => map_placeholder_views(extended, mapping, size),
+    None => map_views(mapping, size),
+  };
+
+  // The views keep the mapping alive, until they are unmapped
+  CloseHandle(mapping);
+  result
+}
+
+/// Maps two adjacent views of a file mapping, using `MapViewOfFile3` to
+/// replace the halves of a reserved placeholder.
+unsafe fn map_placeholder_views(
+  extended: &Extended,
+  mapping: HANDLE,
+  size: usize,
+) -> Result<*const ()> {
+  use winapi::um::memoryapi::UnmapViewOfFile;
+
+  let base = (extended.virtual_alloc2)(
+    GetCurrentProcess(),
+    std::ptr::null_mut(),
+    size * 2,
+    MEM_RESERVE | MEM_RESERVE_PLACEHOLDER,
+    winapi::um::winnt::PAGE_NOACCESS,
+    std::ptr::null_mut(),
+    0,
+  );
+
+  if base.is_null() {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  // The placeholder is split in two, so that each half can be replaced
+  let base = base.cast::<u8>();
+  if VirtualFree(base.cast(), size, MEM_RELEASE | MEM_PRESERVE_PLACEHOLDER) == 0 {
+    let error = io::Error::last_os_error();
+    VirtualFree(base.cast(), 0, MEM_RELEASE);
+    return Err(Error::SystemCall(error));
+  }
+
+  let map_view = |address: *mut u8| {
+    (extended.map_view_of_file3)(
+      mapping,
+      GetCurrentProcess(),
+      address.cast(),
+      0,
+      size,
+      MEM_REPLACE_PLACEHOLDER,
+      winapi::um::winnt::PAGE_READWRITE,
+      std::ptr::null_mut(),
+      0,
+    )
+  };
+
+  let first = map_view(base);
+  if first.is_null() {
+    let error = io::Error::last_os_error();
+    VirtualFree(base.cast(), 0, MEM_RELEASE);
+    VirtualFree(base.add(size).cast(), 0, MEM_RELEASE);
+    return Err(Error::SystemCall(error));
+  }
+
+  if map_view(base.add(size)).is_null() {
+    let error = io::Error::last_os_error();
+    UnmapViewOfFile(first);
+    VirtualFree(base.add(size).cast(), 0, MEM_RELEASE);
+    return Err(Error::SystemCall(error));
+  }
+
+  Ok(base as *const ())
+}
+
+/// Maps two adjacent views of a file mapping, at an address located by
+/// reserving (and releasing) a range with room for both.
+unsafe fn map_views(mapping: HANDLE, size: usize) -> Result<*const ()> {
+  use winapi::um::memoryapi::{MapViewOfFileEx, UnmapViewOfFile, FILE_MAP_ALL_ACCESS};
+
+  // Without placeholders, views cannot be mapped over a reservation. Instead,
+  // an address with room for both views is located by reserving it, which is
+  // then released and mapped anew. Since another thread may claim the address
+  // in the meantime, this is attempted more than once.
+  let mut error = io::Error::last_os_error();
+  for _ in 0..ALIGNED_ALLOC_ATTEMPTS {
+    let base = VirtualAlloc(
+      std::ptr::null_mut(),
+      size * 2,
+      MEM_RESERVE,
+      winapi::um::winnt::PAGE_NOACCESS,
+    );
+
+    if base.is_null() {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+
+    free(base as *const (), size * 2)?;
+    let base = base.cast::<u8>();
+
+    let first = MapViewOfFileEx(mapping, FILE_MAP_ALL_ACCESS, 0, 0, size, base.cast());
+    if first.is_null() {
+      error = io::Error::last_os_error();
+      continue;
+    }
+
+    let second = MapViewOfFileEx(
+      mapping,
+      FILE_MAP_ALL_ACCESS,
+      0,
+      0,
+      size,
+      base.add(size).cast(),
+    );
+    if second.is_null() {
+      error = io::Error::last_os_error();
+      UnmapViewOfFile(first);
+      continue;
+    }
+
+    return Ok(base as *const ());
+  }
+
+  Err(Error::SystemCall(error))
+}
+
+// The extended memory management functions of Windows 10 (version 1803) and
+//   later, which are resolved at runtime since `winapi` does not declare them.
+const MEM_RESERVE_PLACEHOLDER: DWORD = 0x0004_0000;
+const MEM_REPLACE_PLACEHOLDER: DWORD = 0x0000_4000;
+const MEM_PRESERVE_PLACEHOLDER: DWORD = 0x0000_0002;
+const MEM_EXTENDED_PARAMETER_ADDRESS_REQUIREMENTS: u64 = 1;
+
+#[repr(C)]
+struct MemAddressRequirements {
+  lowest_starting_address: PVOID,
+  highest_ending_address: PVOID,
+  alignment: SIZE_T,
+}
+
+#[repr(C, align(8))]
+struct MemExtendedParameter {
+  // The lowest 8 bits are the type, the remaining bits are reserved
+  kind: u64,
+  // A union of the parameter's possible values
+  value: u64,
+}
+
+impl MemExtendedParameter {
+  fn address_requirements(requirements: &mut MemAddressRequirements) -> Self {
+    Self {
+      kind: MEM_EXTENDED_PARAMETER_ADDRESS_REQUIREMENTS,
+      value: requirements as *mut MemAddressRequirements as usize as u64,
+    }
+  }
+}
+
+type VirtualAlloc2 = unsafe extern "system" fn(
+  HANDLE,
+  PVOID,
+  SIZE_T,
+  ULONG,
+  ULONG,
+  *mut MemExtendedParameter,
+  ULONG,
+) -> PVOID;
+
+type MapViewOfFile3 = unsafe extern "system" fn(
+  HANDLE,
+  HANDLE,
+  PVOID,
+  u64,
+  SIZE_T,
+  ULONG,
+  ULONG,
+  *mut MemExtendedParameter,
+  ULONG,
+) -> PVOID;
+
+struct Extended {
+  virtual_alloc2: VirtualAlloc2,
+  map_view_of_file3: MapViewOfFile3,
+}
+
+/// Returns the extended memory management functions, if they're available.
+fn extended() -> Option<&'static Extended> {
+  static EXTENDED: OnceLock<Option<Extended>> = OnceLock::new();
+
+  EXTENDED
+    .get_or_init(|| unsafe {
+      let module = GetModuleHandleA(b"kernelbase.dll\0".as_ptr().cast());
+      if module.is_null() {
+        return None;
+      }
+
+      let virtual_alloc2 = GetProcAddress(module, b"VirtualAlloc2\0".as_ptr().cast());
+      let map_view_of_file3 = GetProcAddress(module, b"MapViewOfFile3\0".as_ptr().cast());
+      if virtual_alloc2.is_null() || map_view_of_file3.is_null() {
+        return None;
+      }
+
+      Some(Extended {
+        virtual_alloc2: std::mem::transmute::<FARPROC, VirtualAlloc2>(virtual_alloc2),
+        map_view_of_file3: std::mem::transmute::<FARPROC, MapViewOfFile3>(map_view_of_file3),
+      })
+    })
+    .as_ref()
+}
+
+pub unsafe fn seal(_base: *const (), _size: usize) -> Result<()> {
+  Err(Error::UnsupportedOperation)
+}
+
+pub unsafe fn set_inherit(_base: *const (), _size: usize, _inherit: bool) -> Result<()> {
+  // Windows has no notion of forking a process
+  Err(Error::UnsupportedOperation)
+}
+
+pub unsafe fn free(base: *const (), size: usize) -> Result<()> {
+  let mut info: MEMORY_BASIC_INFORMATION = std::mem::zeroed();
+  let bytes = VirtualQuery(
+    base as winapi::um::winnt::PVOID,
+    &mut info,
+    size_of::<MEMORY_BASIC_INFORMATION>() as winapi::shared::basetsd::SIZE_T,
+  );
+
+  // Mirrored allocations consist of views, which must be unmapped one by one
+  if bytes != 0 && info.Type == winapi::um::winnt::MEM_MAPPED {
+    return free_views(base, size);
+  }
+
+  match VirtualFree(base as winapi::um::winnt::PVOID, 0, MEM_RELEASE) {
+    winapi::shared::minwindef::FALSE => Err(Error::SystemCall(io::Error::last_os_error())),
+    _ => Ok(()),
+  }
+}
+
+unsafe fn free_views(base: *const (), size: usize) -> Result<()> {
+  let end = base as usize + size;
+  let mut address = base as usize;
+  let mut info: MEMORY_BASIC_INFORMATION = std::mem::zeroed();
+
+  while address < end {
+    let bytes = VirtualQuery(
+      address as winapi::um::winnt::PVOID,
+      &mut info,
+      size_of::<MEMORY_BASIC_INFORMATION>() as winapi::shared::basetsd::SIZE_T,
+    );
+
+    if bytes == 0 {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+
+    if info.Type == winapi::um::winnt::MEM_MAPPED
+      && winapi::um::memoryapi::UnmapViewOfFile(info.AllocationBase)
+        == winapi::shared::minwindef::FALSE
+    {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+
+    address = (info.BaseAddress as usize).saturating_add(info.RegionSize);
+  }
+
+  Ok(())
+}
+
+pub unsafe fn commit(base: *const (), size: usize, guard: bool) -> Result<()> {
+  let protection = if guard {
+    winapi::um::winnt::PAGE_READWRITE | winapi::um::winnt::PAGE_GUARD
+  } else {
+    winapi::um::winnt::PAGE_READWRITE
+  };
+
   let allocation = VirtualAlloc(
     base as winapi::um::winnt::PVOID,
     size,
-    MEM_COMMIT | MEM_RESERVE,
-    protection.to_native(),
+    MEM_COMMIT,
+    protection,
   );
 
   if allocation.is_null() {
     return Err(Error::SystemCall(io::Error::last_os_error()));
   }
+  Ok(())
+}
 
-  Ok(allocation as *const ())
+pub fn resident_size() -> Result<usize> {
+  let mut counters = MaybeUninit::<PROCESS_MEMORY_COUNTERS>::uninit();
+  let result = unsafe {
+    GetProcessMemoryInfo(
+      GetCurrentProcess(),
+      counters.as_mut_ptr(),
+      size_of::<PROCESS_MEMORY_COUNTERS>() as winapi::shared::minwindef::DWORD,
+    )
+  };
+
+  match result {
+    winapi::shared::minwindef::FALSE => Err(Error::SystemCall(io::Error::last_os_error())),
+    _ => Ok(unsafe { counters.assume_init() }.WorkingSetSize),
+  }
 }
 
-pub unsafe fn free(base: *const (), _size: usize) -> Result<()> {
-  match VirtualFree(base as winapi::um::winnt::PVOID, 0, MEM_RELEASE) {
+pub unsafe fn flush(base: *const (), size: usize, _asynchronous: bool) -> Result<()> {
+  // Waiting for the data to reach the disk requires the file's handle (i.e.
+  // `FlushFileBuffers`), therefore the flush is always initiated asynchronously.
+  match FlushViewOfFile(base as winapi::um::winnt::PVOID, size) {
     winapi::shared::minwindef::FALSE => Err(Error::SystemCall(io::Error::last_os_error())),
     _ => Ok(()),
   }
@@ -117,6 +584,52 @@ pub unsafe fn protect(base: *const (), size: usize, protection: Protection) -> R
   }
 }
 
+pub unsafe fn protect_preserve_flags(
+  base: *const (),
+  size: usize,
+  protection: Protection,
+) -> Result<()> {
+  const MODIFIERS: winapi::shared::minwindef::DWORD = winapi::um::winnt::PAGE_GUARD
+    | winapi::um::winnt::PAGE_NOCACHE
+    | winapi::um::winnt::PAGE_WRITECOMBINE;
+
+  let end = base as usize + size;
+  let mut address = base as usize;
+  let mut info: MEMORY_BASIC_INFORMATION = std::mem::zeroed();
+
+  // Each region may have different modifiers, so they are protected one by one
+  while address < end {
+    let bytes = VirtualQuery(
+      address as winapi::um::winnt::PVOID,
+      &mut info,
+      size_of::<MEMORY_BASIC_INFORMATION>() as winapi::shared::basetsd::SIZE_T,
+    );
+
+    if bytes == 0 {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+
+    let region_end = min(
+      (info.BaseAddress as usize).saturating_add(info.RegionSize),
+      end,
+    );
+    let result = VirtualProtect(
+      address as winapi::um::winnt::PVOID,
+      (region_end - address) as winapi::shared::basetsd::SIZE_T,
+      protection.to_native() | (info.Protect & MODIFIERS),
+      &mut 0,
+    );
+
+    if result == winapi::shared::minwindef::FALSE {
+      return Err(Error::SystemCall(io::Error::last_os_error()));
+    }
+
+    address = region_end;
+  }
+
+  Ok(())
+}
+
 pub fn lock(base: *const (), size: usize) -> Result<()> {
   let result = unsafe {
     VirtualLock(
@@ -126,7 +639,11 @@ pub fn lock(base: *const (), size: usize) -> Result<()> {
   };
 
   if result == winapi::shared::minwindef::FALSE {
-    Err(Error::SystemCall(io::Error::last_os_error()))
+    let error = io::Error::last_os_error();
+    match error.raw_os_error().map(|code| code as u32) {
+      Some(winapi::shared::winerror::ERROR_WORKING_SET_QUOTA) => Err(Error::LockLimitExceeded),
+      _ => Err(Error::SystemCall(error)),
+    }
   } else {
     Ok(())
   }
@@ -147,6 +664,67 @@ pub fn unlock(base: *const (), size: usize) -> Result<()> {
   }
 }
 
+pub fn flush_icache(base: *const (), size: usize) {
+  let result = unsafe {
+    FlushInstructionCache(
+      GetCurrentProcess(),
+      base as winapi::um::winnt::PVOID,
+      size as winapi::shared::basetsd::SIZE_T,
+    )
+  };
+  debug_assert_ne!(result, winapi::shared::minwindef::FALSE, "flushing icache");
+}
+
+pub fn is_resident(base: *const (), size: usize) -> Result<bool> {
+  resident_pages(base, size).map(|pages| pages == size / page_size())
+}
+
+pub fn resident_pages(base: *const (), size: usize) -> Result<usize> {
+  let page_size = page_size();
+  let mut pages = (0..size / page_size)
+    .map(|index| {
+      let mut info: PSAPI_WORKING_SET_EX_INFORMATION = unsafe { std::mem::zeroed() };
+      info.VirtualAddress = (base as usize + index * page_size) as winapi::um::winnt::PVOID;
+      info
+    })
+    .collect::<Vec<_>>();
+
+  let result = unsafe {
+    QueryWorkingSetEx(
+      GetCurrentProcess(),
+      pages.as_mut_ptr().cast(),
+      (pages.len() * size_of::<PSAPI_WORKING_SET_EX_INFORMATION>())
+        as winapi::shared::minwindef::DWORD,
+    )
+  };
+
+  if result == winapi::shared::minwindef::FALSE {
+    return Err(Error::SystemCall(io::Error::last_os_error()));
+  }
+
+  Ok(
+    pages
+      .iter()
+      .filter(|page| page.VirtualAttributes.Valid() == 1)
+      .count(),
+  )
+}
+
+#[cfg(feature = "async")]
+pub unsafe fn prefetch(base: *const (), size: usize) -> Result<()> {
+  use winapi::um::memoryapi::{PrefetchVirtualMemory, WIN32_MEMORY_RANGE_ENTRY};
+
+  let mut range = WIN32_MEMORY_RANGE_ENTRY {
+    VirtualAddress: base as winapi::um::winnt::PVOID,
+    NumberOfBytes: size,
+  };
+
+  match PrefetchVirtualMemory(GetCurrentProcess(), 1, &mut range, 0) {
+    winapi::shared::minwindef::FALSE => Err(Error::SystemCall(io::Error::last_os_error())),
+    _ => Ok(()),
+  }
+}
+
 fn system_info() -> &'static SYSTEM_INFO {
   static INIT: Once = Once::new();
   static mut INFO: MaybeUninit<SYSTEM_INFO> = MaybeUninit::uninit();
@@ -158,35 +736,126 @@ fn system_info() -> &'static SYSTEM_INFO {
 }
 
 impl Protection {
-  fn from_native(protection: winapi::shared::minwindef::DWORD) -> Self {
-    // Ignore unsupported flags (TODO: Preserve this information?)
+  /// Converts Windows `PAGE_*` protection flags into a protection.
+  ///
+  /// This is only available on Windows, for interoperating with code that
+  /// uses the WinAPI directly. Any modifiers (i.e. `PAGE_GUARD`,
+  /// `PAGE_NOCACHE` and `PAGE_WRITECOMBINE`) are ignored, and the copy-on-write
+  /// constants are treated as writable. If the flags do not contain exactly one
+  /// `PAGE_*` protection constant, `None` is returned.
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use region::Protection;
+  /// use winapi::um::winnt::{PAGE_EXECUTE_READ, PAGE_GUARD};
+  ///
+  /// let protection = Protection::from_win_flags(PAGE_EXECUTE_READ | PAGE_GUARD);
+  /// assert_eq!(protection, Some(Protection::READ_EXECUTE));
+  /// ```
+  #[inline]
+  pub fn from_win_flags(flags: u32) -> Option<Self> {
     let ignored = winapi::um::winnt::PAGE_GUARD
       | winapi::um::winnt::PAGE_NOCACHE
       | winapi::um::winnt::PAGE_WRITECOMBINE;
 
-    match protection & !ignored {
-      winapi::um::winnt::PAGE_EXECUTE => Protection::EXECUTE,
-      winapi::um::winnt::PAGE_EXECUTE_READ => Protection::READ_EXECUTE,
-      winapi::um::winnt::PAGE_EXECUTE_READWRITE => Protection::READ_WRITE_EXECUTE,
-      winapi::um::winnt::PAGE_EXECUTE_WRITECOPY => Protection::READ_WRITE_EXECUTE,
-      winapi::um::winnt::PAGE_NOACCESS => Protection::NONE,
-      winapi::um::winnt::PAGE_READONLY => Protection::READ,
-      winapi::um::winnt::PAGE_READWRITE => Protection::READ_WRITE,
-      winapi::um::winnt::PAGE_WRITECOPY => Protection::READ_WRITE,
-      _ => unreachable!("Protection: 0x{:X}", protection),
+    match flags & !ignored {
+      winapi::um::winnt::PAGE_EXECUTE => Some(Protection::EXECUTE),
+      winapi::um::winnt::PAGE_EXECUTE_READ => Some(Protection::READ_EXECUTE),
+      winapi::um::winnt::PAGE_EXECUTE_READWRITE => Some(Protection::READ_WRITE_EXECUTE),
+      winapi::um::winnt::PAGE_EXECUTE_WRITECOPY => Some(Protection::READ_WRITE_EXECUTE),
+      winapi::um::winnt::PAGE_NOACCESS => Some(Protection::NONE),
+      winapi::um::winnt::PAGE_READONLY => Some(Protection::READ),
+      winapi::um::winnt::PAGE_READWRITE => Some(Protection::READ_WRITE),
+      winapi::um::winnt::PAGE_WRITECOPY => Some(Protection::READ_WRITE),
+      _ => None,
     }
   }
 
-  pub(crate) fn to_native(self) -> winapi::shared::minwindef::DWORD {
+  /// Converts a protection into its Windows `PAGE_*` protection constant.
+  ///
+  /// This is only available on Windows. Since Windows lacks write-only pages,
+  /// a writable protection is always readable as well (e.g.
+  /// [`Protection::WRITE`] is converted to `PAGE_READWRITE`).
+  ///
+  /// # Examples
+  ///
+  /// ```
+  /// use region::Protection;
+  /// use winapi::um::winnt::PAGE_READWRITE;
+  ///
+  /// assert_eq!(Protection::READ_WRITE.to_win_flags(), PAGE_READWRITE);
+  /// ```
+  #[inline]
+  pub fn to_win_flags(self) -> u32 {
     match self {
       Protection::NONE => winapi::um::winnt::PAGE_NOACCESS,
       Protection::READ => winapi::um::winnt::PAGE_READONLY,
       Protection::EXECUTE => winapi::um::winnt::PAGE_EXECUTE,
       Protection::READ_EXECUTE => winapi::um::winnt::PAGE_EXECUTE_READ,
-      Protection::READ_WRITE => winapi::um::winnt::PAGE_READWRITE,
+      Protection::WRITE | Protection::READ_WRITE => winapi::um::winnt::PAGE_READWRITE,
       Protection::READ_WRITE_EXECUTE => winapi::um::winnt::PAGE_EXECUTE_READWRITE,
       Protection::WRITE_EXECUTE => winapi::um::winnt::PAGE_EXECUTE_READWRITE,
       _ => unreachable!("Protection: {:?}", self),
     }
   }
+
+  fn from_native(protection: winapi::shared::minwindef::DWORD) -> Self {
+    // Ignore unsupported flags (TODO: Preserve this information?)
+    Self::from_win_flags(protection)
+      .unwrap_or_else(|| unreachable!("Protection: 0x{:X}", protection))
+  }
+
+  pub(crate) fn to_native(self) -> winapi::shared::minwindef::DWORD {
+    self.to_win_flags()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use winapi::um::winnt::{
+    PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_GUARD,
+    PAGE_NOACCESS, PAGE_NOCACHE, PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOPY,
+  };
+
+  #[test]
+  fn win_flags_round_trip_each_protection_constant() {
+    let constants = [
+      (PAGE_NOACCESS, Protection::NONE),
+      (PAGE_READONLY, Protection::READ),
+      (PAGE_READWRITE, Protection::READ_WRITE),
+      (PAGE_EXECUTE, Protection::EXECUTE),
+      (PAGE_EXECUTE_READ, Protection::READ_EXECUTE),
+      (PAGE_EXECUTE_READWRITE, Protection::READ_WRITE_EXECUTE),
+    ];
+
+    for &(flags, protection) in &constants {
+      assert_eq!(Protection::from_win_flags(flags), Some(protection));
+      assert_eq!(protection.to_win_flags(), flags);
+    }
+
+    // Copy-on-write is not preserved, since it has no equivalent protection
+    let copy_on_write = [
+      (PAGE_WRITECOPY, PAGE_READWRITE),
+      (PAGE_EXECUTE_WRITECOPY, PAGE_EXECUTE_READWRITE),
+    ];
+
+    for &(flags, writable) in &copy_on_write {
+      let protection = Protection::from_win_flags(flags).unwrap();
+      assert_eq!(protection.to_win_flags(), writable);
+    }
+  }
+
+  #[test]
+  fn win_flags_ignore_modifiers_and_reject_invalid_flags() {
+    let flags = PAGE_READONLY | PAGE_GUARD | PAGE_NOCACHE;
+    assert_eq!(Protection::from_win_flags(flags), Some(Protection::READ));
+    assert_eq!(
+      Protection::from_win_flags(PAGE_READONLY | PAGE_READWRITE),
+      None
+    );
+    assert_eq!(Protection::from_win_flags(0), None);
+    assert_eq!(Protection::WRITE.to_win_flags(), PAGE_READWRITE);
+  }
 }
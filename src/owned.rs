@@ -0,0 +1,140 @@
+use std::ops::Range;
+
+#[cfg(not(target_os = "haiku"))]
+use std::{collections::BTreeMap, sync::Mutex};
+
+// The registry of all live allocations, mapping their base address to their
+//   size. Haiku already has one (see `ALLPAGES`), hence this only exists for
+//   the other backends.
+#[cfg(not(target_os = "haiku"))]
+static ALLOCATIONS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Returns the address ranges of all live allocations created by the crate.
+///
+/// This includes every [`Allocation`](crate::Allocation) that has not yet
+/// been dropped, including any that have been leaked (e.g. using
+/// [`std::mem::forget`]). Since the allocations remain owned by their
+/// handles, only their address ranges are returned. The ranges are sorted by
+/// address, except on Haiku, where they are in no particular order.
+///
+/// This function is only available with the `tracking` feature, since every
+/// allocation and deallocation has to synchronize on a global registry.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let memory = region::alloc(1, Protection::READ_WRITE)?;
+/// assert!(region::owned_allocations().contains(&memory.as_range()));
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn owned_allocations() -> Vec<Range<usize>> {
+  #[cfg(target_os = "haiku")]
+  return crate::os::owned_allocations();
+
+  #[cfg(not(target_os = "haiku"))]
+  return allocations()
+    .iter()
+    .map(|(&base, &size)| base..base + size)
+    .collect();
+}
+
+/// Returns whether an address is within any live allocation created by the
+/// crate.
+///
+/// This allows distinguishing the crate's own allocations from foreign
+/// mappings (e.g. the heap, the stack or the executable's segments), using
+/// the same registry as [`owned_allocations`].
+///
+/// This function is only available with the `tracking` feature.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let memory = region::alloc(1, Protection::READ_WRITE)?;
+/// assert!(region::is_owned(memory.as_ptr()));
+/// assert!(!region::is_owned(&memory as *const _ as *const ()));
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn is_owned(address: *const ()) -> bool {
+  let address = address as usize;
+
+  #[cfg(target_os = "haiku")]
+  return crate::os::owned_allocations()
+    .iter()
+    .any(|range| range.contains(&address));
+
+  #[cfg(not(target_os = "haiku"))]
+  return matches!(
+    allocations().range(..=address).next_back(),
+    Some((&base, &size)) if address - base < size
+  );
+}
+
+/// Registers an allocation by its base address.
+#[cfg(not(target_os = "haiku"))]
+pub(crate) fn register(base: *const (), size: usize) {
+  allocations().insert(base as usize, size);
+}
+
+/// Removes an allocation from the registry by its base address.
+#[cfg(not(target_os = "haiku"))]
+pub(crate) fn unregister(base: *const ()) {
+  allocations().remove(&(base as usize));
+}
+
+#[cfg(not(target_os = "haiku"))]
+fn allocations() -> std::sync::MutexGuard<'static, BTreeMap<usize, usize>> {
+  ALLOCATIONS
+    .lock()
+    .unwrap_or_else(|error| error.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{page, Protection, Result};
+
+  #[test]
+  fn owned_allocations_tracks_live_allocations() -> Result<()> {
+    // An unusual size makes it unlikely for another test to reuse the range
+    let first = crate::alloc(page::size() * 13, Protection::READ_WRITE)?;
+    let second = crate::alloc(1, Protection::READ)?;
+    let (first_range, second_range) = (first.as_range(), second.as_range());
+
+    // Other tests may allocate concurrently, hence only membership is checked
+    let owned = owned_allocations();
+    assert!(owned.contains(&first_range));
+    assert!(owned.contains(&second_range));
+
+    drop(first);
+    let owned = owned_allocations();
+    assert!(!owned.contains(&first_range));
+    assert!(owned.contains(&second_range));
+    Ok(())
+  }
+
+  #[test]
+  fn is_owned_distinguishes_crate_allocations() -> Result<()> {
+    let memory = crate::alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let base = memory.as_ptr::<u8>();
+
+    assert!(is_owned(base.cast()));
+    assert!(is_owned(unsafe { base.add(memory.len() - 1) }.cast()));
+    assert!(!is_owned(memory.as_ptr_range::<u8>().end.cast()));
+    assert!(!is_owned(is_owned as *const ()));
+
+    drop(memory);
+    assert!(!is_owned(base.cast()));
+    Ok(())
+  }
+}
@@ -1,8 +1,15 @@
 //! Page related functions.
 
 use crate::os;
+#[cfg(test)]
+use std::cell::Cell;
 use std::sync::Once;
 
+#[cfg(test)]
+thread_local! {
+  static SIZE_OVERRIDE: Cell<usize> = const { Cell::new(0) };
+}
+
 /// Returns the operating system's page size.
 ///
 /// This function uses an internally cached page size, and can be called
@@ -16,6 +23,12 @@ use std::sync::Once;
 /// ```
 #[inline]
 pub fn size() -> usize {
+  #[cfg(test)]
+  match SIZE_OVERRIDE.with(Cell::get) {
+    0 => (),
+    size => return size,
+  }
+
   static INIT: Once = Once::new();
   static mut PAGE_SIZE: usize = 0;
 
@@ -25,8 +38,22 @@ pub fn size() -> usize {
   }
 }
 
+/// Overrides the page size of the current thread, so that the rounding logic
+/// can be exercised against page sizes other than the system's. A size of zero
+/// restores the system's page size.
+///
+/// Only the rounding is affected; the override must not be active whilst
+/// interacting with the operating system.
+#[cfg(test)]
+pub(crate) fn set_size_for_testing(size: usize) {
+  assert!(size == 0 || size.is_power_of_two(), "page size: {}", size);
+  SIZE_OVERRIDE.with(|cell| cell.set(size));
+}
+
 /// Rounds an address down to its closest page boundary.
 ///
+/// This never overflows, and the result is never greater than the address.
+///
 /// # Examples
 ///
 /// ```
@@ -42,6 +69,13 @@ pub fn floor<T>(address: *const T) -> *const T {
 
 /// Rounds an address up to its closest page boundary.
 ///
+/// The result saturates instead of overflowing; an address beyond the highest
+/// page boundary of the address space (i.e. within the last page) has no
+/// boundary above it, thus the highest page boundary is returned. Otherwise,
+/// the result is never less than the address. The same policy is applied to
+/// ranges that would exceed the address space throughout the crate (e.g. by
+/// [`query_range`](crate::query_range)).
+///
 /// # Examples
 ///
 /// ```
@@ -74,14 +108,77 @@ mod tests {
   #[test]
   fn page_rounding_works() {
     let pz = size();
-    let point = 1 as *const ();
+    let point = std::ptr::dangling::<u8>().cast::<()>();
 
     assert_eq!(floor(point) as usize, 0);
     assert_eq!(floor(pz as *const ()) as usize, pz);
-    assert_eq!(floor(usize::max_value() as *const ()) as usize % pz, 0);
+    assert_eq!(floor(usize::MAX as *const ()) as usize % pz, 0);
 
     assert_eq!(ceil(point) as usize, pz);
     assert_eq!(ceil(pz as *const ()) as usize, pz);
-    assert_eq!(ceil(usize::max_value() as *const ()) as usize % pz, 0);
+    assert_eq!(ceil(usize::MAX as *const ()) as usize % pz, 0);
+  }
+
+  #[test]
+  fn page_rounding_saturates_within_last_page() {
+    let pz = size();
+    let last = usize::MAX - (pz - 1);
+
+    assert_eq!(ceil(last as *const ()) as usize, last);
+    assert_eq!(ceil((last + 1) as *const ()) as usize, last);
+    assert_eq!(ceil(usize::MAX as *const ()) as usize, last);
+    assert_eq!(floor(usize::MAX as *const ()) as usize, last);
+  }
+
+  #[test]
+  fn page_rounding_bounds_random_addresses() {
+    // A xorshift generator, since the exact inputs only need to be arbitrary
+    let mut state = 0x2545_F491_4F6C_DD1D_u64;
+    let mut random = move || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      state as usize
+    };
+
+    for &pz in &[0x1000, 0x4000, 0x10000] {
+      set_size_for_testing(pz);
+      let last = usize::MAX - (pz - 1);
+
+      for _ in 0..10_000 {
+        let address = random();
+        let (lower, upper) = (
+          floor(address as *const ()) as usize,
+          ceil(address as *const ()) as usize,
+        );
+
+        assert_eq!(lower % pz, 0);
+        assert_eq!(upper % pz, 0);
+        assert!(lower <= address);
+        assert!(address <= upper || upper == last);
+        assert!(upper - lower <= pz);
+      }
+    }
+
+    set_size_for_testing(0);
+  }
+
+  #[test]
+  fn page_rounding_works_for_simulated_page_sizes() {
+    let (system, point) = (size(), std::ptr::dangling::<u8>().cast::<()>());
+
+    for &pz in &[0x1000, 0x4000, 0x10000] {
+      set_size_for_testing(pz);
+      assert_eq!(size(), pz);
+
+      assert_eq!(floor((pz - 1) as *const ()) as usize, 0);
+      assert_eq!(floor((pz * 2 + 1) as *const ()) as usize, pz * 2);
+      assert_eq!(ceil(point) as usize, pz);
+      assert_eq!(ceil((pz + 1) as *const ()) as usize, pz * 2);
+      assert_eq!(ceil(usize::MAX as *const ()) as usize % pz, 0);
+    }
+
+    set_size_for_testing(0);
+    assert_eq!(size(), system);
   }
 }
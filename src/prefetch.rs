@@ -0,0 +1,318 @@
+use crate::{os, page, Allocation, Error, Result};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+// The number of consecutive polls without any further pages brought in, after
+//   which a prefetch is considered stalled.
+const STALLED_POLLS: usize = 8;
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+const MAX_INTERVAL: Duration = Duration::from_millis(64);
+
+// The prefetches that are in progress, polled by a single worker thread,
+//   which is spawned on first use.
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+  requests: Vec::new(),
+  worker: false,
+});
+static QUEUED: Condvar = Condvar::new();
+
+struct Queue {
+  requests: Vec<Arc<Request>>,
+  worker: bool,
+}
+
+/// A future resolving once a prefetch, issued by
+/// [`Allocation::prefetch_async`], has completed.
+///
+/// The allocation is borrowed for the lifetime of the future, and once the
+/// future is dropped, the range is no longer polled.
+pub(crate) struct Prefetch<'a> {
+  request: Arc<Request>,
+  allocation: PhantomData<&'a Allocation>,
+}
+
+impl Prefetch<'_> {
+  /// Advises the OS to read in a page-aligned range, on the calling thread,
+  /// and polls its residency on the worker thread until it's complete.
+  pub(crate) fn new(address: *const (), size: usize) -> Self {
+    let request = Arc::new(Request {
+      address: address as usize,
+      size,
+      progress: Default::default(),
+    });
+
+    let issued = unsafe { os::prefetch(address, size) }.and_then(|_| {
+      if request.poll(Instant::now()) {
+        Ok(())
+      } else {
+        enqueue(request.clone())
+      }
+    });
+
+    if let Err(error) = issued {
+      request.lock().outcome = Some(Err(error));
+    }
+    Self::with_request(request)
+  }
+
+  /// Returns a prefetch that has already completed.
+  pub(crate) fn completed(result: Result<()>) -> Self {
+    let request = Request {
+      address: 0,
+      size: 0,
+      progress: Mutex::new(Progress {
+        outcome: Some(result),
+        ..Default::default()
+      }),
+    };
+    Self::with_request(Arc::new(request))
+  }
+
+  fn with_request(request: Arc<Request>) -> Self {
+    Self {
+      request,
+      allocation: PhantomData,
+    }
+  }
+}
+
+impl Future for Prefetch<'_> {
+  type Output = Result<()>;
+
+  #[inline]
+  fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+    let mut progress = self.request.lock();
+    match progress.outcome.take() {
+      Some(result) => Poll::Ready(result),
+      None => {
+        progress.waker = Some(context.waker().clone());
+        Poll::Pending
+      }
+    }
+  }
+}
+
+impl Drop for Prefetch<'_> {
+  #[inline]
+  fn drop(&mut self) {
+    // The worker polls whilst holding the lock, hence the range is never
+    //   accessed once this returns (i.e. once the allocation may be freed).
+    self.request.lock().cancelled = true;
+  }
+}
+
+/// A prefetch of a page-aligned range.
+struct Request {
+  address: usize,
+  size: usize,
+  progress: Mutex<Progress>,
+}
+
+#[derive(Default)]
+struct Progress {
+  outcome: Option<Result<()>>,
+  waker: Option<Waker>,
+  cancelled: bool,
+  resident: usize,
+  stalled: usize,
+  interval: Duration,
+  due: Option<Instant>,
+}
+
+impl Request {
+  fn lock(&self) -> MutexGuard<'_, Progress> {
+    self
+      .progress
+      .lock()
+      .unwrap_or_else(|error| error.into_inner())
+  }
+
+  /// Polls the residency of the range if it's due, returning whether the
+  /// prefetch has finished (or is no longer awaited).
+  fn poll(&self, now: Instant) -> bool {
+    let mut progress = self.lock();
+    if progress.cancelled || progress.outcome.is_some() {
+      return true;
+    }
+
+    if progress.due.is_some_and(|due| now < due) {
+      return false;
+    }
+
+    let pages = self.size / page::size();
+    let outcome = match os::resident_pages(self.address as *const (), self.size) {
+      Err(error) => Some(Err(error)),
+      Ok(current) if current >= pages => Some(Ok(())),
+      Ok(current) if current > progress.resident => {
+        progress.resident = current;
+        progress.stalled = 0;
+        None
+      }
+      Ok(current) if progress.stalled >= STALLED_POLLS => Some(Err(stalled(current))),
+      Ok(_) => {
+        progress.stalled += 1;
+        None
+      }
+    };
+
+    match outcome {
+      Some(outcome) => {
+        progress.outcome = Some(outcome);
+        if let Some(waker) = progress.waker.take() {
+          waker.wake();
+        }
+        true
+      }
+      None => {
+        progress.interval = (progress.interval * 2).clamp(MIN_INTERVAL, MAX_INTERVAL);
+        progress.due = Some(now + progress.interval);
+        false
+      }
+    }
+  }
+
+  fn due(&self) -> Option<Instant> {
+    self.lock().due
+  }
+}
+
+/// Returns the error of a prefetch that stopped bringing in pages, e.g. since
+/// the range consists of anonymous memory that has never been touched.
+fn stalled(resident: usize) -> Error {
+  Error::PartialFailure {
+    succeeded: resident * page::size(),
+    error: Box::new(Error::SystemCall(std::io::Error::new(
+      std::io::ErrorKind::TimedOut,
+      "pages are no longer being brought in",
+    ))),
+  }
+}
+
+fn enqueue(request: Arc<Request>) -> Result<()> {
+  let mut queue = QUEUE.lock().unwrap_or_else(|error| error.into_inner());
+  if !queue.worker {
+    std::thread::Builder::new()
+      .name("region-prefetch".to_string())
+      .spawn(serve)
+      .map_err(Error::SystemCall)?;
+    queue.worker = true;
+  }
+
+  queue.requests.push(request);
+  QUEUED.notify_one();
+  Ok(())
+}
+
+/// Polls the queued prefetches, until the process exits.
+fn serve() {
+  let mut queue = QUEUE.lock().unwrap_or_else(|error| error.into_inner());
+
+  loop {
+    // The queue is unlocked whilst polling, so that prefetches can be issued
+    let requests = queue.requests.clone();
+    drop(queue);
+
+    let now = Instant::now();
+    let (finished, pending): (Vec<_>, Vec<_>) =
+      requests.into_iter().partition(|request| request.poll(now));
+    let due = pending.iter().filter_map(|request| request.due()).min();
+
+    queue = QUEUE.lock().unwrap_or_else(|error| error.into_inner());
+    queue
+      .requests
+      .retain(|request| !finished.iter().any(|other| Arc::ptr_eq(request, other)));
+
+    // Prefetches issued whilst polling are polled right away
+    if queue.requests.len() > pending.len() {
+      continue;
+    }
+
+    // The requests are released before waiting, so that none outlives its future
+    drop((finished, pending));
+
+    queue = match due {
+      Some(due) => {
+        QUEUED
+          .wait_timeout(queue, due.saturating_duration_since(Instant::now()))
+          .unwrap_or_else(|error| error.into_inner())
+          .0
+      }
+      None => QUEUED
+        .wait_while(queue, |queue| queue.requests.is_empty())
+        .unwrap_or_else(|error| error.into_inner()),
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Protection;
+  use std::task::Wake;
+
+  struct Unpark(std::thread::Thread);
+
+  impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  fn block_on<T>(future: impl Future<Output = T>) -> T {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    loop {
+      match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(result) => break result,
+        Poll::Pending => std::thread::park(),
+      }
+    }
+  }
+
+  #[test]
+  fn prefetch_resolves_once_resident() -> Result<()> {
+    let memory = crate::alloc(crate::page::size() * 2, Protection::READ_WRITE)?;
+    unsafe { std::ptr::write_bytes(memory.as_ptr::<u8>().cast_mut(), 1, memory.len()) };
+
+    block_on(memory.prefetch_async(None))?;
+    assert!(crate::is_resident(memory.as_ptr::<u8>(), memory.len())?);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn prefetch_reports_stalled_pages() -> Result<()> {
+    let memory = crate::alloc(crate::page::size() * 2, Protection::READ_WRITE)?;
+    unsafe { memory.as_ptr::<u8>().cast_mut().write(1) };
+
+    // Untouched anonymous memory is never brought in
+    let result = block_on(memory.prefetch_async(None));
+    assert!(matches!(
+      result,
+      Err(Error::PartialFailure { succeeded, .. }) if succeeded == crate::page::size()
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn prefetch_is_dequeued_once_dropped() -> Result<()> {
+    let memory = crate::alloc(crate::page::size(), Protection::READ_WRITE)?;
+    let prefetch = Prefetch::new(memory.as_ptr(), memory.len());
+    let request = prefetch.request.clone();
+    drop(prefetch);
+
+    // The worker only releases the request once it's no longer polled
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Arc::strong_count(&request) > 1 && Instant::now() < deadline {
+      std::thread::sleep(MIN_INTERVAL);
+    }
+
+    assert!(request.lock().cancelled || request.lock().outcome.is_some());
+    assert_eq!(Arc::strong_count(&request), 1);
+    Ok(())
+  }
+}
@@ -1,4 +1,4 @@
-use crate::{os, util, Protection, QueryIter, Region, Result};
+use crate::{observe, os, page, util, Error, Protection, QueryIter, Region, Result};
 
 /// Changes the memory protection of one or more pages.
 ///
@@ -18,10 +18,23 @@ use crate::{os, util, Protection, QueryIter, Region, Result};
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
-/// - If size is zero,
-/// [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
-/// returned.
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If the OS only applied the protection to the leading part of the range
+///   (e.g. when it spans unmapped memory) on POSIX,
+///   [`Error::PartialFailure`](crate::Error::PartialFailure) will be returned,
+///   reporting the number of bytes whose protection was changed. Pages that
+///   already had the protection are not counted. On Windows, a protection
+///   change is never partially applied.
+///
+/// # Implementation
+///
+/// On POSIX, this function issues a single `mprotect` call. A range of a
+/// single page is not queried, hence it's suitable for repeatedly changing
+/// the protection of a page in a hot loop. Larger ranges are queried once
+/// beforehand, so that a partial failure can be accounted for.
 ///
 /// # Safety
 ///
@@ -52,7 +65,292 @@ use crate::{os, util, Protection, QueryIter, Region, Result};
 #[inline]
 pub unsafe fn protect<T>(address: *const T, size: usize, protection: Protection) -> Result<()> {
   let (address, size) = util::round_to_page_boundaries(address, size)?;
-  os::protect(address.cast(), size, protection)
+
+  // A single page cannot be partially changed, hence it's never queried
+  #[cfg(unix)]
+  let previous = match size > page::size() {
+    true => leading_protections(address.cast(), size).ok(),
+    false => None,
+  };
+
+  let result = observe::protect(address.cast(), size, protection, || {
+    os::protect(address.cast(), size, protection)
+  });
+
+  #[cfg(unix)]
+  let result = match (result, previous) {
+    (Err(error), Some(previous)) => match changed_bytes(address.cast(), &previous, protection) {
+      0 => Err(error),
+      succeeded => Err(Error::PartialFailure {
+        succeeded,
+        error: Box::new(error),
+      }),
+    },
+    (result, _) => result,
+  };
+
+  result
+}
+
+/// Returns whether the platform enforces non-executable memory (i.e the NX
+/// bit).
+///
+/// On hardware without support for no-execute pages, any readable memory is
+/// also executable, regardless of whether [`Protection::EXECUTE`] is set.
+/// Security-sensitive callers can use this to emit a warning.
+///
+/// # Implementation
+///
+/// On x86 & x86-64, this is determined using `CPUID`. Note that this only
+/// reflects whether the CPU supports the NX bit, not whether the OS has enabled
+/// it (e.g. 32-bit kernels without PAE). All other architectures are assumed to
+/// enforce it.
+///
+/// # Examples
+///
+/// ```
+/// if !region::nx_enforced() {
+///   eprintln!("warning: non-executable memory is not enforced");
+/// }
+/// ```
+#[inline]
+pub fn nx_enforced() -> bool {
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+  {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{__cpuid, __get_cpuid_max};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{__cpuid, __get_cpuid_max};
+
+    // The NX bit is reported by the extended processor info & feature bits
+    const EXTENDED_FEATURES: u32 = 0x8000_0001;
+    const NX: u32 = 1 << 20;
+
+    #[allow(unused_unsafe)]
+    unsafe {
+      let (max_leaf, _) = __get_cpuid_max(0x8000_0000);
+      max_leaf >= EXTENDED_FEATURES && __cpuid(EXTENDED_FEATURES).edx & NX != 0
+    }
+  }
+
+  #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+  true
+}
+
+/// Changes the memory protection of one or more pages, whilst preserving any
+/// page modifiers.
+///
+/// On Windows, [`protect`] replaces all protection flags of the affected pages,
+/// including modifiers such as `PAGE_GUARD`, `PAGE_NOCACHE` and
+/// `PAGE_WRITECOMBINE`. This function re-applies the modifiers of each affected
+/// region, so that e.g. one-shot stack guards are not accidentally cleared.
+///
+/// POSIX has no such distinction; there this function is equivalent to
+/// [`protect`].
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+///
+/// # Safety
+///
+/// See [protect].
+#[inline]
+pub unsafe fn protect_preserve_flags<T>(
+  address: *const T,
+  size: usize,
+  protection: Protection,
+) -> Result<()> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  observe::protect(address.cast(), size, protection, || {
+    os::protect_preserve_flags(address.cast(), size, protection)
+  })
+}
+
+/// Seals one or more pages, preventing any further changes to their protection
+/// or mapping.
+///
+/// Once sealed, the pages can no longer be unmapped, remapped, or have their
+/// protection changed, for the remainder of the process' lifetime. This is
+/// intended for defense-in-depth, e.g. to finalize JIT code as immutable
+/// [`Protection::READ_EXECUTE`]. Any subsequent [`protect`] of a sealed page
+/// fails with [`Error::SystemCall`] (`EPERM`).
+///
+/// # Implementation
+///
+/// This function is implemented using `mseal` on Linux (6.10 and later, on
+/// 64-bit platforms), and `mimmutable` on OpenBSD.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`] will be returned.
+/// - If the platform (or kernel) does not support sealing,
+///   [`Error::UnsupportedOperation`] will be returned.
+///
+/// # Safety
+///
+/// A sealed range can never be freed. Any [`Allocation`](crate::Allocation)
+/// overlapping it must be leaked (e.g. using [`std::mem::forget`]), since
+/// dropping it fails to release the memory.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::{Error, Protection};
+///
+/// let code = region::alloc(1, Protection::READ_EXECUTE)?;
+/// match unsafe { region::seal(code.as_ptr::<u8>(), code.len()) } {
+///   Ok(()) => std::mem::forget(code),
+///   Err(Error::UnsupportedOperation) => (),
+///   Err(error) => return Err(error),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub unsafe fn seal<T>(address: *const T, size: usize) -> Result<()> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  os::seal(address.cast(), size)
+}
+
+/// Changes the memory protection of one or more pages, reporting the previous
+/// and new protection of each affected page.
+///
+/// This is intended for debugging protection logic. The range is queried both
+/// before and after the protection is applied, which makes this function
+/// considerably slower than [`protect`].
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If any page within the range is unmapped,
+///   [`Error::UnmappedRegion`](crate::Error::UnmappedRegion) will be returned.
+///
+/// # Safety
+///
+/// See [protect].
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let memory = region::alloc(1, Protection::READ)?;
+/// let report =
+///   unsafe { region::protect_verbose(memory.as_ptr::<u8>(), 1, Protection::READ_WRITE)? };
+///
+/// assert_eq!(report.changed().count(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub unsafe fn protect_verbose<T>(
+  address: *const T,
+  size: usize,
+  protection: Protection,
+) -> Result<ProtectReport> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  let previous = page_protections(address.cast(), size)?;
+
+  protect(address, size, protection)?;
+  let current = page_protections(address.cast(), size)?;
+
+  let pages = previous
+    .into_iter()
+    .zip(current)
+    .enumerate()
+    .map(|(index, (previous, current))| PageProtection {
+      address: (address as usize + index * page::size()) as *const (),
+      previous,
+      current,
+    })
+    .collect();
+
+  Ok(ProtectReport { pages })
+}
+
+/// Returns the protection of each page within a page-aligned range.
+fn page_protections(address: *const (), size: usize) -> Result<Vec<Protection>> {
+  let protections = leading_protections(address, size)?;
+
+  if protections.len() != size / page::size() {
+    return Err(Error::UnmappedRegion);
+  }
+
+  Ok(protections)
+}
+
+/// Returns the protection of each page within the leading part of a
+/// page-aligned range that is contiguously mapped.
+fn leading_protections(address: *const (), size: usize) -> Result<Vec<Protection>> {
+  let mut protections = Vec::with_capacity(size / page::size());
+  let mut cursor = address as usize;
+
+  for region in QueryIter::new(address, size)? {
+    let region = region?;
+    let range = region.as_range();
+    if range.start > cursor {
+      break;
+    }
+
+    let end = range.end.min(address as usize + size);
+    let pages = (end - cursor) / page::size();
+    protections.resize(protections.len() + pages, region.protection);
+    cursor = end;
+  }
+
+  Ok(protections)
+}
+
+/// Returns the number of bytes within a range whose protection was changed to
+/// the specified one, given each page's protection beforehand.
+#[cfg(unix)]
+fn changed_bytes(address: *const (), previous: &[Protection], protection: Protection) -> usize {
+  let current = leading_protections(address, previous.len() * page::size()).unwrap_or_default();
+  let changed = previous
+    .iter()
+    .zip(current)
+    .filter(|&(&previous, current)| previous != protection && current == protection)
+    .count();
+
+  changed * page::size()
 }
 
 /// Temporarily changes the memory protection of one or more pages.
@@ -89,10 +387,10 @@ pub unsafe fn protect<T>(address: *const T, size: usize, protection: Protection)
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
-/// - If size is zero,
-/// [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
-/// returned.
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
 ///
 /// # Safety
 ///
@@ -126,6 +424,104 @@ pub unsafe fn protect_with_handle<T>(
   Ok(ProtectGuard::new(regions))
 }
 
+/// Temporarily changes the memory protection of one or more pages, whilst
+/// invoking a closure.
+///
+/// This is equivalent to holding a [`ProtectGuard`] (see
+/// [`protect_with_handle`]) for the duration of the closure, and is intended
+/// for e.g. patching code: the pages are made writable, patched, and restored
+/// to their previous protection. The protection is restored even if the
+/// closure panics.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned. This includes failing to restore the protection, once
+///   the closure returns.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+///
+/// # Safety
+///
+/// See [protect].
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let memory = region::alloc(1, Protection::READ)?;
+/// let address = memory.as_ptr::<u8>().cast_mut();
+///
+/// unsafe {
+///   region::with_protection(address, 1, Protection::READ_WRITE, || address.write(0xFF))?;
+///   assert_eq!(address.read(), 0xFF);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub unsafe fn with_protection<T, R>(
+  address: *const T,
+  size: usize,
+  protection: Protection,
+  f: impl FnOnce() -> R,
+) -> Result<R> {
+  let guard = protect_with_handle(address, size, protection)?;
+  let value = f();
+  guard.restore().map(|_| value)
+}
+
+/// A report of the protection changes applied by [`protect_verbose`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectReport {
+  pages: Vec<PageProtection>,
+}
+
+impl ProtectReport {
+  /// Returns the previous and new protection of each affected page, in
+  /// ascending order.
+  #[inline(always)]
+  pub fn pages(&self) -> &[PageProtection] {
+    &self.pages
+  }
+
+  /// Returns an iterator over the pages whose protection changed.
+  #[inline]
+  pub fn changed(&self) -> impl Iterator<Item = &PageProtection> {
+    self
+      .pages
+      .iter()
+      .filter(|page| page.previous != page.current)
+  }
+}
+
+/// The protection of a page, before and after a change.
+///
+/// This `struct` is part of a [`ProtectReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageProtection {
+  /// The base address of the page.
+  pub address: *const (),
+  /// The protection prior to the change.
+  pub previous: Protection,
+  /// The protection after the change.
+  pub current: Protection,
+}
+
+unsafe impl Send for ProtectReport {}
+unsafe impl Sync for ProtectReport {}
+
 /// A RAII implementation of a scoped protection guard.
 ///
 /// When this structure is dropped (falls out of scope), the memory regions'
@@ -140,6 +536,13 @@ impl ProtectGuard {
   fn new(regions: Vec<Region>) -> Self {
     Self { regions }
   }
+
+  /// Restores the regions' protection, reporting any failure to do so.
+  fn restore(mut self) -> Result<()> {
+    std::mem::take(&mut self.regions)
+      .iter()
+      .try_for_each(|region| unsafe { protect(region.base, region.size, region.protection) })
+  }
 }
 
 impl Drop for ProtectGuard {
@@ -162,16 +565,42 @@ mod tests {
   use crate::tests::util::alloc_pages;
   use crate::{page, query, query_range};
 
+  #[test]
+  fn nx_enforced_is_reported() {
+    // Every x86-64 CPU supports the NX bit
+    assert!(nx_enforced() || !cfg!(target_arch = "x86_64"));
+  }
+
   #[test]
   fn protect_null_fails() {
     assert!(unsafe { protect(std::ptr::null::<()>(), 0, Protection::NONE) }.is_err());
   }
 
+  #[test]
+  fn seal_prevents_subsequent_protection_changes() -> Result<()> {
+    let memory = crate::alloc(1, Protection::READ)?;
+
+    match unsafe { seal(memory.as_ptr::<u8>(), memory.len()) } {
+      Ok(()) => (),
+      // Older kernels (and most platforms) do not support sealing
+      Err(Error::UnsupportedOperation) => return Ok(()),
+      Err(error) => return Err(error),
+    }
+
+    // The sealed memory can never be freed
+    let address = memory.as_ptr::<u8>();
+    std::mem::forget(memory);
+
+    let result = unsafe { protect(address, 1, Protection::READ_WRITE) };
+    assert!(matches!(result, Err(Error::SystemCall(_))));
+    assert_eq!(query(address)?.protection(), Protection::READ);
+    Ok(())
+  }
+
   #[test]
   #[cfg(not(target_os = "openbsd"))]
   fn protect_can_alter_text_segments() {
-    #[allow(clippy::ptr_as_ptr)]
-    let address = &mut protect_can_alter_text_segments as *mut _ as *mut u8;
+    let address = protect_can_alter_text_segments as fn() as *mut u8;
     unsafe {
       protect(address, 1, Protection::READ_WRITE_EXECUTE).unwrap();
       *address = 0x90;
@@ -245,6 +674,112 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn protect_verbose_reports_each_changed_page() -> Result<()> {
+    let pages = [Protection::READ, Protection::READ_WRITE, Protection::READ];
+    let map = alloc_pages(&pages);
+
+    let report = unsafe { protect_verbose(map.as_ptr(), page::size() * 3, Protection::READ)? };
+    let changed = report.changed().collect::<Vec<_>>();
+    let second_page = unsafe { map.as_ptr().add(page::size()) };
+
+    assert_eq!(report.pages().len(), pages.len());
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].address, second_page.cast());
+    assert_eq!(changed[0].previous, Protection::READ_WRITE);
+    assert_eq!(changed[0].current, Protection::READ);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(all(unix, not(target_os = "haiku")))]
+  fn protect_issues_a_single_syscall() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ]);
+
+    let calls = os::record(|| {
+      for &protection in &[Protection::READ_WRITE, Protection::READ] {
+        unsafe { protect(map.as_ptr(), page::size(), protection) }.expect("protecting page");
+      }
+    });
+
+    assert_eq!(calls, [os::Call::Mprotect, os::Call::Mprotect]);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn protect_reports_partial_failure_for_unmapped_tail() -> Result<()> {
+    let memory = crate::alloc(page::size() * 2, Protection::READ)?;
+    let second_page = unsafe { memory.as_ptr::<u8>().add(page::size()) };
+    unsafe { os::free(second_page.cast(), page::size())? };
+
+    let result = unsafe { protect(memory.as_ptr::<u8>(), memory.len(), Protection::READ_WRITE) };
+    assert!(matches!(
+      result,
+      Err(Error::PartialFailure { succeeded, .. }) if succeeded == page::size()
+    ));
+    assert_eq!(
+      query(memory.as_ptr::<u8>())?.protection(),
+      Protection::READ_WRITE
+    );
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  fn protect_partial_failure_excludes_unchanged_pages() -> Result<()> {
+    let memory = crate::alloc(page::size() * 3, Protection::READ)?;
+    let (second_page, third_page) = unsafe {
+      let base = memory.as_ptr::<u8>();
+      (base.add(page::size()), base.add(page::size() * 2))
+    };
+
+    unsafe {
+      protect(memory.as_ptr::<u8>(), 1, Protection::READ_WRITE)?;
+      os::free(third_page.cast(), page::size())?;
+    }
+
+    let result = unsafe { protect(memory.as_ptr::<u8>(), memory.len(), Protection::READ_WRITE) };
+    assert!(matches!(
+      result,
+      Err(Error::PartialFailure { succeeded, .. }) if succeeded == page::size()
+    ));
+    assert_eq!(query(second_page)?.protection(), Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  fn protect_preserve_flags_alters_protection() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ]);
+    unsafe { protect_preserve_flags(map.as_ptr(), 1, Protection::READ_WRITE)? };
+    assert_eq!(query(map.as_ptr())?.protection(), Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(windows)]
+  fn protect_preserve_flags_retains_guard() -> Result<()> {
+    use winapi::um::winnt::{PAGE_GUARD, PAGE_READWRITE};
+
+    let map = alloc_pages(&[Protection::READ_WRITE]);
+    let guarded = unsafe {
+      winapi::um::memoryapi::VirtualProtect(
+        map.as_ptr() as *mut _,
+        page::size(),
+        PAGE_READWRITE | PAGE_GUARD,
+        &mut 0,
+      )
+    };
+    assert_ne!(guarded, 0);
+    assert!(query(map.as_ptr())?.is_guarded());
+
+    unsafe { protect_preserve_flags(map.as_ptr(), 1, Protection::READ)? };
+    let region = query(map.as_ptr())?;
+    assert!(region.is_guarded());
+    assert_eq!(region.protection(), Protection::READ);
+    Ok(())
+  }
+
   #[test]
   fn protect_with_handle_resets_protection() -> Result<()> {
     let map = alloc_pages(&[Protection::READ]);
@@ -291,4 +826,38 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn with_protection_restores_protection_after_closure() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ_EXECUTE]);
+    let address = map.as_ptr().cast_mut();
+
+    let during = unsafe {
+      with_protection(address, 1, Protection::READ_WRITE, || {
+        address.write(0xFF);
+        query(address).map(|region| region.protection())
+      })??
+    };
+
+    assert_eq!(during, Protection::READ_WRITE);
+    assert_eq!(unsafe { address.read() }, 0xFF);
+    assert_eq!(query(address)?.protection(), Protection::READ_EXECUTE);
+    Ok(())
+  }
+
+  #[test]
+  fn with_protection_restores_protection_on_panic() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ]);
+    let address = map.as_ptr() as usize;
+
+    let result = std::panic::catch_unwind(|| unsafe {
+      with_protection(address as *const u8, 1, Protection::READ_WRITE, || {
+        panic!("patching")
+      })
+    });
+
+    assert!(result.is_err());
+    assert_eq!(query(map.as_ptr())?.protection(), Protection::READ);
+    Ok(())
+  }
 }
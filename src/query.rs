@@ -1,4 +1,10 @@
-use crate::{os, util, Error, Region, Result};
+use crate::{os, page, util, Error, Protection, Region, Result};
+use std::ops::{ControlFlow, Range};
+use std::sync::{Mutex, MutexGuard};
+
+// The snapshot of the process' regions used by `query_cached`, sorted by
+//   address. It's only populated once it's first queried.
+static SNAPSHOT: Mutex<Option<Vec<Region>>> = Mutex::new(None);
 
 /// An iterator over the [`Region`]s that encompass an address range.
 ///
@@ -6,6 +12,9 @@ use crate::{os, util, Error, Region, Result};
 pub struct QueryIter {
   iterator: Option<os::QueryIter>,
   origin: *const (),
+  cursor: usize,
+  upper_bound: usize,
+  frontier: usize,
 }
 
 impl QueryIter {
@@ -13,10 +22,27 @@ impl QueryIter {
     let origin = origin.cast();
 
     os::QueryIter::new(origin, size).map(|iterator| Self {
+      cursor: origin as usize,
+      upper_bound: iterator.upper_bound(),
       iterator: Some(iterator),
+      frontier: 0,
       origin,
     })
   }
+
+  /// Returns the size, in bytes, of the queried range that remains to be
+  /// iterated.
+  ///
+  /// This is computed from the end of the last yielded region, and becomes
+  /// zero once the iterator has been exhausted.
+  #[inline]
+  pub fn remaining(&self) -> usize {
+    if self.iterator.is_none() {
+      return 0;
+    }
+
+    self.upper_bound.saturating_sub(self.cursor)
+  }
 }
 
 impl Iterator for QueryIter {
@@ -28,17 +54,23 @@ impl Iterator for QueryIter {
   /// queried), or if an error is encountered during iteration, all further
   /// invocations will return [`None`] (in the case of an error, the error will
   /// be the last item that is yielded before the iterator is fused).
+  ///
+  /// The yielded regions are never empty, and are strictly increasing and
+  /// non-overlapping. Any region reported by the OS that overlaps with a
+  /// previously yielded one is trimmed (or skipped, if fully covered).
   #[allow(clippy::missing_inline_in_public_items)]
   fn next(&mut self) -> Option<Self::Item> {
     let regions = self.iterator.as_mut()?;
 
     while let Some(result) = regions.next() {
       match result {
-        Ok(region) => {
+        Ok(mut region) => {
           let range = region.as_range();
 
-          // Skip the region if it is prior to the queried range
-          if range.end <= self.origin as usize {
+          // Skip the region if it is prior to the queried range, or if it is
+          // empty or entirely covered by an already yielded region (e.g. a
+          // guard or reserved region reported twice).
+          if range.end <= (self.origin as usize).max(self.frontier) || range.is_empty() {
             continue;
           }
 
@@ -47,6 +79,21 @@ impl Iterator for QueryIter {
             break;
           }
 
+          // Trim the region if it overlaps with the previously yielded one
+          if range.start < self.frontier {
+            region.base = self.frontier as *const ();
+            region.size = range.end - self.frontier;
+          }
+
+          // POSIX lacks the notion of committed memory, therefore inaccessible
+          // pages are considered to be reserved address space.
+          #[cfg(all(unix, not(target_os = "haiku")))]
+          if region.protection == Protection::NONE {
+            region.reserved = true;
+          }
+
+          self.cursor = range.end.min(self.upper_bound);
+          self.frontier = range.end;
           return Some(Ok(region));
         }
         Err(error) => {
@@ -70,7 +117,9 @@ unsafe impl Sync for QueryIter {}
 ///
 /// If the queried address does not reside within any mapped region, or if it's
 /// outside the process' address space, the function will error with
-/// [`Error::UnmappedRegion`].
+/// [`Error::UnmappedRegion`]. This includes the null address, which is never
+/// reported as a region (not even on platforms that permit mapping the zero
+/// page), on all backends.
 ///
 /// # Parameters
 ///
@@ -80,7 +129,7 @@ unsafe impl Sync for QueryIter {}
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
+///   will be returned.
 ///
 /// # Examples
 ///
@@ -98,13 +147,347 @@ unsafe impl Sync for QueryIter {}
 #[inline]
 pub fn query<T>(address: *const T) -> Result<Region> {
   // For UNIX systems, the address must be aligned to the closest page boundary
-  let (address, size) = util::round_to_page_boundaries(address, 1)?;
+  let (address, size) = util::round_to_page_boundaries_saturating(address, 1)?;
 
   QueryIter::new(address, size)?
     .next()
     .ok_or(Error::UnmappedRegion)?
 }
 
+/// Queries the OS with multiple addresses, returning the region each of them
+/// resides within.
+///
+/// This is equivalent to calling [`query`] for each address, with the results
+/// in the same order, but lookups are batched. Any address that falls within
+/// a region that has already been resolved is answered without querying the
+/// OS again. This is intended for tooling (e.g. debuggers) inspecting many
+/// pointers at once.
+///
+/// # Implementation
+///
+/// On Linux, `/proc/self/maps` is parsed once, and every address is resolved
+/// from that snapshot.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let data = [0u8; 100];
+/// let addresses = [data.as_ptr().cast(), std::ptr::null()];
+/// let regions = region::query_many(&addresses);
+///
+/// assert!(regions[0].is_ok());
+/// assert!(matches!(regions[1], Err(region::Error::UnmappedRegion)));
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn query_many(addresses: &[*const ()]) -> Vec<Result<Region>> {
+  // A single snapshot answers every address, unless it cannot be obtained
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  if let Ok(regions) = query_process().and_then(Iterator::collect::<Result<Vec<_>>>) {
+    return addresses
+      .iter()
+      .map(|&address| find_region(&regions, address).ok_or(Error::UnmappedRegion))
+      .collect();
+  }
+
+  let mut resolved: Vec<Region> = Vec::new();
+  addresses
+    .iter()
+    .map(|&address| {
+      let known = resolved
+        .iter()
+        .find(|region| region.as_range().contains(&(address as usize)));
+
+      match known {
+        Some(region) => Ok(*region),
+        None => query(address).inspect(|region| resolved.push(*region)),
+      }
+    })
+    .collect()
+}
+
+/// Returns the region containing an address, from a set of sorted regions.
+fn find_region(regions: &[Region], address: *const ()) -> Option<Region> {
+  let address = address as usize;
+  let index = regions.partition_point(|region| region.as_range().end <= address);
+
+  regions
+    .get(index)
+    .filter(|region| region.as_range().contains(&address))
+    .copied()
+}
+
+/// Queries a cached snapshot of the process' regions with an address,
+/// returning the region it resides within.
+///
+/// The snapshot is created by the first invocation, and every later one is
+/// answered from it, as long as the address resides within a cached region.
+/// This avoids querying the OS (e.g. reading `/proc/self/maps` on Linux) in
+/// tight scanning loops, in exchange for potentially stale results. An address
+/// outside of any cached region is queried using [`query`], and the snapshot
+/// is updated with its region. Once the caller knows that the process' regions
+/// have changed (e.g. after protecting or freeing memory), the snapshot should
+/// be discarded using [`invalidate_query_cache`].
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the address is not mapped, [`Error::UnmappedRegion`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let data = [0; 100];
+/// for chunk in data.chunks(10) {
+///   let region = region::query_cached(chunk.as_ptr())?;
+///   assert_eq!(region.protection(), Protection::READ_WRITE);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn query_cached<T>(address: *const T) -> Result<Region> {
+  let mut snapshot = snapshot();
+  let regions = match snapshot.as_mut() {
+    Some(regions) => regions,
+    None => snapshot.insert(query_process()?.collect::<Result<Vec<_>>>()?),
+  };
+
+  if let Some(region) = find_region(regions, address.cast()) {
+    return Ok(region);
+  }
+
+  // The region is merged into the snapshot, replacing any stale overlaps
+  let region = query(address)?;
+  let range = region.as_range();
+  regions.retain(|cached| {
+    let cached = cached.as_range();
+    cached.end <= range.start || cached.start >= range.end
+  });
+
+  let index = regions.partition_point(|cached| cached.as_range().end <= range.start);
+  regions.insert(index, region);
+  Ok(region)
+}
+
+/// Discards the snapshot used by [`query_cached`].
+///
+/// The next invocation of [`query_cached`] creates a new snapshot, reflecting
+/// the process' current regions.
+#[inline]
+pub fn invalidate_query_cache() {
+  snapshot().take();
+}
+
+fn snapshot() -> MutexGuard<'static, Option<Vec<Region>>> {
+  SNAPSHOT.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Queries the OS with an address, returning the protection of the region it
+/// resides within.
+///
+/// This is a thin wrapper around [`query`], for when only the protection
+/// flags are of interest (e.g. to determine whether an address is executable).
+///
+/// # Parameters
+///
+/// - The address is rounded down to the closest page boundary.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the address is not mapped, [`Error::UnmappedRegion`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let data = [0; 100];
+/// assert_eq!(region::protection_at(data.as_ptr())?, Protection::READ_WRITE);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn protection_at<T>(address: *const T) -> Result<Protection> {
+  #[cfg(target_os = "haiku")]
+  return os::protection_at(address.cast());
+
+  #[cfg(not(target_os = "haiku"))]
+  query(address).map(|region| region.protection())
+}
+
+/// Queries the OS with an address, returning the region it resides within, or
+/// the closest mapped region if it's unmapped.
+///
+/// The distance to the region is returned alongside it, i.e. the number of
+/// bytes between the address and the nearest byte of the region (zero if the
+/// address resides within it). If two regions are equally close, the lower one
+/// is returned. This is intended for tooling (e.g. debuggers), where an address
+/// within a hole should still be attributed to a region.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the process has no mapped regions, [`Error::UnmappedRegion`] will be
+///   returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let data = [0; 100];
+/// let (region, distance) = region::nearest_region(data.as_ptr())?;
+///
+/// assert!(region.as_range().contains(&(data.as_ptr() as usize)));
+/// assert_eq!(distance, 0);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn nearest_region<T>(address: *const T) -> Result<(Region, usize)> {
+  match query(address) {
+    Ok(region) => return Ok((region, 0)),
+    Err(Error::UnmappedRegion) => (),
+    Err(error) => return Err(error),
+  }
+
+  let address = address as usize;
+  let mut nearest: Option<(Region, usize)> = None;
+
+  for region in query_process()? {
+    let region = region?;
+    let range = region.as_range();
+    let distance = if address < range.start {
+      range.start - address
+    } else {
+      address - (range.end - 1)
+    };
+
+    match nearest {
+      Some((_, closest)) if closest <= distance => (),
+      _ => nearest = Some((region, distance)),
+    }
+
+    // The regions are sorted, hence the remaining ones are farther away
+    if range.start > address {
+      break;
+    }
+  }
+
+  nearest.ok_or(Error::UnmappedRegion)
+}
+
+/// Returns the largest unmapped range of addresses within a window.
+///
+/// The window is shrunk to its innermost page boundaries, and the mapped
+/// regions within it are scanned for the largest gap between them. If several
+/// gaps are equally large, the lowest one is returned. If the window is
+/// entirely mapped, an empty range at its start is returned. This is intended
+/// for reserving as much contiguous address space as possible, e.g. using
+/// [`alloc_at`](crate::alloc_at), although another thread may map the range
+/// in the meantime.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the window does not span a single page, [`Error::InvalidParameter`]
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let hole = region::largest_free_hole(0..usize::MAX)?;
+/// assert!(matches!(
+///   region::query(hole.start as *const ()),
+///   Err(region::Error::UnmappedRegion)
+/// ));
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn largest_free_hole(within: Range<usize>) -> Result<Range<usize>> {
+  let start = page::ceil(within.start as *const ()) as usize;
+  let end = page::floor(within.end as *const ()) as usize;
+
+  if start >= end {
+    return Err(Error::InvalidParameter("within"));
+  }
+
+  let holes = free_holes(start..end)?;
+  let largest = holes.into_iter().fold(start..start, |largest, hole| {
+    if hole.len() > largest.len() {
+      hole
+    } else {
+      largest
+    }
+  });
+  Ok(largest)
+}
+
+/// Returns the unmapped ranges of addresses within a page-aligned window, in
+/// ascending order.
+pub(crate) fn free_holes(within: Range<usize>) -> Result<Vec<Range<usize>>> {
+  let (start, end) = (within.start, within.end);
+  let mut holes = Vec::new();
+  let mut cursor = start;
+
+  for region in query_range(start as *const (), end - start)? {
+    let range = region?.as_range();
+    if range.start > cursor {
+      holes.push(cursor..range.start.min(end));
+    }
+
+    cursor = cursor.max(range.end);
+    if cursor >= end {
+      break;
+    }
+  }
+
+  if cursor < end {
+    holes.push(cursor..end);
+  }
+  Ok(holes)
+}
+
+/// Returns the range of addresses available for mappings in user space.
+///
+/// This is intended for bounding the search for free address space (e.g. using
+/// [`largest_free_hole`]), such as when randomizing the placement of JIT code.
+/// The range is half-open, and its bounds are page aligned.
+///
+/// # Implementation
+///
+/// On Windows, the range is retrieved using `GetSystemInfo`. Elsewhere, the
+/// lower bound is the page size (or `mmap_min_addr` on Linux), whilst the upper
+/// bound is the end of the highest mapped region (typically the main thread's
+/// stack). On x86-64, the upper bound is no less than the end of the 47-bit
+/// user space. Therefore, the upper bound is an approximation on POSIX.
+///
+/// # Examples
+///
+/// ```
+/// let bounds = region::address_space_bounds();
+/// let data = [0; 100];
+///
+/// assert!(bounds.contains(&(data.as_ptr() as usize)));
+/// ```
+#[inline]
+pub fn address_space_bounds() -> Range<usize> {
+  os::address_space_bounds()
+}
+
 /// Queries the OS for mapped regions that overlap with the specified range.
 ///
 /// The implementation clamps any input that exceeds the boundaries of a
@@ -124,7 +507,8 @@ pub fn query<T>(address: *const T) -> Result<Region> {
 ///
 /// # Parameters
 ///
-/// - The range is `[address, address + size)`
+/// - The range is `[address, address + size)`, i.e. a region starting exactly
+///   at `address + size` is not returned (see [`query_range_inclusive`]).
 /// - The address is rounded down to the closest page boundary.
 /// - The size may not be zero.
 /// - The size is rounded up to the closest page boundary, relative to the
@@ -133,7 +517,7 @@ pub fn query<T>(address: *const T) -> Result<Region> {
 /// # Errors
 ///
 /// - If an interaction with the underlying operating system fails, an error
-/// will be returned.
+///   will be returned.
 /// - If size is zero, [`Error::InvalidParameter`] will be returned.
 ///
 /// # Examples
@@ -152,19 +536,206 @@ pub fn query<T>(address: *const T) -> Result<Region> {
 /// ```
 #[inline]
 pub fn query_range<T>(address: *const T, size: usize) -> Result<QueryIter> {
-  let (address, size) = util::round_to_page_boundaries(address, size)?;
+  let (address, size) = util::round_to_page_boundaries_saturating(address, size)?;
   QueryIter::new(address, size)
 }
 
+/// Invokes a callback for each mapped region that overlaps with the specified
+/// range, until it returns [`ControlFlow::Break`].
+///
+/// This is equivalent to iterating [`query_range`], but lends itself to early
+/// exiting searches, without collecting any regions. The regions are visited
+/// in order of increasing address.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use std::ops::ControlFlow;
+///
+/// let mut executable = None;
+/// region::for_each_region(std::ptr::null::<()>(), usize::MAX, |region| {
+///   if region.is_executable() {
+///     executable = Some(*region);
+///     return ControlFlow::Break(());
+///   }
+///   ControlFlow::Continue(())
+/// })?;
+///
+/// assert!(executable.is_some());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn for_each_region<T>(
+  address: *const T,
+  size: usize,
+  mut f: impl FnMut(&Region) -> ControlFlow<()>,
+) -> Result<()> {
+  for region in query_range(address, size)? {
+    if let ControlFlow::Break(()) = f(&region?) {
+      break;
+    }
+  }
+  Ok(())
+}
+
+/// Queries the OS for mapped regions that overlap with the specified range,
+/// including the region at its end.
+///
+/// Unlike [`query_range`], the byte at `address + size` is part of the range,
+/// which means that a region starting exactly at the end is also returned.
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size]`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may be zero, in which case only the region containing the
+///   address is returned.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+#[inline]
+pub fn query_range_inclusive<T>(address: *const T, size: usize) -> Result<QueryIter> {
+  query_range(address, size.saturating_add(1))
+}
+
+/// Queries the OS for all mapped regions of the current process.
+///
+/// This is equivalent to calling [`query_range`] with [`std::ptr::null`] and
+/// [`usize::MAX`].
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let regions = region::query_process()?.collect::<region::Result<Vec<_>>>()?;
+/// assert!(!regions.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn query_process() -> Result<QueryIter> {
+  query_range(std::ptr::null::<()>(), usize::MAX)
+}
+
+/// Returns the total size, in bytes, of all mapped regions of the current
+/// process.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+#[inline]
+pub fn total_mapped() -> Result<usize> {
+  total_mapped_with(Protection::NONE)
+}
+
+/// Returns the total size, in bytes, of all mapped regions of the current
+/// process, that have at least the specified protection.
+///
+/// E.g. passing [`Protection::EXECUTE`] sums all executable regions,
+/// irrespective of whether they are also readable or writable.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let executable = region::total_mapped_with(Protection::EXECUTE)?;
+/// assert!(executable <= region::total_mapped()?);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn total_mapped_with(protection: Protection) -> Result<usize> {
+  query_process()?.try_fold(0usize, |total, region| {
+    let region = region?;
+    Ok(if region.protection().contains(protection) {
+      total.saturating_add(region.len())
+    } else {
+      total
+    })
+  })
+}
+
+/// Returns all mapped regions of the current process that are simultaneously
+/// writable and executable.
+///
+/// Such regions violate W^X (write xor execute), and are a common target for
+/// code injection. This is intended for auditing, e.g. failing a test suite if
+/// any unexpected region is reported.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// for region in region::audit_wx()? {
+///   println!("W^X violation: {:?}", region.as_range());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn audit_wx() -> Result<Vec<Region>> {
+  query_process()?
+    .filter(|region| match region {
+      Ok(region) => region.protection().is_wx(),
+      Err(_) => true,
+    })
+    .collect()
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
   use crate::tests::util::alloc_pages;
   use crate::{page, Protection};
 
+  #[test]
+  fn query_returns_unmapped_for_null_address() {
+    let null = std::ptr::null::<u8>();
+    assert!(matches!(query(null), Err(Error::UnmappedRegion)));
+    assert!(matches!(protection_at(null), Err(Error::UnmappedRegion)));
+  }
+
   #[test]
   fn query_returns_unmapped_for_oob_address() {
-    let (min, max) = (std::ptr::null::<()>(), usize::max_value() as *const ());
+    let (min, max) = (std::ptr::null::<()>(), usize::MAX as *const ());
     assert!(matches!(query(min), Err(Error::UnmappedRegion)));
     assert!(matches!(query(max), Err(Error::UnmappedRegion)));
   }
@@ -178,6 +749,16 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn query_returns_uncommitted_region_for_reservation() -> Result<()> {
+    let reservation = crate::reserve(page::size() * 2)?;
+    let region = query(reservation.as_ptr::<()>())?;
+
+    assert!(!region.is_committed());
+    assert_eq!(region.protection(), Protection::NONE);
+    Ok(())
+  }
+
   #[test]
   fn query_returns_one_region_for_multiple_page_allocation() -> Result<()> {
     let alloc = crate::alloc(page::size() + 1, Protection::READ_EXECUTE)?;
@@ -190,6 +771,46 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  #[cfg(unix)]
+  fn query_many_returns_equal_regions_for_same_region() -> Result<()> {
+    let pz = page::size();
+    let map = alloc_pages(&[Protection::READ, Protection::READ, Protection::READ]);
+    let base = map.as_ptr();
+    let addresses = [
+      base.cast::<()>(),
+      unsafe { base.add(pz) }.cast(),
+      unsafe { base.add(pz * 3 - 1) }.cast(),
+      std::ptr::null(),
+    ];
+
+    let regions = query_many(&addresses);
+    let expected = query(base)?;
+    for result in &regions[..3] {
+      assert_eq!(*result.as_ref().unwrap(), expected);
+    }
+    assert!(matches!(regions[3], Err(Error::UnmappedRegion)));
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn query_cached_only_rereads_once_invalidated() -> Result<()> {
+    let map = alloc_pages(&[Protection::READ, Protection::READ_WRITE]);
+    let (first, second) = (map.as_ptr(), unsafe { map.as_ptr().add(page::size()) });
+    invalidate_query_cache();
+    assert_eq!(query_cached(first)?, query(first)?);
+    assert_eq!(query_cached(second)?, query(second)?);
+
+    // The stale snapshot is used, until it's invalidated
+    unsafe { crate::protect(second, page::size(), Protection::READ)? };
+    assert_eq!(query_cached(second)?.protection(), Protection::READ_WRITE);
+
+    invalidate_query_cache();
+    assert_eq!(query_cached(second)?.protection(), Protection::READ);
+    Ok(())
+  }
+
   #[test]
   fn query_is_not_off_by_one() -> Result<()> {
     let pages = [Protection::READ, Protection::READ_EXECUTE, Protection::READ];
@@ -208,6 +829,72 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn protection_at_distinguishes_text_and_data_segments() -> Result<()> {
+    let data = [0; 100];
+    let text = protection_at(protection_at_distinguishes_text_and_data_segments as *const ())?;
+
+    assert!(text.contains(Protection::EXECUTE));
+    assert!(!protection_at(data.as_ptr())?.contains(Protection::EXECUTE));
+    Ok(())
+  }
+
+  #[test]
+  fn nearest_region_returns_closest_region_for_unmapped_address() -> Result<()> {
+    let base = crate::alloc(page::size() * 3, Protection::READ)?.as_ptr::<u8>();
+    let first = crate::alloc_at(base, page::size(), Protection::READ)?;
+    let gap = unsafe { base.add(page::size()) };
+
+    // The page below the gap is one byte away, whilst any above is a page away
+    let (region, distance) = nearest_region(gap)?;
+    assert!(region.as_range().contains(&(base as usize)));
+    assert_eq!(distance, 1);
+
+    let (region, distance) = nearest_region(base)?;
+    assert_eq!(region.as_ptr(), first.as_ptr::<()>());
+    assert_eq!(distance, 0);
+    Ok(())
+  }
+
+  #[test]
+  fn largest_free_hole_returns_unmapped_range() -> Result<()> {
+    let pz = page::size();
+    let base = crate::alloc(pz * 4, Protection::READ)?.as_ptr::<u8>();
+    let _first = crate::alloc_at(base, pz, Protection::READ)?;
+    let _last = crate::alloc_at(unsafe { base.add(pz * 3) }, pz, Protection::READ)?;
+
+    let window = base as usize..base as usize + pz * 4;
+    let hole = largest_free_hole(window)?;
+    assert_eq!(hole, base as usize + pz..base as usize + pz * 3);
+
+    for address in hole.step_by(pz) {
+      let result = query(address as *const ());
+      assert!(matches!(result, Err(Error::UnmappedRegion)));
+    }
+
+    let mapped = base as usize..base as usize + pz;
+    assert_eq!(largest_free_hole(mapped)?, base as usize..base as usize);
+    assert!(matches!(
+      largest_free_hole(base as usize..base as usize + 1),
+      Err(Error::InvalidParameter("within"))
+    ));
+    Ok(())
+  }
+
+  #[test]
+  fn address_space_bounds_contain_mapped_memory() -> Result<()> {
+    let bounds = address_space_bounds();
+    assert!(!bounds.is_empty());
+    assert!(bounds.start >= page::size());
+
+    let stack = 0u8;
+    let memory = crate::alloc(1, Protection::READ)?;
+    assert!(bounds.contains(&(&stack as *const u8 as usize)));
+    assert!(bounds.contains(&(memory.as_ptr::<u8>() as usize)));
+    assert!(bounds.contains(&(memory.as_range().end - 1)));
+    Ok(())
+  }
+
   #[test]
   fn query_range_does_not_return_unmapped_regions() -> Result<()> {
     let regions = query_range(std::ptr::null::<()>(), 1)?.collect::<Result<Vec<_>>>()?;
@@ -231,6 +918,28 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn for_each_region_stops_at_first_executable_region() -> Result<()> {
+    let (mut visited, mut executable) = (0, None);
+
+    for_each_region(std::ptr::null::<()>(), usize::MAX, |region| {
+      visited += 1;
+      if region.is_executable() {
+        executable = Some(*region);
+        return ControlFlow::Break(());
+      }
+      ControlFlow::Continue(())
+    })?;
+
+    let executable = executable.expect("executable region");
+    let regions = query_process()?.collect::<Result<Vec<_>>>()?;
+    let expected = regions.iter().find(|region| region.is_executable());
+
+    assert_eq!(executable.as_ptr(), expected.unwrap().as_ptr::<()>());
+    assert!(visited < regions.len());
+    Ok(())
+  }
+
   #[test]
   fn query_range_has_inclusive_lower_and_exclusive_upper_bound() -> Result<()> {
     let pages = [Protection::READ, Protection::READ_WRITE, Protection::READ];
@@ -247,10 +956,29 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn query_range_inclusive_includes_region_at_upper_bound() -> Result<()> {
+    let pages = [Protection::READ, Protection::READ_WRITE, Protection::READ];
+    let map = alloc_pages(&pages);
+
+    let regions = query_range_inclusive(map.as_ptr(), page::size() - 1)?;
+    let regions = regions.collect::<Result<Vec<_>>>()?;
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions[0].protection(), Protection::READ);
+
+    let regions = query_range_inclusive(map.as_ptr(), page::size())?;
+    let regions = regions.collect::<Result<Vec<_>>>()?;
+    assert_eq!(regions.len(), 2);
+    assert_eq!(regions[1].protection(), Protection::READ_WRITE);
+
+    let regions = query_range_inclusive(map.as_ptr(), 0)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(regions.len(), 1);
+    Ok(())
+  }
+
   #[test]
   fn query_range_can_iterate_over_entire_process() -> Result<()> {
-    let regions =
-      query_range(std::ptr::null::<()>(), usize::max_value())?.collect::<Result<Vec<_>>>()?;
+    let regions = query_range(std::ptr::null::<()>(), usize::MAX)?.collect::<Result<Vec<_>>>()?;
     let (r, rw, rx) = (
       Protection::READ,
       Protection::READ_WRITE,
@@ -265,6 +993,65 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn query_process_yields_increasing_non_overlapping_regions() -> Result<()> {
+    let regions = query_process()?.collect::<Result<Vec<_>>>()?;
+
+    assert!(regions.iter().all(|region| !region.is_empty()));
+    for pair in regions.windows(2) {
+      assert!(pair[0].as_range().end <= pair[1].as_range().start);
+    }
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(not(any(
+    target_os = "openbsd",
+    all(target_os = "macos", target_arch = "aarch64")
+  )))]
+  fn audit_wx_reports_writable_and_executable_regions() -> Result<()> {
+    let memory = crate::alloc(page::size(), Protection::READ_WRITE_EXECUTE)?;
+    let base = memory.as_ptr::<()>() as usize;
+    let audited = |regions: Vec<Region>| {
+      regions
+        .iter()
+        .any(|region| region.as_range().contains(&base))
+    };
+
+    assert!(audited(audit_wx()?));
+    unsafe { memory.protect(Protection::READ_EXECUTE)? };
+    assert!(!audited(audit_wx()?));
+    Ok(())
+  }
+
+  #[test]
+  fn total_mapped_includes_allocations() -> Result<()> {
+    let alloc = crate::alloc(page::size() * 4, Protection::READ_WRITE)?;
+    let total = total_mapped()?;
+
+    assert!(total >= alloc.len());
+    assert!(total_mapped_with(Protection::READ_WRITE)? >= alloc.len());
+    assert!(total_mapped_with(Protection::EXECUTE)? < total);
+    Ok(())
+  }
+
+  #[test]
+  fn query_range_remaining_decreases_to_zero() -> Result<()> {
+    let pages = [Protection::READ, Protection::READ_WRITE, Protection::READ];
+    let map = alloc_pages(&pages);
+    let mut iter = query_range(map.as_ptr(), page::size() * pages.len())?;
+
+    for index in (0..pages.len()).rev() {
+      assert_eq!(iter.remaining(), page::size() * (index + 1));
+      assert!(iter.next().transpose()?.is_some());
+    }
+
+    assert_eq!(iter.remaining(), 0);
+    assert!(iter.next().is_none());
+    assert_eq!(iter.remaining(), 0);
+    Ok(())
+  }
+
   #[test]
   fn query_range_iterator_is_fused_after_exhaustion() -> Result<()> {
     let pages = [Protection::READ, Protection::READ_WRITE];
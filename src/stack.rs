@@ -0,0 +1,172 @@
+use crate::{os, page, Allocation, Error, Result};
+
+/// Allocates a growable stack, committing only its topmost page.
+///
+/// The entire `max_size` is reserved up front, whilst only the topmost page is
+/// committed, since stacks grow downwards (i.e. the initial stack pointer is
+/// the end of the allocation). The page directly below the committed portion
+/// is a guard page, which faults once it's touched. The stack is then grown
+/// one page at a time, using [`commit_next_guard`].
+///
+/// The lowest page of the stack always remains a guard page, so that
+/// overflowing the stack faults, rather than corrupting adjacent memory.
+///
+/// # Implementation
+///
+/// On Windows, the guard page is committed with `PAGE_GUARD`. Touching it
+/// raises `STATUS_GUARD_PAGE_VIOLATION`, once, after which the page is
+/// accessible. On POSIX, the guard page remains inaccessible, and touching it
+/// raises `SIGSEGV`.
+///
+/// # Parameters
+///
+/// - The size must span at least two pages (i.e. the initial page and the
+///   guard page).
+/// - The size is rounded up to the closest page boundary.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If the size spans less than two pages, or the range exceeds the address
+///   space, [`Error::InvalidParameter`] will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let stack = region::alloc_growable_stack(1024 * 1024)?;
+/// let top = stack.as_ptr_range::<u8>().end;
+///
+/// let region = region::query(unsafe { top.sub(1) })?;
+/// assert!(region.is_committed());
+/// assert!(region.len() < stack.len());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn alloc_growable_stack(max_size: usize) -> Result<Allocation> {
+  if max_size <= page::size() {
+    return Err(Error::InvalidParameter("size"));
+  }
+
+  let stack = crate::reserve(max_size)?;
+  let top = stack.as_ptr_range::<u8>().end;
+
+  unsafe {
+    os::commit(top.sub(page::size()).cast(), page::size(), false)?;
+    os::commit(top.sub(page::size() * 2).cast(), page::size(), true)?;
+  }
+  Ok(stack)
+}
+
+/// Commits the guard page of a growable stack, and places a new guard page
+/// below it.
+///
+/// This is intended to be invoked once the guard page has been touched (or
+/// preemptively, e.g. when a coroutine's stack pointer is nearing it). If the
+/// stack has already grown to its maximum size, nothing is committed and
+/// `false` is returned.
+///
+/// The committed portion of the stack is resolved by querying its regions,
+/// therefore this function is not async-signal-safe, and it must not be
+/// invoked from a `SIGSEGV` handler.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If no part of the stack is committed, [`Error::InvalidParameter`] will be
+///   returned.
+///
+/// # Safety
+///
+/// The allocation must have been created by [`alloc_growable_stack`], and the
+/// committed portion of it must not have been altered through other means
+/// (such as by [`protect`](crate::protect)).
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let stack = region::alloc_growable_stack(1024 * 1024)?;
+///
+/// // Grow the stack by a single page
+/// assert!(unsafe { region::commit_next_guard(&stack)? });
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub unsafe fn commit_next_guard(stack: &Allocation) -> Result<bool> {
+  let base = stack.as_ptr::<u8>();
+  let mut committed = None;
+
+  for region in crate::query_range(base, stack.len())? {
+    let region = region?;
+    if region.is_committed() && region.is_readable() && !region.is_guarded() {
+      committed = Some(region.as_ptr::<u8>());
+      break;
+    }
+  }
+
+  let lowest = committed.ok_or(Error::InvalidParameter("stack"))?;
+  if lowest as usize - base as usize <= page::size() {
+    return Ok(false);
+  }
+
+  let guard = lowest.sub(page::size());
+  os::commit(guard.cast(), page::size(), false)?;
+  os::commit(guard.sub(page::size()).cast(), page::size(), true)?;
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{Protection, Region};
+
+  fn is_guard(region: &Region) -> bool {
+    // Windows guards with `PAGE_GUARD`, whilst POSIX leaves the page inaccessible
+    region.is_guarded() || !region.is_readable()
+  }
+
+  #[test]
+  fn alloc_growable_stack_commits_smaller_initial_region() -> Result<()> {
+    let pz = page::size();
+    let stack = alloc_growable_stack(pz * 8)?;
+    let top = stack.as_ptr_range::<u8>().end;
+
+    let region = crate::query(unsafe { top.sub(1) })?;
+    assert_eq!(region.protection(), Protection::READ_WRITE);
+    assert!(region.is_committed());
+    assert!(region.len() < stack.len());
+
+    let guard = crate::query(unsafe { top.sub(pz + 1) })?;
+    assert!(is_guard(&guard));
+    assert!(is_guard(&crate::query(stack.as_ptr::<u8>())?));
+    Ok(())
+  }
+
+  #[test]
+  fn commit_next_guard_grows_stack_until_exhausted() -> Result<()> {
+    let pz = page::size();
+    let stack = alloc_growable_stack(pz * 4)?;
+
+    assert!(unsafe { commit_next_guard(&stack)? });
+    assert!(unsafe { commit_next_guard(&stack)? });
+    assert!(!unsafe { commit_next_guard(&stack)? });
+
+    // All pages but the lowest one are committed and writable
+    let second = unsafe { stack.as_ptr::<u8>().add(pz) as *mut u8 };
+    unsafe { second.write(0xFF) };
+    assert_eq!(crate::query(second)?.protection(), Protection::READ_WRITE);
+    assert!(is_guard(&crate::query(stack.as_ptr::<u8>())?));
+    Ok(())
+  }
+
+  #[test]
+  fn alloc_growable_stack_rejects_single_page() {
+    let result = alloc_growable_stack(page::size());
+    assert!(matches!(result, Err(Error::InvalidParameter("size"))));
+  }
+}
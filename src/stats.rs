@@ -0,0 +1,110 @@
+use crate::{query_process, Protection, Result};
+use std::collections::BTreeMap;
+
+/// A summary of the current process' virtual memory.
+///
+/// This `struct` is created by [`memory_stats`]. See its documentation for
+/// more.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+  /// The total size, in bytes, of all mapped regions.
+  pub mapped: usize,
+  /// The total size, in bytes, of all committed regions (see
+  /// [`Region::is_committed`](crate::Region::is_committed)).
+  pub committed: usize,
+  /// The size, in bytes, of the memory resident in RAM, if available.
+  pub resident: Option<usize>,
+  /// The number of mapped regions.
+  pub regions: usize,
+  /// The total size, in bytes, of mapped regions, per protection.
+  pub protections: BTreeMap<Protection, usize>,
+}
+
+/// Returns a summary of the current process' virtual memory.
+///
+/// Since the properties of memory pages can change at any time, the
+/// statistics only represent a snapshot. The figures are gathered separately,
+/// so they are not guaranteed to be entirely consistent with each other (e.g.
+/// if memory is allocated concurrently).
+///
+/// # Implementation
+///
+/// The regions are aggregated using [`query_process`]. The resident size is
+/// retrieved from `/proc/self/statm` on Linux, `GetProcessMemoryInfo` on
+/// Windows, and by summing the `ram_size` of each area on Haiku. It is not
+/// available on other platforms.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let stats = region::memory_stats()?;
+/// assert!(stats.committed <= stats.mapped);
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn memory_stats() -> Result<MemoryStats> {
+  #[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "haiku",
+    windows
+  ))]
+  let resident = Some(crate::os::resident_size()?);
+
+  #[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "haiku",
+    windows
+  )))]
+  let resident = None;
+
+  let mut stats = MemoryStats {
+    resident,
+    ..Default::default()
+  };
+
+  for region in query_process()? {
+    let region = region?;
+
+    stats.mapped = stats.mapped.saturating_add(region.len());
+    stats.regions += 1;
+
+    if region.is_committed() {
+      stats.committed = stats.committed.saturating_add(region.len());
+    }
+
+    let size = stats.protections.entry(region.protection()).or_insert(0);
+    *size = size.saturating_add(region.len());
+  }
+
+  Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn memory_stats_are_consistent() -> Result<()> {
+    let memory = crate::alloc(crate::page::size(), Protection::READ_WRITE)?;
+    let stats = memory_stats()?;
+
+    assert!(stats.regions > 0);
+    assert!(stats.mapped >= memory.len());
+    assert!(stats.committed <= stats.mapped);
+    if let Some(resident) = stats.resident {
+      assert!(resident <= stats.committed);
+    }
+    assert_eq!(stats.protections.values().sum::<usize>(), stats.mapped);
+    assert!(stats.protections[&Protection::READ_WRITE] >= memory.len());
+    Ok(())
+  }
+}
@@ -0,0 +1,262 @@
+use crate::{util, Result};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::sync::{Arc, RwLock};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type Handler = Arc<dyn Fn(*const ()) + Send + Sync>;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+static HANDLER: RwLock<Option<Handler>> = RwLock::new(None);
+
+/// Write-protects one or more pages, for tracking which of them are written
+/// to.
+///
+/// The first write to each page is reported to the handler set by
+/// [`install_wp_handler`], with the page's base address, after which writes to
+/// the page are re-enabled. This is the core of incremental (copy-on-write)
+/// checkpointing: the pages are write-protected once a checkpoint is taken,
+/// and each page is recorded before it's first modified.
+///
+/// The protection only applies to the pages themselves; the region's
+/// [`Protection`](crate::Protection) (as reported by [`query`](crate::query))
+/// is unchanged. A write to a tracked page does not fault; the writing thread
+/// is blocked until the handler has returned.
+///
+/// # Implementation
+///
+/// This function is only available on Linux, where it's implemented using
+/// `userfaultfd` with `UFFDIO_WRITEPROTECT`. This requires Linux 6.4 or later
+/// (i.e. `UFFD_FEATURE_WP_UNPOPULATED`).
+///
+/// # Parameters
+///
+/// - The range is `[address, address + size)`
+/// - The address is rounded down to the closest page boundary.
+/// - The size may not be zero.
+/// - The size is rounded up to the closest page boundary, relative to the
+///   address.
+/// - The range must consist of private anonymous memory (e.g. allocated by
+///   [`alloc`](crate::alloc)).
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+/// - If size is zero, or the range exceeds the address space,
+///   [`Error::InvalidParameter`](crate::Error::InvalidParameter) will be
+///   returned.
+/// - If the platform is not Linux, or `userfaultfd` is unavailable (or lacks
+///   write-protection),
+///   [`Error::UnsupportedOperation`](crate::Error::UnsupportedOperation) will
+///   be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+///
+/// let memory = region::alloc(1, Protection::READ_WRITE)?;
+/// match region::write_protect_for_tracking(memory.as_ptr::<u8>(), memory.len()) {
+///   Ok(()) | Err(region::Error::UnsupportedOperation) => (),
+///   Err(error) => return Err(error),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn write_protect_for_tracking<T>(address: *const T, size: usize) -> Result<()> {
+  let (address, size) = util::round_to_page_boundaries(address, size)?;
+
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  return crate::os::write_protect(address.cast(), size);
+
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  {
+    let _ = (address, size);
+    Err(crate::Error::UnsupportedOperation)
+  }
+}
+
+/// Sets the handler that is invoked on the first write to each page tracked
+/// by [`write_protect_for_tracking`].
+///
+/// The handler receives the base address of the written page. It's invoked
+/// on a dedicated thread, whilst the writing thread is blocked, and writes to
+/// the page are re-enabled once it returns. The handler may e.g. copy the
+/// page's contents, but it must not write to any tracked page itself, since
+/// it would deadlock. Any previously set handler is replaced, and writes are
+/// merely re-enabled whilst no handler is set. If the handler panics, the
+/// panic is caught, and the range containing the page (as passed to
+/// [`write_protect_for_tracking`]) is no longer tracked.
+///
+/// # Errors
+///
+/// - If the platform is not Linux, or `userfaultfd` is unavailable (or lacks
+///   write-protection),
+///   [`Error::UnsupportedOperation`](crate::Error::UnsupportedOperation) will
+///   be returned.
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// let result = region::install_wp_handler(|page| {
+///   eprintln!("{:p} is about to be written to", page);
+/// });
+///
+/// match result {
+///   Ok(()) | Err(region::Error::UnsupportedOperation) => (),
+///   Err(error) => return Err(error),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn install_wp_handler(handler: impl Fn(*const ()) + Send + Sync + 'static) -> Result<()> {
+  #[cfg(any(target_os = "linux", target_os = "android"))]
+  {
+    crate::os::tracker()?;
+    *HANDLER.write().unwrap_or_else(|error| error.into_inner()) = Some(Arc::new(handler));
+    Ok(())
+  }
+
+  #[cfg(not(any(target_os = "linux", target_os = "android")))]
+  {
+    let _ = handler;
+    Err(crate::Error::UnsupportedOperation)
+  }
+}
+
+/// Invokes the handler with the base address of a written page, returning an
+/// error if it panicked.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn notify(page: *const ()) -> std::thread::Result<()> {
+  // The lock is released before invoking the handler, so that it may be replaced
+  let handler = HANDLER
+    .read()
+    .unwrap_or_else(|error| error.into_inner())
+    .clone();
+
+  match handler {
+    Some(handler) => std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(page))),
+    None => Ok(()),
+  }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+  use super::*;
+  use crate::{page, Error, Protection};
+  use std::sync::Mutex;
+
+  // The handler is process-wide, hence the tests installing one are serialized
+  static SERIAL: Mutex<()> = Mutex::new(());
+
+  /// Returns whether the kernel is expected to support write-protect tracking,
+  /// i.e. Linux 6.4 or later, with `userfaultfd` permitted.
+  fn is_supported() -> bool {
+    let mut name = unsafe { std::mem::zeroed::<libc::utsname>() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+      return false;
+    }
+
+    let release = unsafe { std::ffi::CStr::from_ptr(name.release.as_ptr()) };
+    let mut version = release
+      .to_string_lossy()
+      .split(|c: char| !c.is_ascii_digit())
+      .take(2)
+      .map(|part| part.parse::<u32>().unwrap_or(0))
+      .collect::<Vec<_>>();
+    version.resize(2, 0);
+
+    // Unprivileged processes may always create user-mode only descriptors
+    const UFFD_USER_MODE_ONLY: libc::c_int = 1;
+    let fd = unsafe { libc::syscall(libc::SYS_userfaultfd, libc::O_CLOEXEC | UFFD_USER_MODE_ONLY) };
+    if fd >= 0 {
+      unsafe { libc::close(fd as libc::c_int) };
+    }
+
+    (version[0], version[1]) >= (6, 4) && fd >= 0
+  }
+
+  /// Tracks a range, returning `false` if the platform does not support it.
+  fn track(memory: &crate::Allocation) -> Result<bool> {
+    match write_protect_for_tracking(memory.as_ptr::<u8>(), memory.len()) {
+      Err(Error::UnsupportedOperation) if !is_supported() => Ok(false),
+      result => result.map(|_| true),
+    }
+  }
+
+  #[test]
+  fn write_to_tracked_page_invokes_handler_once() -> Result<()> {
+    let _serial = SERIAL.lock().unwrap_or_else(|error| error.into_inner());
+    let memory = crate::alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let range = memory.as_range();
+    let written = Arc::new(Mutex::new(Vec::new()));
+
+    let recorded = written.clone();
+    let installed = install_wp_handler(move |page| {
+      if range.contains(&(page as usize)) {
+        recorded.lock().unwrap().push(page as usize);
+      }
+    });
+
+    match installed {
+      Err(Error::UnsupportedOperation) if !is_supported() => return Ok(()),
+      result => result?,
+    }
+
+    if !track(&memory)? {
+      return Ok(());
+    }
+
+    let base = memory.as_ptr::<u8>().cast_mut();
+    unsafe {
+      base.write_volatile(1);
+      base.add(1).write_volatile(2);
+      assert_eq!(base.read_volatile(), 1);
+      assert_eq!(base.add(1).read_volatile(), 2);
+    }
+
+    assert_eq!(*written.lock().unwrap(), [base as usize]);
+    Ok(())
+  }
+
+  #[test]
+  fn panicking_handler_untracks_range() -> Result<()> {
+    let _serial = SERIAL.lock().unwrap_or_else(|error| error.into_inner());
+    let memory = crate::alloc(page::size() * 2, Protection::READ_WRITE)?;
+    let range = memory.as_range();
+    let written = Arc::new(Mutex::new(Vec::new()));
+
+    let recorded = written.clone();
+    let installed = install_wp_handler(move |page| {
+      if range.contains(&(page as usize)) {
+        recorded.lock().unwrap().push(page as usize);
+        panic!("handling {:p}", page);
+      }
+    });
+
+    match installed {
+      Err(Error::UnsupportedOperation) if !is_supported() => return Ok(()),
+      result => result?,
+    }
+
+    if !track(&memory)? {
+      return Ok(());
+    }
+
+    // Neither write blocks, and the second page is no longer tracked
+    let base = memory.as_ptr::<u8>().cast_mut();
+    unsafe {
+      base.write_volatile(1);
+      base.add(page::size()).write_volatile(2);
+    }
+
+    assert_eq!(*written.lock().unwrap(), [base as usize]);
+    Ok(())
+  }
+}
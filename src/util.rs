@@ -1,16 +1,105 @@
-use crate::{page, Error, Result};
+use crate::{page, Error, Protection, Result};
 
 /// Validates & rounds an address-size pair to their respective page boundary.
+///
+/// The rounded range must reside within the address space, otherwise an error
+/// is returned (instead of silently wrapping around).
 pub fn round_to_page_boundaries<T>(address: *const T, size: usize) -> Result<(*const T, usize)> {
   if size == 0 {
     return Err(Error::InvalidParameter("size"));
   }
 
-  let size = (address as usize % page::size()).saturating_add(size);
-  let size = page::ceil(size as *const T) as usize;
+  let mask = page::size() - 1;
+  let size = (address as usize & mask)
+    .checked_add(size)
+    .and_then(|size| size.checked_add(mask))
+    .map(|size| size & !mask)
+    .ok_or(Error::InvalidParameter("size"))?;
+
+  let address = page::floor(address);
+  (address as usize)
+    .checked_add(size)
+    .ok_or(Error::InvalidParameter("size"))?;
+
+  Ok((address, size))
+}
+
+/// Validates & rounds an address-size pair to their respective page boundary,
+/// clamping the range to the end of the address space.
+///
+/// A range starting within the first page cannot span the entire address space
+/// (its size would overflow), hence it's clamped to exclude the last page.
+pub fn round_to_page_boundaries_saturating<T>(
+  address: *const T,
+  size: usize,
+) -> Result<(*const T, usize)> {
+  if size == 0 {
+    return Err(Error::InvalidParameter("size"));
+  }
+
+  let size = size.min(usize::MAX - address as usize) + address as usize % page::size();
+  let size = size.saturating_add(page::size() - 1) & !(page::size() - 1);
   Ok((page::floor(address), size))
 }
 
+/// Returns the end of the highest region mapped in user space, or zero if it
+/// cannot be determined.
+///
+/// On 64-bit platforms, regions within the upper half of the address space
+/// (e.g. the `[vsyscall]` page on Linux) are considered to belong to the
+/// kernel, and are ignored.
+#[cfg(not(windows))]
+pub fn highest_mapped_address() -> usize {
+  let kernel_start = if cfg!(target_pointer_width = "64") {
+    !(usize::MAX >> 1)
+  } else {
+    usize::MAX
+  };
+
+  match crate::query_process() {
+    Ok(regions) => regions
+      .filter_map(Result::ok)
+      .map(|region| region.as_range())
+      .filter(|range| range.start < kernel_start)
+      .map(|range| range.end)
+      .max()
+      .unwrap_or(0),
+    Err(_) => 0,
+  }
+}
+
+/// Validates that an address range is entirely mapped & readable.
+pub fn ensure_readable<T>(address: *const T, size: usize) -> Result<()> {
+  ensure_access(address, size, Protection::READ)
+}
+
+/// Validates that an address range is entirely mapped, readable & writable.
+pub fn ensure_writable<T>(address: *const T, size: usize) -> Result<()> {
+  ensure_access(address, size, Protection::READ_WRITE)
+}
+
+fn ensure_access<T>(address: *const T, size: usize, access: Protection) -> Result<()> {
+  let (start, end) = (address as usize, (address as usize).saturating_add(size));
+  let mut cursor = start;
+
+  for region in crate::query_range(address, size)? {
+    let region = region?;
+    if region.as_range().start > cursor {
+      break;
+    }
+
+    if !region.protection().allows(access) || !region.is_committed() || region.is_guarded() {
+      return Err(Error::InvalidParameter("protection"));
+    }
+    cursor = region.as_range().end;
+  }
+
+  if cursor < end {
+    return Err(Error::UnmappedRegion);
+  }
+  Ok(())
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -33,4 +122,91 @@ mod tests {
     }
     Ok(())
   }
+
+  #[test]
+  fn round_to_page_boundaries_works_for_simulated_page_sizes() -> Result<()> {
+    for &pz in &[0x1000, 0x4000, 0x10000] {
+      page::set_size_for_testing(pz);
+      let values = &[
+        ((1, pz), (0, pz * 2)),
+        ((pz - 1, 2), (0, pz * 2)),
+        ((pz + 1, pz - 1), (pz, pz)),
+        ((pz * 3, 1), (pz * 3, pz)),
+      ];
+
+      for ((before_address, before_size), (after_address, after_size)) in values {
+        let (address, size) = round_to_page_boundaries(*before_address as *const (), *before_size)?;
+        assert_eq!((address, size), (*after_address as *const (), *after_size));
+      }
+
+      let result = round_to_page_boundaries(usize::MAX as *const (), 1);
+      assert!(matches!(result, Err(Error::InvalidParameter("size"))));
+
+      let (address, size) = round_to_page_boundaries_saturating((pz + 1) as *const (), usize::MAX)?;
+      assert_eq!(address as usize, pz);
+      assert_eq!(size % pz, 0);
+    }
+
+    page::set_size_for_testing(0);
+    Ok(())
+  }
+
+  #[test]
+  fn round_to_page_boundaries_rejects_overflow() {
+    let pz = page::size();
+    let values = &[
+      (0, usize::MAX),
+      (0, usize::MAX - pz + 2),
+      (1, usize::MAX - pz + 1),
+      (pz, usize::MAX - pz + 1),
+      (usize::MAX, 1),
+      (usize::MAX - pz, pz + 1),
+    ];
+
+    for (address, size) in values {
+      assert!(matches!(
+        round_to_page_boundaries(*address as *const (), *size),
+        Err(Error::InvalidParameter("size"))
+      ));
+    }
+  }
+
+  #[test]
+  fn round_to_page_boundaries_accepts_range_ending_at_last_page() -> Result<()> {
+    let pz = page::size();
+    let address = page::floor(usize::MAX as *const ()) as usize - pz;
+    let (_, size) = round_to_page_boundaries(address as *const (), pz)?;
+    assert_eq!(size, pz);
+    Ok(())
+  }
+
+  #[test]
+  fn round_to_page_boundaries_saturating_clamps_range() -> Result<()> {
+    let pz = page::size();
+    let (address, size) = round_to_page_boundaries_saturating(pz as *const (), usize::MAX)?;
+
+    assert_eq!(address as usize, pz);
+    assert_eq!(size, page::floor(usize::MAX as *const ()) as usize);
+    Ok(())
+  }
+
+  #[test]
+  fn round_to_page_boundaries_saturating_clamps_range_near_end() -> Result<()> {
+    let pz = page::size();
+    let last = page::floor(usize::MAX as *const ()) as usize;
+    let values = &[
+      ((usize::MAX - pz, 1), (last - pz, pz)),
+      ((usize::MAX - pz, pz), (last - pz, pz * 2)),
+      ((usize::MAX - pz, usize::MAX), (last - pz, pz * 2)),
+      ((usize::MAX, usize::MAX), (last, pz)),
+      ((0, usize::MAX), (0, last)),
+    ];
+
+    for ((before_address, before_size), (after_address, after_size)) in values {
+      let (address, size) =
+        round_to_page_boundaries_saturating(*before_address as *const (), *before_size)?;
+      assert_eq!((address, size), (*after_address as *const (), *after_size));
+    }
+    Ok(())
+  }
 }
@@ -0,0 +1,223 @@
+use crate::diff::{merge, Advance, Front};
+use crate::{query_process, Protection, Region, Result};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// The interval at which the process' regions are polled for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The number of changes that are buffered, until they're received.
+const CAPACITY: usize = 1024;
+
+/// A change of protection, observed by a [`ProtectionWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionChange {
+  /// The address range whose protection changed.
+  pub range: Range<usize>,
+  /// The protection prior to the change.
+  pub previous: Protection,
+  /// The protection after the change.
+  pub current: Protection,
+}
+
+/// A background watcher of protection changes.
+///
+/// This `struct` is created by [`watch_protection_changes`]. See its
+/// documentation for more. The watcher stops once it's dropped.
+pub struct ProtectionWatcher {
+  receiver: Receiver<RegionChange>,
+  stop: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl ProtectionWatcher {
+  /// Returns the channel on which changes are received.
+  ///
+  /// The channel is disconnected if the watcher fails to query the process'
+  /// regions.
+  #[inline(always)]
+  pub fn receiver(&self) -> &Receiver<RegionChange> {
+    &self.receiver
+  }
+}
+
+impl Drop for ProtectionWatcher {
+  #[inline]
+  fn drop(&mut self) {
+    self.stop.store(true, Ordering::Relaxed);
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+/// Watches the current process for protection changes.
+///
+/// A background thread polls the process' regions (i.e. `/proc/self/maps`),
+/// and sends a [`RegionChange`] for each address range whose protection has
+/// changed since the previous poll. The initial snapshot is taken before this
+/// function returns, so any subsequent change is observed.
+///
+/// This is inherently best-effort, and is subject to the following
+/// limitations:
+///
+/// - Changes are only observed every 10 milliseconds, therefore a change that
+///   is reverted in between two polls is missed.
+/// - Regions that are mapped or unmapped are not reported; only ranges which
+///   are mapped in consecutive polls are compared.
+/// - Changes made by the process itself (e.g. by the allocator, or the
+///   watcher's own allocations) are reported alongside any others.
+/// - At most 1024 changes are buffered. Once the buffer is full, any further
+///   changes are discarded until the receiver catches up.
+///
+/// # Errors
+///
+/// - If an interaction with the underlying operating system fails, an error
+///   will be returned.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> region::Result<()> {
+/// use region::Protection;
+/// use std::time::Duration;
+///
+/// let memory = region::alloc(1, Protection::READ)?;
+/// let watcher = region::watch_protection_changes()?;
+///
+/// unsafe { region::protect(memory.as_ptr::<u8>(), memory.len(), Protection::READ_WRITE)? };
+/// let change = watcher.receiver().recv_timeout(Duration::from_secs(5));
+/// assert!(change.is_ok());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub fn watch_protection_changes() -> Result<ProtectionWatcher> {
+  let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+  let stop = Arc::new(AtomicBool::new(false));
+  let snapshot = query_process()?.collect::<Result<Vec<_>>>()?;
+
+  let thread = {
+    let stop = stop.clone();
+    std::thread::spawn(move || poll(snapshot, &sender, &stop))
+  };
+
+  Ok(ProtectionWatcher {
+    receiver,
+    stop,
+    thread: Some(thread),
+  })
+}
+
+fn poll(mut previous: Vec<Region>, sender: &SyncSender<RegionChange>, stop: &AtomicBool) {
+  while !stop.load(Ordering::Relaxed) {
+    std::thread::sleep(POLL_INTERVAL);
+
+    let current = match query_process().and_then(Iterator::collect::<Result<Vec<_>>>) {
+      Ok(current) => current,
+      Err(_) => return,
+    };
+
+    // Sending never blocks, so that the watcher can always be stopped
+    for change in diff(&previous, &current) {
+      if let Err(TrySendError::Disconnected(_)) = sender.try_send(change) {
+        return;
+      }
+    }
+    previous = current;
+  }
+}
+
+/// Returns the ranges, mapped in both snapshots, whose protection differ.
+///
+/// Both snapshots must be sorted and non-overlapping, as yielded by
+/// [`QueryIter`](crate::QueryIter).
+fn diff(previous: &[Region], current: &[Region]) -> Vec<RegionChange> {
+  let mut changes = Vec::new();
+
+  merge(previous, current, |front| match front {
+    Front::Both(before, after) => {
+      let (before_range, after_range) = (before.as_range(), after.as_range());
+      let range = before_range.start.max(after_range.start)..before_range.end.min(after_range.end);
+
+      if !range.is_empty() && before.protection() != after.protection() {
+        changes.push(RegionChange {
+          range,
+          previous: before.protection(),
+          current: after.protection(),
+        });
+      }
+
+      if before_range.end <= after_range.end {
+        Advance::Before
+      } else {
+        Advance::After
+      }
+    }
+    // Ranges that are only mapped in one of the snapshots are not compared
+    Front::Before(_) => Advance::Before,
+    Front::After(_) => Advance::After,
+  });
+
+  changes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::page;
+  use std::time::Instant;
+
+  #[test]
+  fn watch_protection_changes_reports_protect() -> Result<()> {
+    let memory = crate::alloc(page::size(), Protection::READ)?;
+    let base = memory.as_ptr::<u8>() as usize;
+
+    let watcher = watch_protection_changes()?;
+    unsafe { crate::protect(memory.as_ptr::<u8>(), memory.len(), Protection::READ_WRITE)? };
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let change = loop {
+      let timeout = deadline.saturating_duration_since(Instant::now());
+      match watcher.receiver().recv_timeout(timeout) {
+        Ok(change) if change.range.contains(&base) => break change,
+        Ok(_) => continue,
+        Err(error) => panic!("awaiting protection change: {}", error),
+      }
+    };
+
+    assert_eq!(change.previous, Protection::READ);
+    assert_eq!(change.current, Protection::READ_WRITE);
+    Ok(())
+  }
+
+  #[test]
+  fn diff_reports_intersection_of_changed_regions() {
+    let region = |base: usize, size: usize, protection: Protection| Region {
+      base: base as *const (),
+      size,
+      protection,
+      ..Default::default()
+    };
+
+    let previous = [region(0x1000, 0x3000, Protection::READ)];
+    let current = [
+      region(0x1000, 0x1000, Protection::READ),
+      region(0x2000, 0x1000, Protection::READ_WRITE),
+      region(0x3000, 0x2000, Protection::READ),
+    ];
+
+    assert_eq!(
+      diff(&previous, &current),
+      vec![RegionChange {
+        range: 0x2000..0x3000,
+        previous: Protection::READ,
+        current: Protection::READ_WRITE,
+      }]
+    );
+  }
+}
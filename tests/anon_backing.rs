@@ -0,0 +1,25 @@
+//! The backing is a process-wide setting, hence it's tested in a dedicated
+//! binary, where no other tests allocate concurrently.
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use region::{AnonBacking, Protection, Result};
+
+#[test]
+fn set_anon_backing_applies_to_every_thread() -> Result<()> {
+  region::set_anon_backing(AnonBacking::Memfd);
+  let memory = region::alloc(1, Protection::READ_WRITE)?;
+  let other = std::thread::spawn(|| {
+    let memory = region::alloc(1, Protection::READ_WRITE)?;
+    region::query(memory.as_ptr::<u8>())
+  })
+  .join()
+  .expect("allocating on another thread")?;
+
+  assert!(region::query(memory.as_ptr::<u8>())?.is_file_backed());
+  assert!(other.is_file_backed());
+
+  region::set_anon_backing(AnonBacking::MmapAnon);
+  let memory = region::alloc(1, Protection::READ_WRITE)?;
+  assert!(!region::query(memory.as_ptr::<u8>())?.is_file_backed());
+  Ok(())
+}
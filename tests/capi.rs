@@ -0,0 +1,95 @@
+//! Compiles a C program against `include/region.h`, and compares the layout
+//! of its declarations against the Rust definitions.
+#![cfg(feature = "capi")]
+
+use region::capi::{RegionError, RegionInfo};
+use region::Protection;
+use std::mem::{offset_of, size_of};
+use std::path::Path;
+use std::process::Command;
+
+fn c_layout() -> Vec<Vec<usize>> {
+  let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+  let out_dir = std::env::temp_dir().join(format!("region-capi-{}", std::process::id()));
+  std::fs::create_dir_all(&out_dir).expect("creating output directory");
+
+  let compiler = cc::Build::new()
+    .cargo_metadata(false)
+    .opt_level(0)
+    .out_dir(&out_dir)
+    .target(env!("REGION_TARGET"))
+    .host(env!("REGION_TARGET"))
+    .include(root.join("include"))
+    .warnings_into_errors(true)
+    .get_compiler();
+
+  let binary = out_dir.join("layout");
+  let mut command = compiler.to_command();
+  command.arg(root.join("tests/capi/layout.c"));
+  if compiler.is_like_msvc() {
+    command.arg(format!("/Fe{}", binary.display()));
+  } else {
+    command.arg("-o").arg(&binary);
+  }
+
+  let status = command.status().expect("running the C compiler");
+  assert!(status.success(), "compiling against the header failed");
+
+  let output = Command::new(&binary)
+    .output()
+    .expect("running the C program");
+  std::fs::remove_dir_all(&out_dir).ok();
+  assert!(output.status.success());
+
+  String::from_utf8(output.stdout)
+    .expect("reading the C program's output")
+    .lines()
+    .map(|line| {
+      line
+        .split_whitespace()
+        .map(|value| value.parse().unwrap())
+        .collect()
+    })
+    .collect()
+}
+
+#[test]
+fn header_matches_rust_definitions() {
+  let layout = c_layout();
+
+  assert_eq!(
+    layout[0],
+    [
+      size_of::<RegionInfo>(),
+      offset_of!(RegionInfo, base),
+      offset_of!(RegionInfo, size),
+      offset_of!(RegionInfo, protection),
+      offset_of!(RegionInfo, committed),
+      offset_of!(RegionInfo, guarded),
+      offset_of!(RegionInfo, shared),
+    ]
+  );
+  assert_eq!(
+    layout[1],
+    [
+      size_of::<RegionError>(),
+      RegionError::Ok as usize,
+      RegionError::UnmappedRegion as usize,
+      RegionError::InvalidParameter as usize,
+      RegionError::AlreadyMapped as usize,
+      RegionError::LockLimitExceeded as usize,
+      RegionError::PartialFailure as usize,
+      RegionError::Os as usize,
+      RegionError::UnsupportedOperation as usize,
+    ]
+  );
+  assert_eq!(
+    layout[2],
+    [
+      Protection::NONE.bits(),
+      Protection::READ.bits(),
+      Protection::WRITE.bits(),
+      Protection::EXECUTE.bits(),
+    ]
+  );
+}
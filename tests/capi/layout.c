@@ -0,0 +1,44 @@
+/*
+ * Prints the layout of the declarations in `include/region.h`, which is
+ * compared against the Rust definitions by `tests/capi.rs`.
+ */
+#include <region.h>
+#include <stdio.h>
+
+int main(void) {
+  printf("%zu %zu %zu %zu %zu %zu %zu\n",
+         sizeof(RegionInfo),
+         offsetof(RegionInfo, base),
+         offsetof(RegionInfo, size),
+         offsetof(RegionInfo, protection),
+         offsetof(RegionInfo, committed),
+         offsetof(RegionInfo, guarded),
+         offsetof(RegionInfo, shared));
+  printf("%zu %d %d %d %d %d %d %d %d\n",
+         sizeof(RegionError),
+         REGION_ERROR_OK,
+         REGION_ERROR_UNMAPPED_REGION,
+         REGION_ERROR_INVALID_PARAMETER,
+         REGION_ERROR_ALREADY_MAPPED,
+         REGION_ERROR_LOCK_LIMIT_EXCEEDED,
+         REGION_ERROR_PARTIAL_FAILURE,
+         REGION_ERROR_OS,
+         REGION_ERROR_UNSUPPORTED_OPERATION);
+  printf("%u %u %u %u\n",
+         REGION_PROTECTION_NONE,
+         REGION_PROTECTION_READ,
+         REGION_PROTECTION_WRITE,
+         REGION_PROTECTION_EXECUTE);
+
+  /* The prototypes are checked, without referencing the functions */
+  RegionAllocation *allocation = NULL;
+  RegionInfo info;
+  RegionError error;
+  (void)sizeof(region_alloc((size_t)1, REGION_PROTECTION_READ, &error) == allocation);
+  (void)sizeof(region_allocation_base(allocation));
+  (void)sizeof(region_allocation_size(allocation));
+  (void)sizeof((region_free(allocation), 0));
+  (void)sizeof(region_protect(NULL, (size_t)1, REGION_PROTECTION_READ) == error);
+  (void)sizeof(region_query(NULL, &info) == error);
+  return 0;
+}